@@ -0,0 +1,324 @@
+//! Computer opponents.
+
+use serde::{Deserialize, Serialize};
+
+use crate::rng::SimRng;
+use crate::sim::{Paddle, PaddleInput, PongSim, WallLocation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Impossible,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Impossible,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Impossible => "Impossible",
+        }
+    }
+
+    /// The next preset up, wrapping back round to `Easy`.
+    pub fn next(&self) -> Difficulty {
+        self.step(1)
+    }
+
+    /// The preset `steps` away, wrapping round at either end.
+    pub fn step(&self, steps: i32) -> Difficulty {
+        let index = Difficulty::ALL.iter().position(|d| d == self).unwrap_or(0);
+        let count = Difficulty::ALL.len() as i32;
+        Difficulty::ALL[(index as i32 + steps).rem_euclid(count) as usize]
+    }
+
+    pub fn params(&self) -> AiParams {
+        match self {
+            Difficulty::Easy => AiParams {
+                reaction_delay: 0.35,
+                aim_error: 60.0,
+                max_speed: 0.6,
+                predict: false,
+                return_to_centre: false,
+            },
+            Difficulty::Normal => AiParams {
+                reaction_delay: 0.2,
+                aim_error: 35.0,
+                max_speed: 0.8,
+                predict: true,
+                return_to_centre: true,
+            },
+            Difficulty::Hard => AiParams {
+                reaction_delay: 0.1,
+                aim_error: 15.0,
+                max_speed: 1.0,
+                predict: true,
+                return_to_centre: true,
+            },
+            Difficulty::Impossible => AiParams {
+                reaction_delay: 0.0,
+                aim_error: 0.0,
+                max_speed: 1.0,
+                predict: true,
+                return_to_centre: true,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AiParams {
+    /// Seconds between the ball changing direction and the AI reacting.
+    pub reaction_delay: f32,
+    /// Largest miss, in pixels, in where the AI aims its paddle.
+    pub aim_error: f32,
+    /// Share of the paddle's top speed the AI will use.
+    pub max_speed: f32,
+    /// Work out where the ball will arrive, bounces and all, instead of
+    /// following its current height.
+    pub predict: bool,
+    /// Drift back to the middle while the ball is heading away.
+    pub return_to_centre: bool,
+}
+
+/// A computer player for one paddle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpponentAi {
+    name: String,
+    params: AiParams,
+    rng: SimRng,
+    /// Height the paddle is heading for, once decided.
+    target: Option<f32>,
+    /// Random miss added to `target`, picked once per approach.
+    error: f32,
+    /// Whether the ball was last seen coming towards this paddle.
+    incoming: bool,
+    /// Ticks left before reacting to the ball's last change of direction.
+    reaction_ticks: u32,
+}
+
+impl OpponentAi {
+    pub fn new(params: AiParams, seed: u64) -> OpponentAi {
+        OpponentAi {
+            name: "ai".to_string(),
+            params,
+            rng: SimRng::new(seed),
+            target: None,
+            error: 0.0,
+            incoming: false,
+            reaction_ticks: 0,
+        }
+    }
+
+    /// An AI playing one of the presets, named after it.
+    pub fn with_difficulty(difficulty: Difficulty, seed: u64) -> OpponentAi {
+        OpponentAi {
+            name: difficulty.name().to_lowercase(),
+            ..OpponentAi::new(difficulty.params(), seed)
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn params(&self) -> &AiParams {
+        &self.params
+    }
+
+    pub fn input(&mut self, sim: &PongSim, paddle: Paddle) -> PaddleInput {
+        let state = sim.paddle(paddle);
+        let incoming = sim.ball.velocity.x * paddle.facing() < 0.0;
+
+        if incoming != self.incoming {
+            self.incoming = incoming;
+            self.reaction_ticks = (self.params.reaction_delay * sim.physics.tick_rate) as u32;
+            self.error = self.rng.range(-self.params.aim_error, self.params.aim_error);
+        }
+
+        if self.reaction_ticks > 0 {
+            self.reaction_ticks -= 1;
+        } else if incoming {
+            let aim = if self.params.predict {
+                predict_crossing(sim, paddle)
+            } else {
+                sim.ball.position.y
+            };
+            self.target = Some(aim + self.error);
+        } else if self.params.return_to_centre {
+            self.target = Some(0.0);
+        }
+
+        let target = match self.target {
+            Some(target) => target,
+            None => return PaddleInput::default(),
+        };
+
+        // Ease in to the target so the paddle stops there instead of
+        // overshooting and jittering around it
+        let step = state.speed * sim.physics.time_step();
+        let direction = ((target - state.position.y) / step)
+            .clamp(-self.params.max_speed, self.params.max_speed);
+
+        PaddleInput { direction, ..Default::default() }
+    }
+}
+
+/// Height at which the ball will reach the face of the given paddle,
+/// following its bounces off the top and bottom walls.
+pub fn predict_crossing(sim: &PongSim, paddle: Paddle) -> f32 {
+    let ball = &sim.ball;
+    let state = sim.paddle(paddle);
+    let face = state.position.x + paddle.facing() * (state.size.x + ball.size.x) / 2.;
+
+    if ball.velocity.x == 0.0 {
+        return ball.position.y;
+    }
+    let time = (face - ball.position.x) / ball.velocity.x;
+    if time < 0.0 {
+        return ball.position.y;
+    }
+
+    // Unfold the straight-line path back into the space between the walls
+    let arena = &sim.physics.arena;
+    let low = WallLocation::Bottom.inner(arena).y + ball.size.y / 2.;
+    let high = WallLocation::Top.inner(arena).y - ball.size.y / 2.;
+    let span = high - low;
+    let travelled = (ball.position.y + ball.velocity.y * time - low).rem_euclid(2. * span);
+
+    if travelled > span {
+        low + 2. * span - travelled
+    } else {
+        low + travelled
+    }
+}
+
+/// The original computer opponent: head straight for the ball's height.
+pub fn chase_ball(sim: &PongSim, paddle: Paddle) -> PaddleInput {
+    let ypos = sim.paddle(paddle).position.y;
+    let direction = if sim.ball.position.y > ypos {
+        1.0
+    } else if sim.ball.position.y < ypos {
+        -1.0
+    } else {
+        0.0
+    };
+
+    PaddleInput { direction, ..Default::default() }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+    use crate::sim::{Phase, SimEvent, SimInputs};
+
+    // The ball already in play, heading for the opponent
+    fn incoming(position: Vec2, velocity: Vec2) -> PongSim {
+        let mut sim = PongSim::default();
+        // Past the serve, so the ball moves
+        while let Phase::Serving { .. } = sim.phase() {
+            sim.step(SimInputs::default());
+        }
+        sim.ball.position = position;
+        sim.ball.velocity = velocity;
+        sim
+    }
+
+    #[test]
+    fn predicts_a_straight_ball() {
+        let sim = incoming(Vec2::new(0.0, 120.0), Vec2::new(450.0, 0.0));
+        assert!((predict_crossing(&sim, Paddle::Opponent) - 120.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn predicts_a_bounce_off_the_top_wall() {
+        let sim = incoming(Vec2::new(0.0, 100.0), Vec2::new(400.0, 400.0));
+        let arena = &sim.physics.arena;
+        let high = WallLocation::Top.inner(arena).y - sim.ball.size.y / 2.;
+        let low = WallLocation::Bottom.inner(arena).y + sim.ball.size.y / 2.;
+        let predicted = predict_crossing(&sim, Paddle::Opponent);
+        assert!(predicted <= high && predicted >= low);
+
+        // Straight on it would end up above the wall, so it came back down
+        let state = sim.paddle(Paddle::Opponent);
+        let face = state.position.x - (state.size.x + sim.ball.size.x) / 2.;
+        let unfolded = 100.0 + face;
+        assert!(unfolded > high);
+        assert!((predicted - (2. * high - unfolded)).abs() < 0.01);
+    }
+
+    #[test]
+    fn heads_for_the_ball_without_delay() {
+        let sim = incoming(Vec2::new(0.0, 150.0), Vec2::new(450.0, 0.0));
+        let mut ai = OpponentAi::with_difficulty(Difficulty::Impossible, 1);
+        assert!(ai.input(&sim, Paddle::Opponent).direction > 0.0);
+
+        let sim = incoming(Vec2::new(0.0, -150.0), Vec2::new(450.0, 0.0));
+        let mut ai = OpponentAi::with_difficulty(Difficulty::Impossible, 1);
+        assert!(ai.input(&sim, Paddle::Opponent).direction < 0.0);
+    }
+
+    #[test]
+    fn waits_out_its_reaction_delay() {
+        let sim = incoming(Vec2::new(0.0, 150.0), Vec2::new(450.0, 0.0));
+        let mut ai = OpponentAi::with_difficulty(Difficulty::Easy, 1);
+        let delay = (ai.params().reaction_delay * sim.physics.tick_rate) as u32;
+        for _ in 0..delay {
+            assert_eq!(ai.input(&sim, Paddle::Opponent).direction, 0.0);
+        }
+        assert!(ai.input(&sim, Paddle::Opponent).direction > 0.0);
+    }
+
+    #[test]
+    fn keeps_to_its_top_speed() {
+        let sim = incoming(Vec2::new(0.0, 200.0), Vec2::new(450.0, 0.0));
+        let mut ai = OpponentAi::with_difficulty(Difficulty::Easy, 1);
+        let max_speed = ai.params().max_speed;
+        for _ in 0..100 {
+            assert!(ai.input(&sim, Paddle::Opponent).direction.abs() <= max_speed);
+        }
+    }
+
+    #[test]
+    fn impossible_beats_easy() {
+        let mut sim = PongSim::default();
+        let mut easy = OpponentAi::with_difficulty(Difficulty::Easy, 5);
+        let mut impossible = OpponentAi::with_difficulty(Difficulty::Impossible, 6);
+        let mut winner = None;
+        for _ in 0..200_000 {
+            let inputs = SimInputs {
+                player: easy.input(&sim, Paddle::Player),
+                opponent: impossible.input(&sim, Paddle::Opponent),
+            };
+            for event in sim.step(inputs) {
+                if let SimEvent::MatchWon(paddle) = event {
+                    winner = Some(paddle);
+                }
+            }
+            if winner.is_some() {
+                break;
+            }
+        }
+        assert_eq!(winner, Some(Paddle::Opponent));
+    }
+
+    #[test]
+    fn difficulty_wraps_round() {
+        assert_eq!(Difficulty::Impossible.next(), Difficulty::Easy);
+        assert_eq!(Difficulty::Easy.step(-1), Difficulty::Impossible);
+        assert_eq!(Difficulty::Normal.step(7), Difficulty::Easy);
+    }
+}
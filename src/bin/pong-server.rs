@@ -0,0 +1,238 @@
+//! Dedicated match server. Runs matches headless between two clients at a
+//! time, the first two to join, and logs how each one went. The game joins
+//! with `--server`, and so does `pong-sim` for trying it out without a
+//! window:
+//!
+//! ```text
+//! pong-server --port 7778 --results results.csv
+//! pong-sim --server 127.0.0.1:7778 --player hard
+//! pong-sim --server 127.0.0.1:7778 --player normal
+//! ```
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use pong::server::{MatchRecord, MatchServer, ServerConfig, ServerEvent, ServerStatus, DEFAULT_SERVER_PORT};
+use pong::settings::GameSettings;
+use pong::sim::Paddle;
+
+const USAGE: &str = "\
+Usage: pong-server [OPTIONS]
+
+  --config <PATH>          physics and rules from a settings file
+  --port <PORT>            UDP port to listen on (default 7778)
+  --points <N>             points needed to win a game
+  --win-by <N>             lead needed to win a game
+  --best-of <N>            games in a match
+  --grace <SECONDS>        how long a player who drops out has to come
+                           back before forfeiting (default 30)
+  --snapshot-interval <N>  ticks between snapshots sent out (default 2)
+  --matches <N>            stop after this many matches (default: never)
+  --results <PATH>         add a CSV line to PATH for each match
+  --seed <N>               seed for the match seeds (default: the time)
+  --net-latency <MS>       delay every packet sent by this much
+  --net-jitter <MS>        and by up to this much more at random
+  --net-loss <F>           drop this share of packets sent, from 0 to 1
+  -h, --help               print this message";
+
+const RESULTS_HEADER: &str = "\
+match,seed,winner,forfeit,player_score,opponent_score,player_games,opponent_games,ticks,\
+player_address,opponent_address,player_rejected,opponent_rejected";
+
+struct Options {
+    port: u16,
+    config: ServerConfig,
+    matches: Option<u32>,
+    results: Option<PathBuf>,
+    seed: u64,
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+    value.parse().map_err(|_| format!("invalid value `{}` for {}", value, flag))
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let args: Vec<String> = args.collect();
+    let mut options = Options {
+        port: DEFAULT_SERVER_PORT,
+        config: ServerConfig::default(),
+        matches: None,
+        results: None,
+        seed: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or_default(),
+    };
+
+    // The settings file goes first wherever it appears, so that the other
+    // flags override it
+    if let Some(index) = args.iter().position(|arg| arg == "--config") {
+        let path = args.get(index + 1).ok_or("--config needs a value")?;
+        let settings = GameSettings::load(Path::new(path))?;
+        options.config.physics = settings.physics;
+        options.config.rules = settings.rules;
+    }
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let config = &mut options.config;
+        match arg.as_str() {
+            "--config" => {
+                args.next();
+            }
+            "--port" => options.port = parse_value(&arg, args.next())?,
+            "--points" => config.rules.points_to_win = parse_value(&arg, args.next())?,
+            "--win-by" => config.rules.win_by = parse_value(&arg, args.next())?,
+            "--best-of" => config.rules.best_of = parse_value(&arg, args.next())?,
+            "--grace" => config.grace = Duration::from_secs(parse_value(&arg, args.next())?),
+            "--snapshot-interval" => config.snapshot_interval = parse_value(&arg, args.next())?,
+            "--matches" => options.matches = Some(parse_value(&arg, args.next())?),
+            "--results" => options.results = Some(parse_value(&arg, args.next())?),
+            "--seed" => options.seed = parse_value(&arg, args.next())?,
+            "--net-latency" => {
+                config.shim.latency = Duration::from_millis(parse_value(&arg, args.next())?)
+            }
+            "--net-jitter" => {
+                config.shim.jitter = Duration::from_millis(parse_value(&arg, args.next())?)
+            }
+            "--net-loss" => config.shim.loss = parse_value(&arg, args.next())?,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    options.config.validate()?;
+    Ok(options)
+}
+
+fn side_name(side: Paddle) -> &'static str {
+    match side {
+        Paddle::Player => "left",
+        Paddle::Opponent => "right",
+    }
+}
+
+fn winner_name(winner: Option<Paddle>) -> &'static str {
+    match winner {
+        Some(Paddle::Player) => "player",
+        Some(Paddle::Opponent) => "opponent",
+        None => "none",
+    }
+}
+
+fn describe_result(record: &MatchRecord, tick_rate: f32) -> String {
+    let seconds = (record.ticks as f32 / tick_rate) as u32;
+    let scoreboard = &record.scoreboard;
+    let score = if scoreboard.player_games + scoreboard.opponent_games > 0 {
+        format!("{}-{} in games", scoreboard.player_games, scoreboard.opponent_games)
+    } else {
+        format!("{}-{}", scoreboard.player_score, scoreboard.opponent_score)
+    };
+    let outcome = match (record.winner, record.forfeit) {
+        (Some(winner), false) => format!("{} won {}", side_name(winner), score),
+        (Some(winner), true) => format!("{} won by forfeit at {}", side_name(winner), score),
+        (None, _) => format!("abandoned at {}", score),
+    };
+    let mut line = format!("Match {} over: {} after {}:{:02}", record.number, outcome,
+        seconds / 60, seconds % 60);
+    let [player_rejected, opponent_rejected] = record.rejected_inputs;
+    if player_rejected + opponent_rejected > 0 {
+        line.push_str(&format!(" ({} left and {} right inputs rejected)",
+            player_rejected, opponent_rejected));
+    }
+    line
+}
+
+fn append_result(path: &Path, record: &MatchRecord) -> Result<(), String> {
+    let new = fs::metadata(path).map_or(true, |metadata| metadata.len() == 0);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|error| format!("can't open {}: {}", path.display(), error))?;
+    let scoreboard = &record.scoreboard;
+    let mut lines = String::new();
+    if new {
+        lines.push_str(RESULTS_HEADER);
+        lines.push('\n');
+    }
+    lines.push_str(&format!("{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+        record.number, record.seed, winner_name(record.winner), record.forfeit,
+        scoreboard.player_score, scoreboard.opponent_score,
+        scoreboard.player_games, scoreboard.opponent_games, record.ticks,
+        record.addresses[0], record.addresses[1],
+        record.rejected_inputs[0], record.rejected_inputs[1]));
+    file.write_all(lines.as_bytes())
+        .map_err(|error| format!("can't write to {}: {}", path.display(), error))
+}
+
+fn log(event: &ServerEvent, options: &Options) {
+    match event {
+        ServerEvent::Joined { side, address } => {
+            println!("{} joined on the {}", address, side_name(*side));
+        }
+        ServerEvent::Started { number, seed } => println!("Match {} started (seed {})", number, seed),
+        ServerEvent::Dropped { side } => {
+            println!("The {} side dropped out; it has {} s to come back", side_name(*side),
+                options.config.grace.as_secs());
+        }
+        ServerEvent::Rejoined { side, address } => {
+            println!("The {} side is back, from {}", side_name(*side), address);
+        }
+        ServerEvent::Left { side } => println!("The {} side left", side_name(*side)),
+        ServerEvent::Finished(record) => {
+            println!("{}", describe_result(record, options.config.physics.tick_rate));
+            if let Some(path) = &options.results {
+                if let Err(message) = append_result(path, record) {
+                    eprintln!("{}", message);
+                }
+            }
+        }
+    }
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    let mut server = match MatchServer::bind(options.port, options.config, options.seed) {
+        Ok(server) => server,
+        Err(error) => {
+            eprintln!("Can't listen on port {}: {}", options.port, error);
+            process::exit(1);
+        }
+    };
+    println!("Listening on port {}", server.port().unwrap_or(options.port));
+
+    let mut finished = 0;
+    let started = Instant::now();
+    loop {
+        for event in server.update() {
+            if let ServerEvent::Finished(_) = event {
+                finished += 1;
+            }
+            log(&event, &options);
+        }
+        // The last match is shown to its players before stopping
+        let done = options.matches.is_some_and(|matches| finished >= matches);
+        if done && !matches!(server.status(), ServerStatus::Over { .. }) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    println!("Served {} matches in {} s", finished, started.elapsed().as_secs());
+}
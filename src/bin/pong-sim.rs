@@ -0,0 +1,815 @@
+//! Headless match runner. Plays batches of matches between two computer
+//! controllers with no window and prints the results as CSV or JSON.
+//!
+//! ```text
+//! pong-sim --matches 100 --seed 7 --player chase --opponent random --format json
+//! ```
+//!
+//! It doesn't need Bevy, so on a build box with no display or audio
+//! libraries leave the game out:
+//!
+//! ```text
+//! cargo build --no-default-features --bin pong-sim
+//! ```
+//!
+//! With `--record` each match is saved as a replay, and `--verify` plays
+//! replays back to check the simulation and controllers still behave
+//! exactly as they did when the replays were recorded. The replays in
+//! `replays/` are kept for that, and `cargo test` checks them too:
+//!
+//! ```text
+//! pong-sim --verify replays
+//! ```
+//!
+//! `--host` and `--join` play one match against another `pong-sim` over
+//! the network instead, in real time, to try the online code out without
+//! a window. Run both on one machine with some artificial lag:
+//!
+//! ```text
+//! pong-sim --host 7777 --player hard --net-latency 60 --net-loss 0.05
+//! pong-sim --join 127.0.0.1:7777 --opponent normal --net-latency 60 --net-loss 0.05
+//! ```
+//!
+//! `--server` plays one match on a `pong-server` the same way, with
+//! `--player` on whichever side the server gives it. `--net-outage` goes
+//! quiet partway through to try the server's reconnection grace out.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use pong::collision::Surface;
+use pong::physics::{PaddleMovement, PhysicsConfig};
+use pong::replay::{state_hash, Replay, REPLAY_EXTENSION};
+use pong::rng::SimRng;
+use pong::rules::MatchRules;
+use pong::settings::{GameSettings, NetworkSettings};
+use pong::ai::{Difficulty, OpponentAi};
+use pong::controller::{Chase, Idle, Observation, PaddleController, RandomWalk};
+use pong::net::{Connecting, LinkShim, NetConfig, NetSession, Progress};
+use pong::server::{ServerClient, ServerStatus};
+use pong::sim::{Paddle, PongSim, SimEvent, SimInputs};
+
+const USAGE: &str = "\
+Usage: pong-sim [OPTIONS]
+
+Options:
+  --config <PATH>       read physics and rules from a settings file; the
+                        flags below override it
+  --matches <N>         number of matches to play (default 10)
+  --seed <N>            base seed, match i uses seed + i (default 0); seeds
+                        serve angles and the random controller
+  --points <N>          points needed to win a game (default 11)
+  --win-by <N>          margin a game must be won by (default 2)
+  --best-of <N>         games in a match (default 1)
+  --time-limit <SECS>   match length in simulated seconds (default none)
+  --ball-speed <F>      serve speed in pixels per second (default 450)
+  --ball-speedup <F>    ball speed multiplier per paddle hit (default 1.05)
+  --max-ball-speed <F>  ball speed cap (default 900)
+  --max-angle <DEG>     steepest bounce off a paddle end (default 60)
+  --tick-rate <HZ>      simulation ticks per second (default 60)
+  --player-speed <F>    left paddle speed (default 200)
+  --opponent-speed <F>  right paddle speed (default 250)
+  --paddle-accel <F>    give paddles this acceleration instead of instant speed
+  --paddle-friction <F> slow-down of an accelerating paddle (default: its acceleration)
+  --paddle-spin <F>     share of paddle speed passed to the ball (default 0.3)
+  --max-ticks <N>       tick budget per match before it is abandoned (default 36000)
+  --player <KIND>       controller for the left paddle (default chase)
+  --opponent <KIND>     controller for the right paddle (default chase)
+  --format <csv|json>   output format (default csv)
+  --record <DIR>        save a replay of each match in DIR
+  --verify <PATH>       play back a replay, or every replay in a directory,
+                        and report any tick where it plays out differently;
+                        the other options are ignored
+  --host <PORT>         play one match online, waiting on PORT for
+                        another pong-sim to --join; plays --player
+  --join <ADDRESS>      play one match online against a pong-sim at
+                        ADDRESS; plays --opponent, with the host's physics
+                        and rules
+  --input-delay <N>     ticks to hold online inputs back (default 2)
+  --max-prediction <N>  ticks to play ahead of the other side (default 8)
+  --net-latency <MS>    delay every packet sent by this much
+  --net-jitter <MS>     and by up to this much more at random
+  --net-loss <F>        drop this share of packets sent, from 0 to 1
+  --server <ADDRESS>    play one match on a pong-server at ADDRESS, with
+                        --player on the side the server picks
+  --net-outage <SECS>   with --server, send and take in nothing for this
+                        long five seconds into the match
+  -h, --help            print this message
+
+Controllers: chase, idle, random, easy, normal, hard, impossible";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControllerKind {
+    Chase,
+    Idle,
+    Random,
+    Ai(Difficulty),
+}
+
+impl FromStr for ControllerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chase" => Ok(ControllerKind::Chase),
+            "idle" => Ok(ControllerKind::Idle),
+            "random" => Ok(ControllerKind::Random),
+            _ => Difficulty::ALL
+                .into_iter()
+                .find(|difficulty| difficulty.name().eq_ignore_ascii_case(s))
+                .map(ControllerKind::Ai)
+                .ok_or_else(|| format!("unknown controller `{}`", s)),
+        }
+    }
+}
+
+impl ControllerKind {
+    fn build(&self, seed: u64) -> Box<dyn PaddleController> {
+        match self {
+            ControllerKind::Chase => Box::new(Chase),
+            ControllerKind::Idle => Box::new(Idle),
+            ControllerKind::Random => Box::new(RandomWalk::new(seed)),
+            ControllerKind::Ai(difficulty) => {
+                Box::new(OpponentAi::with_difficulty(*difficulty, seed))
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            ControllerKind::Chase => "chase".to_string(),
+            ControllerKind::Idle => "idle".to_string(),
+            ControllerKind::Random => "random".to_string(),
+            ControllerKind::Ai(difficulty) => difficulty.name().to_lowercase(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+struct Options {
+    matches: u64,
+    seed: u64,
+    physics: PhysicsConfig,
+    rules: MatchRules,
+    max_ticks: u64,
+    player: ControllerKind,
+    opponent: ControllerKind,
+    format: Format,
+    record: Option<PathBuf>,
+    verify: Option<PathBuf>,
+    host: Option<u16>,
+    join: Option<String>,
+    network: NetworkSettings,
+    shim: LinkShim,
+    server: Option<String>,
+    outage: Option<Duration>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            matches: 10,
+            seed: 0,
+            physics: PhysicsConfig::default(),
+            rules: MatchRules::default(),
+            max_ticks: 36_000,
+            player: ControllerKind::Chase,
+            opponent: ControllerKind::Chase,
+            format: Format::Csv,
+            record: None,
+            verify: None,
+            host: None,
+            join: None,
+            network: NetworkSettings::default(),
+            shim: LinkShim::default(),
+            server: None,
+            outage: None,
+        }
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+    value.parse().map_err(|_| format!("invalid value `{}` for {}", value, flag))
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let args: Vec<String> = args.collect();
+    let mut options = Options::default();
+
+    // The settings file goes first wherever it appears, so that the other
+    // flags override it
+    if let Some(index) = args.iter().position(|arg| arg == "--config") {
+        let path = args.get(index + 1).ok_or("--config needs a value")?;
+        let settings = GameSettings::load(Path::new(path))?;
+        options.physics = settings.physics;
+        options.rules = settings.rules;
+        options.network = settings.network;
+    }
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                args.next();
+            }
+            "--matches" => options.matches = parse_value(&arg, args.next())?,
+            "--seed" => options.seed = parse_value(&arg, args.next())?,
+            "--points" => options.rules.points_to_win = parse_value(&arg, args.next())?,
+            "--win-by" => options.rules.win_by = parse_value(&arg, args.next())?,
+            "--best-of" => options.rules.best_of = parse_value(&arg, args.next())?,
+            "--time-limit" => options.rules.time_limit = Some(parse_value(&arg, args.next())?),
+            "--ball-speed" => options.physics.ball_speed = parse_value(&arg, args.next())?,
+            "--ball-speedup" => options.physics.ball_speedup = parse_value(&arg, args.next())?,
+            "--max-ball-speed" => options.physics.max_ball_speed = parse_value(&arg, args.next())?,
+            "--max-angle" => options.physics.max_bounce_angle = parse_value(&arg, args.next())?,
+            "--tick-rate" => options.physics.tick_rate = parse_value(&arg, args.next())?,
+            "--player-speed" => options.physics.player_speed = parse_value(&arg, args.next())?,
+            "--opponent-speed" => options.physics.opponent_speed = parse_value(&arg, args.next())?,
+            "--paddle-accel" => {
+                let acceleration = parse_value(&arg, args.next())?;
+                let friction = match options.physics.paddle_movement {
+                    PaddleMovement::Accelerated { friction, .. } => friction,
+                    PaddleMovement::Direct => acceleration,
+                };
+                options.physics.paddle_movement = PaddleMovement::Accelerated { acceleration, friction };
+            }
+            "--paddle-friction" => {
+                let friction = parse_value(&arg, args.next())?;
+                let acceleration = match options.physics.paddle_movement {
+                    PaddleMovement::Accelerated { acceleration, .. } => acceleration,
+                    PaddleMovement::Direct => return Err("--paddle-friction needs --paddle-accel first".to_string()),
+                };
+                options.physics.paddle_movement = PaddleMovement::Accelerated { acceleration, friction };
+            }
+            "--paddle-spin" => options.physics.paddle_spin = parse_value(&arg, args.next())?,
+            "--max-ticks" => options.max_ticks = parse_value(&arg, args.next())?,
+            "--player" => {
+                options.player = args.next().ok_or("--player needs a value")?.parse()?
+            }
+            "--opponent" => {
+                options.opponent = args.next().ok_or("--opponent needs a value")?.parse()?
+            }
+            "--format" => {
+                options.format = match args.next().as_deref() {
+                    Some("csv") => Format::Csv,
+                    Some("json") => Format::Json,
+                    _ => return Err("--format must be csv or json".to_string()),
+                }
+            }
+            "--record" => options.record = Some(parse_value(&arg, args.next())?),
+            "--verify" => options.verify = Some(parse_value(&arg, args.next())?),
+            "--host" => options.host = Some(parse_value(&arg, args.next())?),
+            "--join" => options.join = Some(args.next().ok_or("--join needs a value")?),
+            "--input-delay" => options.network.input_delay = parse_value(&arg, args.next())?,
+            "--max-prediction" => options.network.max_prediction = parse_value(&arg, args.next())?,
+            "--net-latency" => {
+                options.shim.latency = Duration::from_millis(parse_value(&arg, args.next())?)
+            }
+            "--net-jitter" => {
+                options.shim.jitter = Duration::from_millis(parse_value(&arg, args.next())?)
+            }
+            "--net-loss" => options.shim.loss = parse_value(&arg, args.next())?,
+            "--server" => options.server = Some(args.next().ok_or("--server needs a value")?),
+            "--net-outage" => {
+                options.outage = Some(Duration::from_secs_f32(parse_value(&arg, args.next())?))
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    options.physics.validate()?;
+    options.rules.validate()?;
+    options.network.validate()?;
+    options.shim.validate()?;
+    let online = [options.host.is_some(), options.join.is_some(), options.server.is_some()];
+    if online.into_iter().filter(|set| *set).count() > 1 {
+        return Err("only one of --host, --join and --server can be used".to_string());
+    }
+
+    Ok(options)
+}
+
+struct MatchResult {
+    seed: u64,
+    winner: Option<Paddle>,
+    player_score: usize,
+    opponent_score: usize,
+    player_games: usize,
+    opponent_games: usize,
+    points: usize,
+    ticks: u64,
+    seconds: f64,
+    paddle_hits: u64,
+    // Only with --record
+    replay: Option<Replay>,
+}
+
+impl MatchResult {
+    fn winner_name(&self) -> &'static str {
+        match self.winner {
+            Some(Paddle::Player) => "player",
+            Some(Paddle::Opponent) => "opponent",
+            None => "none",
+        }
+    }
+
+    fn mean_rally(&self) -> f64 {
+        if self.points == 0 {
+            0.0
+        } else {
+            self.paddle_hits as f64 / self.points as f64
+        }
+    }
+}
+
+fn play_match(options: &Options, seed: u64) -> MatchResult {
+    let mut seeds = SimRng::new(seed);
+    let sim_seed = seeds.next_u64();
+    let controller_seeds = [seeds.next_u64(), seeds.next_u64()];
+    let mut sim = PongSim::new(options.physics, options.rules, sim_seed);
+    let mut player = options.player.build(controller_seeds[0]);
+    let mut opponent = options.opponent.build(controller_seeds[1]);
+    let mut replay = options.record.as_ref().map(|_| {
+        let mut replay = Replay::new(sim_seed, options.physics, options.rules,
+            &options.player.name(), &options.opponent.name());
+        replay.header.controller_seeds = Some(controller_seeds);
+        replay
+    });
+    let mut paddle_hits = 0;
+    let mut points = 0;
+
+    while sim.tick() < options.max_ticks && !sim.is_finished() {
+        let inputs = SimInputs {
+            player: player.intent(&Observation::new(&sim, Paddle::Player)),
+            opponent: opponent.intent(&Observation::new(&sim, Paddle::Opponent)),
+        };
+
+        let events = sim.step(inputs);
+        if let Some(replay) = &mut replay {
+            replay.record(inputs, &sim, &events);
+        }
+        for event in events {
+            match event {
+                SimEvent::Collision(collision) => {
+                    if let Surface::Paddle(_) = collision.surface {
+                        paddle_hits += 1;
+                    }
+                }
+                SimEvent::PointScored(_) => points += 1,
+                SimEvent::ServeStarted(_) | SimEvent::Served(_)
+                | SimEvent::GameWon(_) | SimEvent::MatchWon(_) => { }
+            }
+        }
+    }
+
+    MatchResult {
+        seed,
+        winner: sim.winner(),
+        player_score: sim.scoreboard.player_score,
+        opponent_score: sim.scoreboard.opponent_score,
+        player_games: sim.scoreboard.player_games,
+        opponent_games: sim.scoreboard.opponent_games,
+        points,
+        ticks: sim.tick(),
+        seconds: f64::from(sim.elapsed()),
+        paddle_hits,
+        replay,
+    }
+}
+
+// Replay files at `path`, which is either one or a directory of them
+fn replay_paths(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let entries = fs::read_dir(path)
+        .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == REPLAY_EXTENSION))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+// Build the recorded controllers again and check they still make the
+// recorded moves. None if the replay doesn't say how to build them.
+fn check_controllers(replay: &Replay) -> Option<Result<(), String>> {
+    let [player_seed, opponent_seed] = replay.header.controller_seeds?;
+    let player: ControllerKind = replay.header.player.parse().ok()?;
+    let opponent: ControllerKind = replay.header.opponent.parse().ok()?;
+    let mut player = player.build(player_seed);
+    let mut opponent = opponent.build(opponent_seed);
+
+    let mut sim = replay.start();
+    for (index, recorded) in replay.inputs.iter().enumerate() {
+        let inputs = SimInputs {
+            player: player.intent(&Observation::new(&sim, Paddle::Player)),
+            opponent: opponent.intent(&Observation::new(&sim, Paddle::Opponent)),
+        };
+        if inputs != *recorded {
+            return Some(Err(format!("controllers moved differently at tick {}: recorded {:?}, \
+                now {:?}", index + 1, recorded, inputs)));
+        }
+        sim.step(*recorded);
+    }
+    Some(Ok(()))
+}
+
+// Check one replay, printing what was found. False if it failed.
+fn verify_replay(path: &Path) -> bool {
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(message) => {
+            println!("FAIL {}", message);
+            return false;
+        }
+    };
+    if replay.hashes.is_empty() {
+        println!("SKIP {}: no tick hashes to check against", path.display());
+        return true;
+    }
+    if let Err(desync) = replay.verify() {
+        println!("FAIL {}: desync at tick {} of {} (hash {:08x}, recorded {:08x})",
+            path.display(), desync.tick, replay.len(), desync.replayed_hash, desync.recorded_hash);
+        if desync.recorded.is_none() {
+            println!("  no snapshot at tick {}, so compared with tick {}, the last that matched:",
+                desync.tick, desync.tick - 1);
+        }
+        for line in desync.diff() {
+            println!("  {}", line);
+        }
+        return false;
+    }
+    match check_controllers(&replay) {
+        Some(Err(message)) => {
+            println!("FAIL {}: {}", path.display(), message);
+            false
+        }
+        Some(Ok(())) => {
+            println!("ok   {}: {} ticks, controllers checked", path.display(), replay.len());
+            true
+        }
+        None => {
+            println!("ok   {}: {} ticks", path.display(), replay.len());
+            true
+        }
+    }
+}
+
+// Waiting on the other side, and then playing against it at the tick rate
+fn play_online(options: &Options) -> Result<(), String> {
+    let config = NetConfig {
+        input_delay: options.network.input_delay,
+        max_prediction: options.network.max_prediction,
+        shim: options.shim,
+    };
+    let connecting = match (options.host, &options.join) {
+        (Some(port), _) => {
+            eprintln!("Waiting for another player on port {}", port);
+            Connecting::host(port, options.seed, options.physics, options.rules, config)
+        }
+        (None, Some(address)) => {
+            eprintln!("Joining {}", address);
+            Connecting::join(address, options.physics, config)
+        }
+        (None, None) => unreachable!("only called to play online"),
+    };
+    let mut connecting = connecting.map_err(|error| format!("could not open socket: {}", error))?;
+    let mut session = loop {
+        match connecting.poll()? {
+            Progress::Waiting(waiting) => connecting = *waiting,
+            Progress::Connected(session) => break session,
+        }
+        thread::sleep(Duration::from_millis(5));
+    };
+    eprintln!("Connected to {}", session.peer());
+
+    let side = session.local_side();
+    let kind = match side {
+        Paddle::Player => options.player,
+        Paddle::Opponent => options.opponent,
+    };
+    let mut controller = kind.build(session.seed.wrapping_add(side as u64));
+    let mut replay = options.record.as_ref().map(|_| {
+        let sim = session.confirmed();
+        let (player, opponent) = match side {
+            Paddle::Player => (kind.name(), "online".to_string()),
+            Paddle::Opponent => ("online".to_string(), kind.name()),
+        };
+        Replay::new(session.seed, sim.physics, sim.rules, &player, &opponent)
+    });
+
+    let tick_time = Duration::from_secs_f32(session.sim().physics.time_step());
+    let mut next_tick = Instant::now();
+    let mut finished: Option<Instant> = None;
+    loop {
+        session.receive();
+        for tick in session.take_confirmed() {
+            if let Some(replay) = &mut replay {
+                replay.record(tick.inputs, &tick.sim, &tick.events);
+            }
+        }
+        let confirmed = session.confirmed();
+        if finished.is_none() && (confirmed.is_finished() || confirmed.tick() >= options.max_ticks) {
+            finished = Some(Instant::now());
+        }
+        // Keep sending a while after the end, so the other side gets the
+        // inputs it needs to see the end too
+        if finished.is_some_and(|finished| finished.elapsed() >= Duration::from_secs(1)) {
+            session.close();
+            break;
+        }
+        if let Some(reason) = session.closed() {
+            if finished.is_some() {
+                break;
+            }
+            return Err(reason.to_string());
+        }
+
+        while Instant::now() >= next_tick {
+            let stepped = session.step(|sim| controller.intent(&Observation::new(sim, side)));
+            if stepped.is_none() {
+                // Waiting doesn't bank time to catch up with later
+                next_tick = Instant::now() + tick_time;
+                break;
+            }
+            next_tick += tick_time;
+        }
+        session.send();
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    print_online(&session);
+    if let (Some(dir), Some(replay)) = (&options.record, &replay) {
+        let name = format!("{}-online-{}.{}", session.seed,
+            if side == Paddle::Player { "host" } else { "join" }, REPLAY_EXTENSION);
+        let path = dir.join(name);
+        replay.save(&path).map_err(|error| format!("could not save {}: {}", path.display(), error))?;
+    }
+    match session.stats().rollback.desync {
+        Some(tick) => Err(format!("desynced from the other side at tick {}", tick)),
+        None => Ok(()),
+    }
+}
+
+// The end of an online match. Both sides should print the same hash.
+fn print_online(session: &NetSession) {
+    let sim = session.confirmed();
+    let stats = session.stats();
+    let winner = match sim.winner() {
+        Some(Paddle::Player) => "player",
+        Some(Paddle::Opponent) => "opponent",
+        None => "none",
+    };
+    println!("side,winner,player_score,opponent_score,player_games,opponent_games,ticks,hash,\
+        rollbacks,longest_rollback,stalls,ping_ms");
+    println!("{},{},{},{},{},{},{},{:08x},{},{},{},{}",
+        if session.local_side() == Paddle::Player { "host" } else { "join" },
+        winner, sim.scoreboard.player_score, sim.scoreboard.opponent_score,
+        sim.scoreboard.player_games, sim.scoreboard.opponent_games, sim.tick(),
+        state_hash(&sim.state()), stats.rollback.rollbacks, stats.rollback.longest_rollback,
+        stats.rollback.stalls, stats.ping.map_or(0, |ping| ping.as_millis()));
+}
+
+// Follow a match on a dedicated server, sending inputs from what it shows
+fn play_server(options: &Options, address: &str) -> Result<(), String> {
+    eprintln!("Joining the server at {}", address);
+    // Tells this client apart from any other on the machine
+    let token = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or_default()
+        ^ process::id() as u64;
+    let mut client = ServerClient::connect(address, options.physics, token, options.shim)
+        .map_err(|error| format!("could not open socket: {}", error))?;
+    let mut controller: Option<Box<dyn PaddleController>> = None;
+    let mut outage = options.outage;
+    let mut last_frame = Instant::now();
+    let mut next_tick = Instant::now();
+    let view = loop {
+        client.receive();
+        if let Some(reason) = client.closed() {
+            return Err(reason.to_string());
+        }
+        let now = Instant::now();
+        let view = client.view(now.saturating_duration_since(last_frame).as_secs_f32());
+        last_frame = now;
+        client.take_events();
+
+        let (side, view) = match (client.side(), view) {
+            (Some(side), Some(view)) => (side, view),
+            _ => {
+                thread::sleep(Duration::from_millis(1));
+                continue;
+            }
+        };
+        match client.status() {
+            ServerStatus::Over { .. } => break view,
+            ServerStatus::Playing => {
+                let controller = controller.get_or_insert_with(|| {
+                    eprintln!("Playing the {} side", if side == Paddle::Player { "left" } else { "right" });
+                    options.player.build(token)
+                });
+                let tick_time = Duration::from_secs_f32(view.physics.time_step());
+                while Instant::now() >= next_tick {
+                    client.send_input(controller.intent(&Observation::new(&view, side)));
+                    next_tick += tick_time;
+                }
+            }
+            // Time spent waiting isn't banked
+            ServerStatus::Waiting | ServerStatus::Reconnecting { .. } => next_tick = Instant::now(),
+        }
+
+        if let Some(length) = outage {
+            if view.elapsed() >= 5.0 {
+                eprintln!("Going quiet for {:.1} s", length.as_secs_f32());
+                thread::sleep(length);
+                outage = None;
+                next_tick = Instant::now();
+            }
+        }
+        thread::sleep(Duration::from_millis(1));
+    };
+    client.leave();
+
+    let (winner, forfeit) = match client.status() {
+        ServerStatus::Over { winner, forfeit } => (winner, forfeit),
+        _ => (view.winner(), false),
+    };
+    let winner = match winner {
+        Some(Paddle::Player) => "player",
+        Some(Paddle::Opponent) => "opponent",
+        None => "none",
+    };
+    println!("side,winner,forfeit,player_score,opponent_score,player_games,opponent_games,ticks,ping_ms");
+    println!("{},{},{},{},{},{},{},{},{}",
+        if client.side() == Some(Paddle::Player) { "player" } else { "opponent" },
+        winner, forfeit, view.scoreboard.player_score, view.scoreboard.opponent_score,
+        view.scoreboard.player_games, view.scoreboard.opponent_games, view.tick(),
+        client.ping().map_or(0, |ping| ping.as_millis()));
+    Ok(())
+}
+
+fn save_replays(dir: &Path, options: &Options, results: &[MatchResult]) -> Result<(), String> {
+    for result in results {
+        if let Some(replay) = &result.replay {
+            let name = format!("{}-{}-vs-{}.{}", result.seed, options.player.name(),
+                options.opponent.name(), REPLAY_EXTENSION);
+            let path = dir.join(name);
+            replay.save(&path)
+                .map_err(|error| format!("could not save {}: {}", path.display(), error))?;
+        }
+    }
+    Ok(())
+}
+
+struct Summary {
+    matches: usize,
+    player_wins: usize,
+    opponent_wins: usize,
+    unfinished: usize,
+    mean_rally: f64,
+    points_per_minute: f64,
+}
+
+fn summarize(results: &[MatchResult]) -> Summary {
+    let count = |paddle| results.iter().filter(|r| r.winner == paddle).count();
+    let points: usize = results.iter().map(|r| r.points).sum();
+    let hits: u64 = results.iter().map(|r| r.paddle_hits).sum();
+    let minutes = results.iter().map(|r| r.seconds).sum::<f64>() / 60.0;
+
+    Summary {
+        matches: results.len(),
+        player_wins: count(Some(Paddle::Player)),
+        opponent_wins: count(Some(Paddle::Opponent)),
+        unfinished: count(None),
+        mean_rally: if points == 0 { 0.0 } else { hits as f64 / points as f64 },
+        points_per_minute: if minutes == 0.0 { 0.0 } else { points as f64 / minutes },
+    }
+}
+
+fn win_rate(wins: usize, matches: usize) -> f64 {
+    if matches == 0 {
+        0.0
+    } else {
+        wins as f64 / matches as f64
+    }
+}
+
+fn print_csv(options: &Options, results: &[MatchResult], summary: &Summary) {
+    println!("match,seed,player,opponent,winner,player_games,opponent_games,player_score,opponent_score,points,ticks,paddle_hits,mean_rally");
+    for (i, r) in results.iter().enumerate() {
+        println!("{},{},{},{},{},{},{},{},{},{},{},{},{:.3}",
+            i, r.seed, options.player.name(), options.opponent.name(), r.winner_name(),
+            r.player_games, r.opponent_games, r.player_score, r.opponent_score,
+            r.points, r.ticks, r.paddle_hits, r.mean_rally());
+    }
+
+    println!();
+    println!("matches,player_wins,opponent_wins,unfinished,player_win_rate,opponent_win_rate,mean_rally,points_per_minute");
+    println!("{},{},{},{},{:.4},{:.4},{:.3},{:.3}",
+        summary.matches, summary.player_wins, summary.opponent_wins, summary.unfinished,
+        win_rate(summary.player_wins, summary.matches),
+        win_rate(summary.opponent_wins, summary.matches),
+        summary.mean_rally, summary.points_per_minute);
+}
+
+fn print_json(options: &Options, results: &[MatchResult], summary: &Summary) {
+    println!("{{");
+    println!("  \"player\": \"{}\",", options.player.name());
+    println!("  \"opponent\": \"{}\",", options.opponent.name());
+    println!("  \"matches\": [");
+    for (i, r) in results.iter().enumerate() {
+        let comma = if i + 1 < results.len() { "," } else { "" };
+        println!("    {{\"match\": {}, \"seed\": {}, \"winner\": \"{}\", \"player_games\": {}, \
+            \"opponent_games\": {}, \"player_score\": {}, \"opponent_score\": {}, \"points\": {}, \
+            \"ticks\": {}, \"paddle_hits\": {}, \"mean_rally\": {:.3}}}{}",
+            i, r.seed, r.winner_name(), r.player_games, r.opponent_games,
+            r.player_score, r.opponent_score, r.points,
+            r.ticks, r.paddle_hits, r.mean_rally(), comma);
+    }
+    println!("  ],");
+    println!("  \"summary\": {{");
+    println!("    \"matches\": {},", summary.matches);
+    println!("    \"player_wins\": {},", summary.player_wins);
+    println!("    \"opponent_wins\": {},", summary.opponent_wins);
+    println!("    \"unfinished\": {},", summary.unfinished);
+    println!("    \"player_win_rate\": {:.4},", win_rate(summary.player_wins, summary.matches));
+    println!("    \"opponent_win_rate\": {:.4},", win_rate(summary.opponent_wins, summary.matches));
+    println!("    \"mean_rally\": {:.3},", summary.mean_rally);
+    println!("    \"points_per_minute\": {:.3}", summary.points_per_minute);
+    println!("  }}");
+    println!("}}");
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("pong-sim: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Some(path) = &options.verify {
+        let paths = match replay_paths(path) {
+            Ok(paths) => paths,
+            Err(message) => {
+                eprintln!("pong-sim: {}", message);
+                process::exit(2);
+            }
+        };
+        // Check them all rather than stopping at the first failure
+        let failed = paths.iter().filter(|path| !verify_replay(path)).count();
+        println!("{} replays, {} failed", paths.len(), failed);
+        process::exit(if failed == 0 { 0 } else { 1 });
+    }
+
+    if let Some(address) = &options.server {
+        if let Err(message) = play_server(&options, address) {
+            eprintln!("pong-sim: {}", message);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if options.host.is_some() || options.join.is_some() {
+        if let Err(message) = play_online(&options) {
+            eprintln!("pong-sim: {}", message);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let results: Vec<MatchResult> = (0..options.matches)
+        .map(|i| play_match(&options, options.seed.wrapping_add(i)))
+        .collect();
+    let summary = summarize(&results);
+    if let Some(dir) = &options.record {
+        if let Err(message) = save_replays(dir, &options, &results) {
+            eprintln!("pong-sim: {}", message);
+            process::exit(1);
+        }
+    }
+
+    match options.format {
+        Format::Csv => print_csv(&options, &results, &summary),
+        Format::Json => print_json(&options, &results, &summary),
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// Things a key can be bound to. Solo play moves with MoveUp/MoveDown, and
+// versus gives each side its own pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    LeftUp,
+    LeftDown,
+    RightUp,
+    RightDown,
+    Serve,
+    Pause,
+    Back,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move Up",
+            Action::MoveDown => "Move Down",
+            Action::LeftUp => "P1 Up",
+            Action::LeftDown => "P1 Down",
+            Action::RightUp => "P2 Up",
+            Action::RightDown => "P2 Down",
+            Action::Serve => "Serve",
+            Action::Pause => "Pause",
+            Action::Back => "Back",
+        }
+    }
+}
+
+// The key for every action, saved as `bindings.toml` in the user's config
+// directory. Missing entries keep their defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bindings {
+    pub move_up: KeyCode,
+    pub move_down: KeyCode,
+    pub left_up: KeyCode,
+    pub left_down: KeyCode,
+    pub right_up: KeyCode,
+    pub right_down: KeyCode,
+    pub serve: KeyCode,
+    pub pause: KeyCode,
+    pub back: KeyCode,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            move_up: KeyCode::Up,
+            move_down: KeyCode::Down,
+            left_up: KeyCode::W,
+            left_down: KeyCode::S,
+            right_up: KeyCode::Up,
+            right_down: KeyCode::Down,
+            serve: KeyCode::Return,
+            pause: KeyCode::Space,
+            back: KeyCode::Escape,
+        }
+    }
+}
+
+impl Bindings {
+    pub fn key(&self, action: Action) -> KeyCode {
+        match action {
+            Action::MoveUp => self.move_up,
+            Action::MoveDown => self.move_down,
+            Action::LeftUp => self.left_up,
+            Action::LeftDown => self.left_down,
+            Action::RightUp => self.right_up,
+            Action::RightDown => self.right_down,
+            Action::Serve => self.serve,
+            Action::Pause => self.pause,
+            Action::Back => self.back,
+        }
+    }
+
+    pub fn set(&mut self, action: Action, key: KeyCode) {
+        let slot = match action {
+            Action::MoveUp => &mut self.move_up,
+            Action::MoveDown => &mut self.move_down,
+            Action::LeftUp => &mut self.left_up,
+            Action::LeftDown => &mut self.left_down,
+            Action::RightUp => &mut self.right_up,
+            Action::RightDown => &mut self.right_down,
+            Action::Serve => &mut self.serve,
+            Action::Pause => &mut self.pause,
+            Action::Back => &mut self.back,
+        };
+        *slot = key;
+    }
+
+    pub fn pressed(&self, keys: &Input<KeyCode>, action: Action) -> bool {
+        keys.pressed(self.key(action))
+    }
+
+    pub fn just_pressed(&self, keys: &Input<KeyCode>, action: Action) -> bool {
+        keys.just_pressed(self.key(action))
+    }
+
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("pong").join("bindings.toml"))
+    }
+
+    // Saved bindings, or the defaults if there are none or they can't be read
+    pub fn load() -> Bindings {
+        let path = match Bindings::path() {
+            Some(path) => path,
+            None => return Bindings::default(),
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Bindings::default(),
+            Err(error) => {
+                println!("Could not read {}: {}", path.display(), error);
+                return Bindings::default();
+            }
+        };
+        match toml::from_str(&text) {
+            Ok(bindings) => bindings,
+            Err(error) => {
+                println!("Ignoring bad key bindings in {}: {}", path.display(), error);
+                Bindings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Bindings::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string(self)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        fs::write(path, text)
+    }
+}
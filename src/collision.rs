@@ -0,0 +1,92 @@
+//! Swept box collision. The ball is moved along its path each tick and
+//! stopped at the first surface it would touch, so a fast ball cannot pass
+//! through a paddle between two ticks.
+
+use glam::Vec2;
+
+use crate::sim::{Paddle, WallLocation};
+
+/// Something the ball can run into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Surface {
+    Paddle(Paddle),
+    Wall(WallLocation),
+}
+
+/// The ball touched a surface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionEvent {
+    pub surface: Surface,
+    /// Where the ball met the surface.
+    pub point: Vec2,
+    /// Surface normal at the contact, pointing back towards the ball.
+    pub normal: Vec2,
+}
+
+/// Axis-aligned box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub center: Vec2,
+    pub half_size: Vec2,
+}
+
+impl Aabb {
+    pub fn new(center: Vec2, size: Vec2) -> Aabb {
+        Aabb {
+            center,
+            half_size: size / 2.,
+        }
+    }
+
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        let gap = (self.center - other.center).abs() - (self.half_size + other.half_size);
+        gap.x < 0.0 && gap.y < 0.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    /// Fraction of `motion` covered before contact, from 0 to 1.
+    pub time: f32,
+    pub normal: Vec2,
+}
+
+/// Sweep `moving` along `motion` against the still box `target` and find
+/// the first contact. Boxes that already overlap, or that are touching and
+/// moving apart, do not hit.
+pub fn sweep(moving: &Aabb, motion: Vec2, target: &Aabb) -> Option<Hit> {
+    // Shrink the ball to a point and grow the target by its size instead
+    let reach = moving.half_size + target.half_size;
+    let offset = moving.center - target.center;
+
+    let mut entry = f32::NEG_INFINITY;
+    let mut exit = f32::INFINITY;
+    let mut normal = Vec2::ZERO;
+
+    for axis in 0..2 {
+        let (d, m, r) = (offset[axis], motion[axis], reach[axis]);
+        if m == 0.0 {
+            if d.abs() >= r {
+                return None;
+            }
+            continue;
+        }
+
+        let t1 = (-r - d) / m;
+        let t2 = (r - d) / m;
+        let (near, far) = if t1 < t2 { (t1, t2) } else { (t2, t1) };
+
+        if near > entry {
+            entry = near;
+            normal = Vec2::ZERO;
+            normal[axis] = -m.signum();
+        }
+        exit = exit.min(far);
+    }
+
+    if entry > exit || !(0.0..=1.0).contains(&entry) {
+        return None;
+    }
+
+    Some(Hit { time: entry, normal })
+}
@@ -0,0 +1,136 @@
+//! What drives a paddle. Anything that can turn a view of the game into a
+//! paddle input can play either side: people, the AI presets or scripts.
+
+use crate::ai::{self, OpponentAi};
+use crate::rng::SimRng;
+use crate::sim::{Paddle, PaddleInput, PaddleState, PongSim};
+
+/// The game as seen from one side.
+#[derive(Clone, Copy)]
+pub struct Observation<'a> {
+    pub side: Paddle,
+    pub sim: &'a PongSim,
+}
+
+impl<'a> Observation<'a> {
+    pub fn new(sim: &'a PongSim, side: Paddle) -> Observation<'a> {
+        Observation { side, sim }
+    }
+
+    pub fn own_paddle(&self) -> &PaddleState {
+        self.sim.paddle(self.side)
+    }
+
+    pub fn other_paddle(&self) -> &PaddleState {
+        self.sim.paddle(self.side.other())
+    }
+}
+
+pub trait PaddleController: Send + Sync {
+    /// Short name for results, logs and replay headers.
+    fn name(&self) -> String;
+
+    /// What the paddle should do this tick.
+    fn intent(&mut self, observation: &Observation) -> PaddleInput;
+}
+
+impl PaddleController for OpponentAi {
+    fn name(&self) -> String {
+        OpponentAi::name(self).to_string()
+    }
+
+    fn intent(&mut self, observation: &Observation) -> PaddleInput {
+        self.input(observation.sim, observation.side)
+    }
+}
+
+/// The original computer opponent, see [`ai::chase_ball`].
+pub struct Chase;
+
+impl PaddleController for Chase {
+    fn name(&self) -> String {
+        "chase".to_string()
+    }
+
+    fn intent(&mut self, observation: &Observation) -> PaddleInput {
+        ai::chase_ball(observation.sim, observation.side)
+    }
+}
+
+/// Never moves.
+pub struct Idle;
+
+impl PaddleController for Idle {
+    fn name(&self) -> String {
+        "idle".to_string()
+    }
+
+    fn intent(&mut self, _observation: &Observation) -> PaddleInput {
+        PaddleInput::default()
+    }
+}
+
+/// Wanders up and down at random, changing its mind every quarter to
+/// three quarters of a second.
+pub struct RandomWalk {
+    rng: SimRng,
+    direction: f32,
+    hold: u32,
+}
+
+impl RandomWalk {
+    pub fn new(seed: u64) -> RandomWalk {
+        RandomWalk {
+            rng: SimRng::new(seed),
+            direction: 0.0,
+            hold: 0,
+        }
+    }
+}
+
+impl PaddleController for RandomWalk {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+
+    fn intent(&mut self, observation: &Observation) -> PaddleInput {
+        if self.hold == 0 {
+            let tick_rate = observation.sim.physics.tick_rate;
+            self.direction = self.rng.range_u32(0, 3) as f32 - 1.0;
+            self.hold = self.rng.range(0.25 * tick_rate, 0.75 * tick_rate).max(1.0) as u32;
+        }
+        self.hold -= 1;
+        PaddleInput { direction: self.direction, ..Default::default() }
+    }
+}
+
+/// A bot written as a closure.
+pub struct Scripted<F> {
+    name: String,
+    script: F,
+}
+
+impl<F> Scripted<F>
+where
+    F: FnMut(&Observation) -> PaddleInput + Send + Sync,
+{
+    pub fn new(name: &str, script: F) -> Scripted<F> {
+        Scripted {
+            name: name.to_string(),
+            script,
+        }
+    }
+}
+
+impl<F> PaddleController for Scripted<F>
+where
+    F: FnMut(&Observation) -> PaddleInput + Send + Sync,
+{
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn intent(&mut self, observation: &Observation) -> PaddleInput {
+        (self.script)(observation)
+    }
+}
@@ -0,0 +1,484 @@
+use std::str::FromStr;
+
+use bevy::prelude::*;
+
+use pong::ai::{Difficulty, OpponentAi};
+use pong::controller::{Idle, Observation, PaddleController};
+use pong::sim::{Paddle, PaddleInput};
+
+use crate::GameState;
+use crate::bindings::{Action, Bindings};
+
+// What sits in each seat when a match starts. Either side can be anything.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeatKind {
+    Keyboard { up: Action, down: Action },
+    Gamepad,
+    Mouse,
+    Computer,
+    // Someone playing online. Their inputs come over the network.
+    Remote,
+}
+
+impl FromStr for SeatKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keyboard" => Ok(SeatKind::Keyboard { up: Action::MoveUp, down: Action::MoveDown }),
+            "gamepad" => Ok(SeatKind::Gamepad),
+            "mouse" | "touch" => Ok(SeatKind::Mouse),
+            "computer" => Ok(SeatKind::Computer),
+            _ => Err(format!("unknown controller `{}`", s)),
+        }
+    }
+}
+
+impl SeatKind {
+    pub fn is_human(&self) -> bool {
+        !matches!(self, SeatKind::Computer)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SeatKind::Keyboard { .. } => "Keyboard",
+            SeatKind::Gamepad => "Gamepad",
+            SeatKind::Mouse => "Mouse",
+            SeatKind::Computer => "Computer",
+            SeatKind::Remote => "Online",
+        }
+    }
+
+    // The next way for a person to play, for the menu
+    pub fn next_human(&self) -> SeatKind {
+        match self {
+            SeatKind::Keyboard { .. } => SeatKind::Mouse,
+            SeatKind::Mouse => SeatKind::Gamepad,
+            SeatKind::Gamepad | SeatKind::Computer | SeatKind::Remote => {
+                SeatKind::Keyboard { up: Action::MoveUp, down: Action::MoveDown }
+            }
+        }
+    }
+}
+
+// Single player against whatever the command line set up, two people
+// sharing the keyboard, or two people on different machines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameMode {
+    #[default]
+    Solo,
+    Versus,
+    Online,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeatSetup {
+    pub player: SeatKind,
+    pub opponent: SeatKind,
+}
+
+impl SeatSetup {
+    // Each side on its own pair of keys, W/S and the arrows unless rebound
+    pub fn versus() -> SeatSetup {
+        SeatSetup {
+            player: SeatKind::Keyboard { up: Action::LeftUp, down: Action::LeftDown },
+            opponent: SeatKind::Keyboard { up: Action::RightUp, down: Action::RightDown },
+        }
+    }
+
+    // The single player input on this machine's side, and whoever is on
+    // the other end of the connection on the other
+    pub fn online(local: SeatKind, side: Paddle) -> SeatSetup {
+        match side {
+            Paddle::Player => SeatSetup { player: local, opponent: SeatKind::Remote },
+            Paddle::Opponent => SeatSetup { player: SeatKind::Remote, opponent: local },
+        }
+    }
+
+    pub fn for_mode(&self, mode: GameMode) -> SeatSetup {
+        match mode {
+            // The side played depends on the connection; see `online`
+            GameMode::Solo | GameMode::Online => *self,
+            GameMode::Versus => SeatSetup::versus(),
+        }
+    }
+
+    pub fn get(&self, paddle: Paddle) -> SeatKind {
+        match paddle {
+            Paddle::Player => self.player,
+            Paddle::Opponent => self.opponent,
+        }
+    }
+
+    // What to call each side on the HUD and results screen
+    pub fn side_name(&self, paddle: Paddle) -> &'static str {
+        let humans = (self.player.is_human(), self.opponent.is_human());
+        let online = self.player == SeatKind::Remote || self.opponent == SeatKind::Remote;
+        match (humans, paddle) {
+            _ if self.get(paddle) == SeatKind::Remote => "Online",
+            _ if online => "You",
+            ((true, true), Paddle::Player) => "Player 1",
+            ((true, true), Paddle::Opponent) => "Player 2",
+            ((false, false), Paddle::Player) => "Computer 1",
+            ((false, false), Paddle::Opponent) => "Computer 2",
+            _ if self.get(paddle).is_human() => "Player",
+            _ => "Computer",
+        }
+    }
+}
+
+impl Default for SeatSetup {
+    fn default() -> Self {
+        SeatSetup {
+            player: SeatKind::Keyboard { up: Action::MoveUp, down: Action::MoveDown },
+            opponent: SeatKind::Computer,
+        }
+    }
+}
+
+// A controller built for one match. Device-driven controllers can't look
+// at Bevy resources from inside the simulation, so `sample_devices` copies
+// what they need into them each frame first.
+pub enum Seat {
+    Keyboard(KeyboardController),
+    Gamepad(GamepadController),
+    Mouse(MouseController),
+    Computer(Box<dyn PaddleController>),
+    // Never asked; the match gets its inputs from the connection
+    Remote(Idle),
+}
+
+impl Seat {
+    pub fn new(kind: SeatKind, difficulty: Difficulty, seed: u64) -> Seat {
+        match kind {
+            SeatKind::Keyboard { up, down } => Seat::Keyboard(KeyboardController::new(up, down)),
+            SeatKind::Gamepad => Seat::Gamepad(GamepadController::default()),
+            SeatKind::Mouse => Seat::Mouse(MouseController::default()),
+            SeatKind::Computer => {
+                Seat::Computer(Box::new(OpponentAi::with_difficulty(difficulty, seed)))
+            }
+            SeatKind::Remote => Seat::Remote(Idle),
+        }
+    }
+
+    pub fn controller(&mut self) -> &mut dyn PaddleController {
+        match self {
+            Seat::Keyboard(keyboard) => keyboard,
+            Seat::Gamepad(gamepad) => gamepad,
+            Seat::Mouse(mouse) => mouse,
+            Seat::Computer(computer) => computer.as_mut(),
+            Seat::Remote(idle) => idle,
+        }
+    }
+}
+
+pub struct Seats {
+    pub setup: SeatSetup,
+    pub player: Seat,
+    pub opponent: Seat,
+}
+
+impl Seats {
+    pub fn new(setup: &SeatSetup, difficulty: Difficulty, seed: u64) -> Seats {
+        Seats {
+            setup: *setup,
+            player: Seat::new(setup.player, difficulty, seed),
+            opponent: Seat::new(setup.opponent, difficulty, seed.wrapping_add(1)),
+        }
+    }
+
+    // Hand a paddle over to its gamepad partway through a match. The other
+    // side of an online match isn't ours to hand over.
+    fn switch_to_gamepad(&mut self, paddle: Paddle) {
+        if let Seat::Gamepad(_) | Seat::Remote(_) = self.get_mut(paddle) {
+            return;
+        }
+        *self.get_mut(paddle) = Seat::Gamepad(GamepadController::default());
+        match paddle {
+            Paddle::Player => self.setup.player = SeatKind::Gamepad,
+            Paddle::Opponent => self.setup.opponent = SeatKind::Gamepad,
+        }
+    }
+
+    pub fn get_mut(&mut self, paddle: Paddle) -> &mut Seat {
+        match paddle {
+            Paddle::Player => &mut self.player,
+            Paddle::Opponent => &mut self.opponent,
+        }
+    }
+
+    pub fn intent(&mut self, observation: &Observation) -> PaddleInput {
+        self.get_mut(observation.side).controller().intent(observation)
+    }
+}
+
+// Moves with whichever keys are bound to its pair of actions
+pub struct KeyboardController {
+    up: Action,
+    down: Action,
+    direction: f32,
+    serve: bool,
+}
+
+impl KeyboardController {
+    pub fn new(up: Action, down: Action) -> KeyboardController {
+        KeyboardController { up, down, direction: 0.0, serve: false }
+    }
+
+    fn sample(&mut self, keys: &Input<KeyCode>, bindings: &Bindings) {
+        self.direction = 0.0;
+        if bindings.pressed(keys, self.up) {
+            self.direction = 1.0;
+        }
+        if bindings.pressed(keys, self.down) {
+            self.direction = -1.0;
+        }
+        // Held on to until the next tick, which may not run this frame
+        self.serve |= bindings.just_pressed(keys, Action::Serve);
+    }
+}
+
+impl PaddleController for KeyboardController {
+    fn name(&self) -> String {
+        "keyboard".to_string()
+    }
+
+    fn intent(&mut self, _observation: &Observation) -> PaddleInput {
+        PaddleInput { direction: self.direction, serve: std::mem::take(&mut self.serve) }
+    }
+}
+
+// Left stick or d-pad of whichever gamepad is assigned to the seat. The
+// stick is analog, so a half push moves the paddle at half speed.
+#[derive(Default)]
+pub struct GamepadController {
+    direction: f32,
+    serve: bool,
+}
+
+impl GamepadController {
+    fn sample(
+        &mut self,
+        gamepad: Option<Gamepad>,
+        axes: &Axis<GamepadAxis>,
+        buttons: &Input<GamepadButton>,
+    ) {
+        self.direction = 0.0;
+        let gamepad = match gamepad {
+            Some(gamepad) => gamepad,
+            None => return,
+        };
+
+        if buttons.pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadUp)) {
+            self.direction = 1.0;
+        } else if buttons.pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadDown)) {
+            self.direction = -1.0;
+        } else {
+            self.direction = axes
+                .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY))
+                .unwrap_or(0.0)
+                .clamp(-1.0, 1.0);
+        }
+        // A fresh press, like the keyboard, so holding A doesn't serve again
+        // straight after every point. Held on to until the next tick.
+        self.serve |= buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South));
+    }
+}
+
+impl PaddleController for GamepadController {
+    fn name(&self) -> String {
+        "gamepad".to_string()
+    }
+
+    fn intent(&mut self, _observation: &Observation) -> PaddleInput {
+        PaddleInput { direction: self.direction, serve: std::mem::take(&mut self.serve) }
+    }
+}
+
+// Which gamepad drives which paddle. Kept between matches so a pad only
+// has to be claimed once.
+#[derive(Default)]
+pub struct GamepadAssignment {
+    pub player: Option<Gamepad>,
+    pub opponent: Option<Gamepad>,
+}
+
+impl GamepadAssignment {
+    pub fn get(&self, paddle: Paddle) -> Option<Gamepad> {
+        match paddle {
+            Paddle::Player => self.player,
+            Paddle::Opponent => self.opponent,
+        }
+    }
+
+    fn set(&mut self, paddle: Paddle, gamepad: Option<Gamepad>) {
+        match paddle {
+            Paddle::Player => self.player = gamepad,
+            Paddle::Opponent => self.opponent = gamepad,
+        }
+    }
+
+    fn side_of(&self, gamepad: Gamepad) -> Option<Paddle> {
+        [Paddle::Player, Paddle::Opponent]
+            .into_iter()
+            .find(|paddle| self.get(*paddle) == Some(gamepad))
+    }
+
+    // Sides with a gamepad of their own play with it, whatever the setup
+    // had there
+    pub fn apply(&self, mut setup: SeatSetup) -> SeatSetup {
+        if self.player.is_some() {
+            setup.player = SeatKind::Gamepad;
+        }
+        if self.opponent.is_some() {
+            setup.opponent = SeatKind::Gamepad;
+        }
+        setup
+    }
+
+    // Hand spare connected pads to gamepad seats that are still waiting
+    pub fn fill(&mut self, setup: &SeatSetup, gamepads: &Gamepads) {
+        for paddle in [Paddle::Player, Paddle::Opponent] {
+            if setup.get(paddle) != SeatKind::Gamepad || self.get(paddle).is_some() {
+                continue;
+            }
+            let spare = gamepads
+                .iter()
+                .find(|gamepad| self.side_of(**gamepad).is_none())
+                .copied();
+            self.set(paddle, spare);
+        }
+    }
+}
+
+// Paddle follows the height of the mouse cursor or a finger, at no more
+// than its usual top speed. Clicking serves.
+#[derive(Default)]
+pub struct MouseController {
+    target: Option<f32>,
+    serve: bool,
+}
+
+impl MouseController {
+    fn sample(&mut self, pointer: Option<Vec2>, clicked: bool) {
+        // Keep heading for the last place seen if the pointer leaves the window
+        if let Some(pointer) = pointer {
+            self.target = Some(pointer.y);
+        }
+        // Held on to until the next tick, which may not run this frame
+        self.serve |= clicked;
+    }
+}
+
+impl PaddleController for MouseController {
+    fn name(&self) -> String {
+        "mouse".to_string()
+    }
+
+    fn intent(&mut self, observation: &Observation) -> PaddleInput {
+        let target = match self.target {
+            Some(target) => target,
+            None => return PaddleInput::default(),
+        };
+
+        // Full speed when far off, easing in over the last tick's worth
+        let paddle = observation.own_paddle();
+        let step = paddle.speed * observation.sim.physics.time_step();
+        let direction = ((target - paddle.position.y) / step).clamp(-1.0, 1.0);
+        PaddleInput { direction, serve: std::mem::take(&mut self.serve) }
+    }
+}
+
+// Where a touch or the cursor is in the arena. Both come in window
+// coordinates, from the bottom left corner, and go out through the camera.
+fn pointer_position(
+    windows: &Windows,
+    touches: &Touches,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec2> {
+    let window = windows.get_primary()?;
+    let position = touches.first_pressed_position().or_else(|| window.cursor_position())?;
+
+    let window_size = Vec2::new(window.width(), window.height());
+    let ndc = (position / window_size) * 2.0 - Vec2::ONE;
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+    Some(ndc_to_world.project_point3(ndc.extend(-1.0)).truncate())
+}
+
+// Copy this frame's device state into the seats that need it
+pub fn sample_devices(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<Bindings>,
+    assignment: Res<GamepadAssignment>,
+    axes: Res<Axis<GamepadAxis>>,
+    buttons: Res<Input<GamepadButton>>,
+    windows: Res<Windows>,
+    touches: Res<Touches>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    mut seats: ResMut<Seats>,
+) {
+    let pointer = camera_query.get_single().ok().and_then(|(camera, transform)| {
+        pointer_position(&windows, &touches, camera, transform)
+    });
+    let clicked = mouse_buttons.just_pressed(MouseButton::Left);
+
+    for paddle in [Paddle::Player, Paddle::Opponent] {
+        match seats.get_mut(paddle) {
+            Seat::Keyboard(keyboard) => keyboard.sample(&keys, &bindings),
+            Seat::Gamepad(gamepad) => gamepad.sample(assignment.get(paddle), &axes, &buttons),
+            Seat::Mouse(mouse) => mouse.sample(pointer, clicked),
+            Seat::Computer(_) | Seat::Remote(_) => { }
+        }
+    }
+}
+
+// Hot-plugging and claiming sides. A newly connected pad fills the first
+// gamepad seat still waiting for one, and pressing a pad's left or right
+// bumper gives it that paddle. Losing the pad in use pauses the game,
+// except online where there's no pausing.
+pub fn assign_gamepads(
+    mut gamepad_events: EventReader<GamepadEvent>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    mut assignment: ResMut<GamepadAssignment>,
+    mut seats: ResMut<Seats>,
+    mode: Res<GameMode>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for event in gamepad_events.iter() {
+        match event.event_type {
+            GamepadEventType::Connected => {
+                println!("Gamepad {} connected", event.gamepad.id);
+                assignment.fill(&seats.setup, &gamepads);
+            }
+            GamepadEventType::Disconnected => {
+                println!("Gamepad {} disconnected", event.gamepad.id);
+                if let Some(paddle) = assignment.side_of(event.gamepad) {
+                    assignment.set(paddle, None);
+                    if *state.current() == GameState::InGame && *mode != GameMode::Online {
+                        // Another transition may already be queued this frame
+                        let _ = state.push(GameState::Paused);
+                    }
+                }
+            }
+            GamepadEventType::ButtonChanged(..) | GamepadEventType::AxisChanged(..) => { }
+        }
+    }
+
+    for button in buttons.get_just_pressed() {
+        let paddle = match button.button_type {
+            GamepadButtonType::LeftTrigger => Paddle::Player,
+            GamepadButtonType::RightTrigger => Paddle::Opponent,
+            _ => continue,
+        };
+        if let Some(previous) = assignment.side_of(button.gamepad) {
+            assignment.set(previous, None);
+        }
+        assignment.set(paddle, Some(button.gamepad));
+        seats.switch_to_gamepad(paddle);
+        println!("Gamepad {} plays the {} paddle", button.gamepad.id,
+            match paddle { Paddle::Player => "left", Paddle::Opponent => "right" });
+    }
+}
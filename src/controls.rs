@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+
+use crate::GameState;
+use crate::bindings::{Action, Bindings};
+use crate::main_menu::{spawn_button, spawn_menu_column, spawn_sized_button};
+use crate::menu_nav::back_pressed;
+use crate::widgets::spawn_group;
+
+pub struct ControlsPlugin;
+
+#[derive(Component)]
+struct ControlsEntity;
+
+#[derive(Component)]
+struct BindingButton(Action);
+
+#[derive(Component)]
+struct ResetBindingsButton;
+
+#[derive(Component)]
+struct DoneButton;
+
+// The action waiting for its new key, if any
+#[derive(Default)]
+pub struct Rebinding(pub Option<Action>);
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        println!("Building controls screen!");
+        app
+        .init_resource::<Rebinding>()
+        .add_system_set(
+            SystemSet::on_enter(GameState::Controls)
+                .with_system(setup_controls)
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Controls)
+                .with_system(binding_button_sys)
+                .with_system(capture_key_sys)
+                .with_system(reset_button_sys)
+                .with_system(done_button_sys)
+                .with_system(update_binding_labels)
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Controls)
+                .with_system(teardown_controls)
+        );
+    }
+}
+
+fn get_binding_str(action: Action, bindings: &Bindings, rebinding: &Rebinding) -> String {
+    if rebinding.0 == Some(action) {
+        format!("{}: press a key", action.name())
+    } else {
+        format!("{}: {:?}", action.name(), bindings.key(action))
+    }
+}
+
+fn setup_controls(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    bindings: Res<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    rebinding.0 = None;
+
+    let title = commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "Controls",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 60.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            )
+            .with_style(Style {
+                margin: UiRect::all(Val::Px(10.0)),
+                ..default()
+            }),
+        )
+        .id();
+
+    // Solo and general keys on the left, versus keys on the right
+    let mut columns = Vec::new();
+    for actions in [
+        &[Action::MoveUp, Action::MoveDown, Action::Serve, Action::Pause, Action::Back][..],
+        &[Action::LeftUp, Action::LeftDown, Action::RightUp, Action::RightDown][..],
+    ] {
+        let buttons: Vec<Entity> = actions
+            .iter()
+            .map(|action| {
+                spawn_sized_button(&mut commands, &asset_server,
+                    &get_binding_str(*action, &bindings, &rebinding),
+                    Size::new(Val::Px(380.0), Val::Px(50.0)), 30.0)
+                    .insert(BindingButton(*action))
+                    .id()
+            })
+            .collect();
+        let column = spawn_group(&mut commands, FlexDirection::ColumnReverse)
+            .push_children(&buttons)
+            .id();
+        columns.push(column);
+    }
+    let bindings_row = spawn_group(&mut commands, FlexDirection::Row)
+        .push_children(&columns)
+        .id();
+
+    let reset = spawn_button(&mut commands, &asset_server, "Reset")
+        .insert(ResetBindingsButton)
+        .id();
+    let done = spawn_button(&mut commands, &asset_server, "Done")
+        .insert(DoneButton)
+        .id();
+    let buttons_row = spawn_group(&mut commands, FlexDirection::Row)
+        .push_children(&[reset, done])
+        .id();
+
+    spawn_menu_column(&mut commands)
+        .push_children(&[title, bindings_row, buttons_row])
+        .insert(ControlsEntity);
+}
+
+// Click an action, then press the key for it
+fn binding_button_sys(
+    interaction_query: Query<(&Interaction, &BindingButton), Changed<Interaction>>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            rebinding.0 = Some(button.0);
+        }
+    }
+}
+
+// Take the next key for the action being rebound. Escape gives up on it,
+// and otherwise the Back key or B leaves the screen.
+fn capture_key_sys(
+    mut keys: ResMut<Input<KeyCode>>,
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let action = match rebinding.0 {
+        Some(action) => action,
+        None => {
+            if back_pressed(&keys, &bindings, &gamepad_buttons) {
+                state.set(GameState::MainMenu).expect("Failed to exit to menu");
+                keys.reset(bindings.key(Action::Back));
+            }
+            return;
+        }
+    };
+
+    let key = match keys.get_just_pressed().next() {
+        Some(key) => *key,
+        None => return,
+    };
+    if key != KeyCode::Escape {
+        bindings.set(action, key);
+        save_bindings(&bindings);
+    }
+    rebinding.0 = None;
+    keys.reset(key);
+}
+
+fn save_bindings(bindings: &Bindings) {
+    if let Err(error) = bindings.save() {
+        println!("Could not save key bindings: {}", error);
+    }
+}
+
+fn reset_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<ResetBindingsButton>)>,
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            *bindings = Bindings::default();
+            rebinding.0 = None;
+            save_bindings(&bindings);
+        }
+    }
+}
+
+fn done_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<DoneButton>)>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            state.set(GameState::MainMenu).expect("Failed to exit to menu");
+        }
+    }
+}
+
+fn update_binding_labels(
+    button_query: Query<(&BindingButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+    bindings: Res<Bindings>,
+    rebinding: Res<Rebinding>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (button, children) in &button_query {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        text.sections[0].value = get_binding_str(button.0, &bindings, &rebinding);
+    }
+}
+
+fn teardown_controls(
+    mut commands: Commands,
+    query: Query<Entity, With<ControlsEntity>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,48 +1,31 @@
-use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use bevy::{
-    prelude::*,
-    sprite::{collide_aabb::{collide, Collision}, Rect},    
-    time::FixedTimestep, input::keyboard::KeyboardInput,    
-    ui::FocusPolicy,
-};
+use bevy::prelude::*;
+
+use pong::ai::Difficulty;
+use pong::collision::{CollisionEvent, Surface};
+use pong::controller::Observation;
+use pong::physics::{Arena, PhysicsConfig};
+use pong::rules::MatchRules;
+use pong::server::ServerStatus;
+use pong::settings::GameSettings;
+use pong::sim::{Paddle, PongSim, SimEvent, SimInputs, WallLocation};
 
 use crate::GameState;
-use crate::SCREEN_HEIGHT;
-use crate::SCREEN_WIDTH;
-use crate::BG_COLOR;
+use crate::bindings::{Action, Bindings};
+use crate::controllers::{assign_gamepads, sample_devices, GameMode, GamepadAssignment, SeatSetup, Seats};
+use crate::match_state::{LastMatch, Match, MatchPhase};
+use crate::online::{Online, OnlineMatch};
 use crate::pause_game;
+use crate::replays::{Playback, Recording, ReplayPlayer, SeekReplay};
+use crate::theme::palette;
 
-const TIME_STEP: f32 = 1.0 / 60.0;
-
-const RIGHT_WALL: f32 = SCREEN_WIDTH / 2.0;
-const LEFT_WALL: f32 = -1.0 * RIGHT_WALL;
-const TOP_WALL: f32 = SCREEN_HEIGHT / 2.0;
-const BOTTOM_WALL: f32 = -1.0 * TOP_WALL;
-
-const ARENA_WIDTH: f32 = RIGHT_WALL - LEFT_WALL;
-const ARENA_HEIGHT: f32 = TOP_WALL - BOTTOM_WALL;
-
-const WALL_THICKNESS: f32 = 100.0;
-const WALL_LENGTH: f32 = SCREEN_HEIGHT;
+const FLASH_COLOR: Color = Color::WHITE;
+const FLASH_TIME: f32 = 0.15;
 
-const VERT_OFFSET: f32 = 50.0;
-const HORI_OFFSET: f32 = 25.0;
-
-const INITIAL_BALL_DIRECTION: Vec2 = Vec2::new(-0.5, 0.5);
-const BALL_SPEED: f32 = 450.0;
-const BALL_RAD: f32 = 25.0;
-const BALL_SIZE: Vec3 = Vec3::new(BALL_RAD,BALL_RAD, 1.0);
-
-const WALL_COLOR: Color = Color::rgb(0.30, 0.30, 0.15);
-
-const PADDLE_OFFSET: f32 = 50.;
-const PADDLE_WIDTH: f32 = 15.;
-const PADDLE_HEIGHT: f32 = 100.;
-const PLAYER_COLOR: Color = Color::BEIGE;
-const PLAYER_VELOCITY: f32 = 200.;
-const OPPONENT_COLOR: Color = Color::BISQUE;
-const OPPONENT_VELOCITY: f32 = 250.;
+// After a long stall the game slows down rather than running this many
+// ticks in one frame to catch up
+const MAX_TICKS_PER_FRAME: u32 = 8;
 
 
 pub struct InGamePlugin;
@@ -50,22 +33,35 @@ pub struct InGamePlugin;
 impl Plugin for InGamePlugin {
     fn build(&self, app: &mut App) {
         println!{"Building game!"};
+        // Tuning and rules come from the settings file if main loaded one
+        let physics = *app.world.get_resource_or_insert_with(PhysicsConfig::default);
+        let rules = *app.world.get_resource_or_insert_with(MatchRules::default);
         app
-        .insert_resource(Scoreboard {
-            player_score: 0,
-            opponent_score: 0,
-        })
+        .insert_resource(PongSim::new(physics, rules, new_seed()))
+        .insert_resource(SimClock::default())
+        .init_resource::<Difficulty>()
+        .init_resource::<SeatSetup>()
+        .insert_resource(GameMode::default())
+        .insert_resource(GamepadAssignment::default())
+        .init_resource::<Match>()
+        .init_resource::<LastMatch>()
+        // Gamepads come and go whatever screen is up
+        .add_system(assign_gamepads)
+        .insert_resource(Seats::new(&SeatSetup::default(), Difficulty::default(), new_seed()))
+        .add_event::<CollisionEvent>()
+        .add_event::<RestartMatch>()
         .add_system_set(
             SystemSet::on_enter(GameState::InGame)            
                 .with_system(game_setup)                            
         )
         .add_system_set(
             SystemSet::on_update(GameState::InGame)                                
-                .with_system(check_for_collisions)                
-                .with_system(move_player.before(check_for_collisions))
-                .with_system(apply_velocity.before(check_for_collisions))                
-                .with_system(move_opponent.before(check_for_collisions))                
-                .with_system(update_score)
+                .with_system(restart_match.before(step_simulation))
+                .with_system(seek_replay.before(step_simulation))
+                .with_system(sample_devices.before(step_simulation))
+                .with_system(step_simulation.label(SimStep))
+                .with_system(sync_transforms.after(step_simulation))
+                .with_system(flash_on_collision.after(step_simulation))
                 .with_system(esc_to_menu)
                 .with_system(pause_game)                
         )
@@ -74,12 +70,19 @@ impl Plugin for InGamePlugin {
                 .with_system(teardown_ingame)
         )
         .add_system_set(
-            SystemSet::on_update(GameState::Paused)
-            .with_system(pause_game)            
+            SystemSet::on_exit(GameState::GameOver)
+                .with_system(teardown_ingame)
         );
     }    
 }
 
+// Start the match over from the pause menu
+pub struct RestartMatch;
+
+// Systems that show what happened in a frame's ticks run after this
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SimStep;
+
 #[derive(Component)]
 struct Ball;
 
@@ -89,168 +92,180 @@ struct Player;
 #[derive(Component)]
 struct Opponent;
 
-#[derive(Component)]
-struct Wall;
+// Where the ball and paddles were and are, to draw them in between
+#[derive(Default, Clone, Copy)]
+struct Positions {
+    ball: Vec2,
+    player: Vec2,
+    opponent: Vec2,
+}
 
-#[derive(Component)]
-struct Collider;
+impl Positions {
+    fn of(sim: &PongSim) -> Positions {
+        Positions {
+            ball: sim.ball.position,
+            player: sim.player.position,
+            opponent: sim.opponent.position,
+        }
+    }
 
-#[derive(Component)]
-struct Velocity(Vec2);
+    fn lerp(&self, other: &Positions, t: f32) -> Positions {
+        Positions {
+            ball: self.ball.lerp(other.ball, t),
+            player: self.player.lerp(other.player, t),
+            opponent: self.opponent.lerp(other.opponent, t),
+        }
+    }
+}
 
+// Runs the simulation at its own tick rate, whatever the frame rate.
+// Frame time is banked in `accumulator` and spent a whole tick at a time.
 #[derive(Default)]
-struct CollisionEvent;
+struct SimClock {
+    accumulator: f32,
+    previous: Positions,
+}
 
-#[derive(Component)]
-struct GameEntity;
+impl SimClock {
+    fn new(sim: &PongSim) -> SimClock {
+        SimClock {
+            accumulator: 0.0,
+            previous: Positions::of(sim),
+        }
+    }
+}
 
+// Which surface of the simulation an entity draws
 #[derive(Component)]
-struct Score;
+struct Collider(Surface);
 
-struct OuterBallLoc {
-    top: f32,
-    bottom: f32,
-    left: f32,
-    right: f32
-}
-
-struct Scoreboard {
-    player_score: usize,
-    opponent_score: usize,
+// Brightens a collider for a moment after the ball touches it
+#[derive(Component)]
+struct Flash {
+    color: Color,
+    remaining: f32,
 }
 
-enum Paddle {
-    Player,
-    Opponent,
+impl Flash {
+    fn new(color: Color) -> Flash {
+        Flash { color, remaining: 0.0 }
+    }
 }
 
-impl Paddle {
-    fn position(&self) -> Vec2 {
-        match self {
-            Paddle::Player => {
-                Vec2::new(WallLocation::Left.inner().x + PADDLE_OFFSET,
-                WallLocation::Left.inner().y)
-            }
-            Paddle::Opponent => {
-                Vec2::new(WallLocation::Right.inner().x - PADDLE_OFFSET,
-                WallLocation::Left.inner().y)
-            }
-        }
-    }
+#[derive(Component)]
+struct GameEntity;
 
-    fn size(&self) -> Vec2 {
-        Vec2::new(PADDLE_WIDTH, PADDLE_HEIGHT)
-    }            
-}
 #[derive(Bundle)]
 struct WallBundle {
     #[bundle]
     sprite_bundle: SpriteBundle,
     collider: Collider,
-}
-enum WallLocation {
-    Left,
-    Right,
-    Bottom,
-    Top,
-}
-
-impl WallLocation {
-    fn position(&self) -> Vec2 {
-        match self {
-            WallLocation::Left => Vec2::new(LEFT_WALL + HORI_OFFSET, 0.),
-            WallLocation::Right => Vec2::new(RIGHT_WALL - HORI_OFFSET, 0.),
-            WallLocation::Bottom => Vec2::new(0., BOTTOM_WALL + VERT_OFFSET),
-            WallLocation::Top => Vec2::new(0., TOP_WALL - VERT_OFFSET),
-        }
-    }
-
-    fn size(&self) -> Vec2 {
-        match self {
-            WallLocation::Left | WallLocation::Right => {
-                Vec2::new(WALL_THICKNESS, ARENA_HEIGHT + WALL_THICKNESS)
-            }
-            WallLocation::Bottom | WallLocation::Top => {
-                Vec2::new(ARENA_WIDTH + WALL_THICKNESS, WALL_THICKNESS)
-            }
-        }
-    }
-
-    // Return the position of the inner surface of a given wall
-    fn inner(&self) -> Vec2 {
-        match self {
-            WallLocation::Top => {
-                Vec2::new(WallLocation::Top.position().x,
-                 WallLocation::Top.position().y - WALL_THICKNESS/2.)
-            }
-            WallLocation::Bottom => {
-                Vec2::new(WallLocation::Bottom.position().x,
-                WallLocation::Bottom.position().y + WALL_THICKNESS/2.)
-            }
-            WallLocation::Right => {
-                Vec2::new(WallLocation::Right.position().x - WALL_THICKNESS/2.,
-                 WallLocation::Right.position().y)
-            }
-            WallLocation::Left => {
-                Vec2::new(WallLocation::Left.position().x + WALL_THICKNESS/2.,
-                WallLocation::Left.position().y)
-            }
-        }
-    }
+    flash: Flash,
 }
 
 impl WallBundle {
-    fn new(location: WallLocation) -> WallBundle {
+    fn new(location: WallLocation, arena: &Arena, color: Color) -> WallBundle {
         WallBundle {
             sprite_bundle: SpriteBundle {
                 transform: Transform {
-                    translation: location.position().extend(0.0),
+                    translation: location.position(arena).extend(0.0),
 
-                    scale: location.size().extend(1.0),
+                    scale: location.size(arena).extend(1.0),
                     ..default()
                 },
                 sprite: Sprite {
-                    color: WALL_COLOR,
+                    color,
                     ..default()
                 },
                 ..default()
             },
-            collider: Collider,            
+            collider: Collider(Surface::Wall(location)),
+            flash: Flash::new(color),
         }
     }
 }
 
 
-fn apply_velocity(mut query: Query<(&mut Transform, &Velocity)>) {
-    for (mut transform, velocity) in &mut query {
-        transform.translation.x += velocity.0.x * TIME_STEP;        
-        transform.translation.y += velocity.0.y * TIME_STEP;                        
-    }
-}
-
-// Use setup function to add entities to the game world
+// Start a new match from scratch with the current tuning and rules, and
+// add its entities to the game world
 fn game_setup(mut commands: Commands, 
-    asset_server: Res<AssetServer>,
-    scoreboard: Res<Scoreboard>,
-    state: Res<State<GameState>>) {
+    mut current: ResMut<Match>,
+    mut sim: ResMut<PongSim>,
+    mut clock: ResMut<SimClock>,
+    mut seats: ResMut<Seats>,
+    seat_setup: Res<SeatSetup>,
+    mode: Res<GameMode>,
+    difficulty: Res<Difficulty>,
+    gamepads: Res<Gamepads>,
+    mut assignment: ResMut<GamepadAssignment>,
+    physics: Res<PhysicsConfig>,
+    rules: Res<MatchRules>,
+    settings: Res<GameSettings>,
+    mut playback: ResMut<Playback>,
+    mut online: ResMut<Online>,
+    mut recording: ResMut<Recording>) {
+    // Already on the field, so there's nothing to set up
+    if current.phase == MatchPhase::Running {
+        return;
+    }
     println!("Setting up game!");    
+
+    // A replay brings its own match, and so does the host of an online one
+    // or a match server; anything else is played and recorded
+    let setup = match (&mut playback.0, &mut online.0) {
+        (Some(player), _) => {
+            *sim = player.seek(0);
+            recording.0 = None;
+            player.setup()
+        }
+        (None, Some(OnlineMatch::Peer(session))) => {
+            let setup = SeatSetup::online(seat_setup.player, session.local_side());
+            assignment.fill(&setup, &gamepads);
+            *sim = session.sim().clone();
+            *recording = Recording::new(&setup, *difficulty, session.seed, sim.physics, sim.rules);
+            setup
+        }
+        // Only the server sees both sides' inputs, so there's nothing here
+        // to record
+        (None, Some(OnlineMatch::Server(client))) => {
+            let side = client.side().unwrap_or(Paddle::Player);
+            let setup = SeatSetup::online(seat_setup.player, side);
+            assignment.fill(&setup, &gamepads);
+            if let Some(view) = client.view(0.0) {
+                *sim = view;
+            }
+            recording.0 = None;
+            setup
+        }
+        (None, None) => {
+            let setup = assignment.apply(seat_setup.for_mode(*mode));
+            assignment.fill(&setup, &gamepads);
+            let seed = new_seed();
+            *sim = PongSim::new(*physics, *rules, seed);
+            *recording = Recording::new(&setup, *difficulty, seed, *physics, *rules);
+            setup
+        }
+    };
+    *current = Match::new(*mode, setup);
+    *clock = SimClock::new(&sim);
+    *seats = Seats::new(&setup, *difficulty, new_seed());
+    let colors = palette(&settings.window);
     
    commands
         .spawn()
         .insert(Ball)
         .insert_bundle(SpriteBundle {
             transform: Transform {
-                scale: BALL_SIZE,
-                translation: Vec3::new(0.0,0.0,0.0),                
+                scale: sim.ball.size.extend(1.0),
+                translation: sim.ball.position.extend(0.0),                
                 ..default()
             },
             sprite: Sprite {
-                color: Color::rgb(0.9, 0.5, 0.5),
+                color: colors.ball,
                 ..default()
             },
             ..default()
         })        
-        .insert(Velocity(INITIAL_BALL_DIRECTION.normalize()*BALL_SPEED))
         .insert(GameEntity);
 
     // PLayer
@@ -259,17 +274,18 @@ fn game_setup(mut commands: Commands,
         .insert(Player)
         .insert_bundle(SpriteBundle {
             transform: Transform {
-                scale: Paddle::Player.size().extend(0.0),
-                translation: Paddle::Player.position().extend(0.0),
+                scale: sim.player.size.extend(0.0),
+                translation: sim.player.position.extend(0.0),
                 ..default()
             },
             sprite: Sprite {
-                color: PLAYER_COLOR,
+                color: colors.player,
                 ..default()
             },
             ..default()
         })
-        .insert(Collider)
+        .insert(Collider(Surface::Paddle(Paddle::Player)))
+        .insert(Flash::new(colors.player))
         .insert(GameEntity);
         
     // Opponent
@@ -278,194 +294,300 @@ fn game_setup(mut commands: Commands,
         .insert(Opponent)
         .insert_bundle(SpriteBundle {
             transform: Transform {
-                scale: Paddle::Opponent.size().extend(0.0),
-                translation: Paddle::Opponent.position().extend(0.0),
+                scale: sim.opponent.size.extend(0.0),
+                translation: sim.opponent.position.extend(0.0),
                 ..default()
             },
             sprite: Sprite {
-                color: OPPONENT_COLOR,
+                color: colors.opponent,
                 ..default()
             },
             ..default()
         })
-        .insert(Velocity(Vec2::new(0.0,1.0)*OPPONENT_VELOCITY))
-        .insert(Collider)
+        .insert(Collider(Surface::Paddle(Paddle::Opponent)))
+        .insert(Flash::new(colors.opponent))
         .insert(GameEntity);
 
-    // Player Score
-    commands
-        .spawn_bundle(
-            // Create a TextBundle that has a Text with a single section.
-            TextBundle::from_section(
-                // Accepts a `String` or any type that converts into a `String`, such as `&str`
-                get_score_str(scoreboard),
-                TextStyle {
-                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                    font_size: 25.0,
-                    color: PLAYER_COLOR,
-                },
-            ) // Set the alignment of the Text
-            .with_text_alignment(TextAlignment::BOTTOM_LEFT)
-            // Set the style of the TextBundle itself.
-            .with_style(Style {
-                align_self: AlignSelf::FlexEnd,
-                position_type: PositionType::Absolute,
-                position: UiRect {
-                    bottom: Val::Percent(5.0),
-                    left: Val::Percent(5.0),
-                    ..default()
-                },
-                ..default()
-            }),
-        )
-        .insert(Score)
-        .insert(GameEntity);
-    
     // Spawn walls using implementation of WallBundle enum
     commands.spawn_bundle(
-        WallBundle::new(WallLocation::Left)).insert(GameEntity);
+        WallBundle::new(WallLocation::Left, &sim.physics.arena, colors.wall)).insert(GameEntity);
     commands.spawn_bundle(
-        WallBundle::new(WallLocation::Right)).insert(GameEntity);
+        WallBundle::new(WallLocation::Right, &sim.physics.arena, colors.wall)).insert(GameEntity);
     commands.spawn_bundle(
-        WallBundle::new(WallLocation::Top)).insert(GameEntity);
+        WallBundle::new(WallLocation::Top, &sim.physics.arena, colors.wall)).insert(GameEntity);
     commands.spawn_bundle(
-        WallBundle::new(WallLocation::Bottom)).insert(GameEntity);
+        WallBundle::new(WallLocation::Bottom, &sim.physics.arena, colors.wall)).insert(GameEntity);
 
+    current.start();
 }
 
 
-fn get_score_str(scoreboard: Res<Scoreboard>) -> String {    
-    format!("Player Score: {}\nComputer Score: {}", 
-    scoreboard.player_score, scoreboard.opponent_score)
-    .to_string()    
-}
-
-fn update_score(
-    mut score_query: Query<&mut Text, With<Score>>,
-    scoreboard: Res<Scoreboard>
+// Run as many ticks as this frame's time pays for, feeding them this
+// frame's inputs (or the replay's), and forward what happened. Online, the
+// session plays the ticks and only this side's inputs come from here. On
+// a match server no ticks are played here at all: this side's inputs go
+// out once a tick and the server's view of the match is shown.
+fn step_simulation(
+    time: Res<Time>,
+    mut sim: ResMut<PongSim>,
+    mut clock: ResMut<SimClock>,
+    mut seats: ResMut<Seats>,
+    mut collision_events: EventWriter<CollisionEvent>,
+    mut sim_events: EventWriter<SimEvent>,
+    mut current: ResMut<Match>,
+    mut playback: ResMut<Playback>,
+    mut online: ResMut<Online>,
+    mut recording: ResMut<Recording>,
+    mut state: ResMut<State<GameState>>,
 ) {
-    let mut score = score_query.single_mut();
-    score.sections[0].value = get_score_str(scoreboard);    
-}
-
-fn is_between(input: f32, val_1: f32, val_2: f32) -> bool {
-    if input < val_1 && input > val_2 {
-        return true
+    let time_step = sim.physics.time_step();
+    let time_scale = playback.0.as_ref().map_or(1.0, ReplayPlayer::time_scale);
+    clock.accumulator += time.delta_seconds() * time_scale;
+    // A paused replay moves on one whole tick per step asked for
+    if playback.0.as_mut().is_some_and(ReplayPlayer::take_step) {
+        clock.accumulator += time_step;
     }
-    false
-}
-
-fn check_for_collisions(    
-    mut ball_query: Query<(&mut Velocity, &Transform), With<Ball>>,
-    mut player_query: Query<&Transform, With<Player>>,
-    mut opponent_query: Query<&Transform, With<Opponent>>,
-    mut scoreboard: ResMut<Scoreboard>
-) {    
-    let (mut ball_velocity, mut ball_transform) = ball_query.single_mut();
-    let player_transform = player_query.single_mut();
-    let opponent_transform = opponent_query.single_mut();    
-
-    let ball_side = OuterBallLoc {
-        top:    ball_transform.translation.y + ball_transform.scale.y/2.,
-        bottom: ball_transform.translation.y - ball_transform.scale.y/2.,
-        left:   ball_transform.translation.x - ball_transform.scale.x/2.,
-        right:  ball_transform.translation.x + ball_transform.scale.x/2.,
-    };
 
-    if is_between(ball_transform.translation.y, 
-        player_transform.translation.y + player_transform.scale.y/2.,
-        player_transform.translation.y - player_transform.scale.y/2.) &&
-        is_between(ball_side.left,
-        player_transform.translation.x + player_transform.scale.x/2.,
-        player_transform.translation.x - player_transform.scale.x/2.) {
-        
-            ball_velocity.0.x *= -1.0;
+    if let Some(OnlineMatch::Server(client)) = &mut online.0 {
+        let side = client.side().unwrap_or(Paddle::Player);
+        if client.status() == ServerStatus::Playing {
+            let mut ticks = 0;
+            while clock.accumulator >= time_step && ticks < MAX_TICKS_PER_FRAME {
+                client.send_input(seats.intent(&Observation::new(&sim, side)));
+                clock.accumulator -= time_step;
+                ticks += 1;
+            }
+            clock.accumulator %= time_step;
+        } else {
+            // Time spent held up isn't made up for afterwards
+            clock.accumulator = 0.0;
         }
-
-    else if is_between(ball_transform.translation.y, 
-        opponent_transform.translation.y + opponent_transform.scale.y/2.,
-        opponent_transform.translation.y - opponent_transform.scale.y/2.) &&
-        is_between(ball_side.right,
-        opponent_transform.translation.x + opponent_transform.scale.x/2.,
-        opponent_transform.translation.x - opponent_transform.scale.x/2.) {
-        
-            ball_velocity.0.x *= -1.0;
+        if let Some(view) = client.view(time.delta_seconds()) {
+            *sim = view;
         }
-
-    if ball_side.top > WallLocation::Top.inner().y || ball_side.bottom 
-        < WallLocation::Bottom.inner().y {
-        
-        ball_velocity.0.y *= -1.0;
+        // The view is already smooth, so it's drawn as it is
+        clock.previous = Positions::of(&sim);
+        for event in client.take_events() {
+            forward_event(event, &mut collision_events, false, &mut state);
+            sim_events.send(event);
+        }
+        return;
     }
 
-    if ball_side.right > WallLocation::Right.inner().x {        
-        scoreboard.opponent_score += 1;              
-        ball_velocity.0.x *= -1.0;
-    }      
+    let mut ticks = 0;
+    while clock.accumulator >= time_step {
+        if ticks == MAX_TICKS_PER_FRAME {
+            clock.accumulator %= time_step;
+            break;
+        }
 
-    else if  ball_side.left < WallLocation::Left.inner().x {        
-        scoreboard.player_score += 1;  
-        ball_velocity.0.x *= -1.0;
+        let previous = Positions::of(&sim);
+        let events = if let Some(OnlineMatch::Peer(session)) = &mut online.0 {
+            let side = session.local_side();
+            match session.step(|shown| seats.intent(&Observation::new(shown, side))) {
+                // A rollback may have changed more than the latest tick.
+                // Ticks are recorded once the other side confirms them.
+                Some(events) => {
+                    *sim = session.sim().clone();
+                    events
+                }
+                // Too far ahead of the other side: hold still until it
+                // catches up
+                None => {
+                    clock.previous = previous;
+                    clock.accumulator = 0.0;
+                    break;
+                }
+            }
+        } else {
+            let inputs = match &mut playback.0 {
+                Some(player) => match player.next_inputs() {
+                    Some(inputs) => inputs,
+                    // The end of the replay: hold the last frame
+                    None => {
+                        clock.accumulator = 0.0;
+                        break;
+                    }
+                },
+                None => SimInputs {
+                    player: seats.intent(&Observation::new(&sim, Paddle::Player)),
+                    opponent: seats.intent(&Observation::new(&sim, Paddle::Opponent)),
+                },
+            };
+            let events = sim.step(inputs);
+            recording.record(inputs, &sim, &events);
+            events
+        };
+        clock.previous = previous;
+        clock.accumulator -= time_step;
+        ticks += 1;
+
+        // A replay plays on past the end, and an online match is only won
+        // once both sides agree on it
+        let deciding = playback.0.is_none() && online.0.is_none();
+        for event in events {
+            if let SimEvent::MatchWon(_) = event {
+                if deciding {
+                    current.finish(&sim);
+                }
+            }
+            forward_event(event, &mut collision_events, deciding, &mut state);
+            sim_events.send(event);
+        }
     }
-         
 }
 
-
-fn move_player(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<&mut Transform, With<Player>>,
+fn forward_event(
+    event: SimEvent,
+    collision_events: &mut EventWriter<CollisionEvent>,
+    deciding: bool,
+    state: &mut State<GameState>,
 ) {
-    let mut player_transform = query.single_mut();
-    let mut direction = 0.0;
-
-    if keyboard_input.pressed(KeyCode::Up) {
-        direction = 1.0;
+    match event {
+        SimEvent::Collision(collision) => {
+            collision_events.send(collision);
+        }
+        // A replay stays on the field at the end, for another look, and
+        // an online win has to be confirmed first
+        SimEvent::MatchWon(_) if !deciding => { }
+        SimEvent::MatchWon(_) => {
+            state.set(GameState::GameOver).expect("Failed to end match");
+        }
+        SimEvent::ServeStarted(_) | SimEvent::Served(_)
+        | SimEvent::PointScored(_) | SimEvent::GameWon(_) => { }
     }
+}
 
-    if keyboard_input.pressed(KeyCode::Down) {
-        direction = -1.0; 
-    }    
+fn flash_on_collision(
+    time: Res<Time>,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut query: Query<(&Collider, &mut Flash, &mut Sprite)>,
+) {
+    let hits: Vec<Surface> = collision_events.iter().map(|event| event.surface).collect();
 
-    let new_pos = player_transform.translation.y + direction * PLAYER_VELOCITY * TIME_STEP;
+    for (collider, mut flash, mut sprite) in &mut query {
+        if hits.contains(&collider.0) {
+            flash.remaining = FLASH_TIME;
+        } else {
+            flash.remaining = (flash.remaining - time.delta_seconds()).max(0.0);
+        }
 
-    player_transform.translation.y = new_pos;
+        let t = flash.remaining / FLASH_TIME;
+        let [r, g, b, a] = flash.color.as_rgba_f32();
+        let [fr, fg, fb, _] = FLASH_COLOR.as_rgba_f32();
+        sprite.color = Color::rgba(r + (fr - r) * t, g + (fg - g) * t, b + (fb - b) * t, a);
+    }
 }
 
-fn move_opponent(
-    mut query: Query<(&mut Velocity, &Transform), With<Opponent>>,
-    mut ball_query: Query<&Transform, With<Ball>>
+// Move the sprites to where the simulation says they are, part way from
+// the previous tick to the latest one. Sizes follow too, as the paddles
+// can be resized from the settings.
+fn sync_transforms(
+    sim: Res<PongSim>,
+    clock: Res<SimClock>,
+    mut ball_query: Query<&mut Transform, With<Ball>>,
+    mut player_query: Query<&mut Transform, (With<Player>, Without<Ball>)>,
+    mut opponent_query: Query<&mut Transform, (With<Opponent>, Without<Ball>, Without<Player>)>,
 ) {
-    let (mut opponent_velocity, opponent_transform) 
-        = query.single_mut();
-    let ypos = opponent_transform.translation.y;
-    let ylen = opponent_transform.scale.y;
-
-    let ball_transform = ball_query.single_mut();
-    
-    if ball_transform.translation.y > ypos {
-        opponent_velocity.0.y = OPPONENT_VELOCITY;
-    }
-    else if ball_transform.translation.y < ypos {
-        opponent_velocity.0.y = -1.0*OPPONENT_VELOCITY;
-    }
+    let alpha = (clock.accumulator / sim.physics.time_step()).clamp(0.0, 1.0);
+    let positions = clock.previous.lerp(&Positions::of(&sim), alpha);
+
+    let mut ball = ball_query.single_mut();
+    ball.translation = positions.ball.extend(0.0);
+    ball.scale = sim.ball.size.extend(1.0);
+    let mut player = player_query.single_mut();
+    player.translation = positions.player.extend(0.0);
+    player.scale = sim.player.size.extend(0.0);
+    let mut opponent = opponent_query.single_mut();
+    opponent.translation = positions.opponent.extend(0.0);
+    opponent.scale = sim.opponent.size.extend(0.0);
 }
 
 fn esc_to_menu(
     mut keys: ResMut<Input<KeyCode>>,
+    bindings: Res<Bindings>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
     mut app_state: ResMut<State<GameState>>,
 ) {
-    if keys.just_pressed(KeyCode::Escape) {
+    let select = gamepad_buttons.get_just_pressed()
+        .any(|button| button.button_type == GamepadButtonType::Select);
+    if bindings.just_pressed(&keys, Action::Back) || select {
         app_state.set(GameState::MainMenu)
         .expect("Failed to exit to menu");
-        keys.reset(KeyCode::Escape);
+        keys.reset(bindings.key(Action::Back));
+    }
+}
+
+// Jump to another point in the replay being watched
+fn seek_replay(
+    mut seek_events: EventReader<SeekReplay>,
+    mut playback: ResMut<Playback>,
+    mut sim: ResMut<PongSim>,
+    mut clock: ResMut<SimClock>,
+) {
+    let tick = match seek_events.iter().last() {
+        Some(seek) => seek.0,
+        None => return,
+    };
+    if let Some(player) = &mut playback.0 {
+        *sim = player.seek(tick);
+        *clock = SimClock::new(&sim);
+    }
+}
+
+// Fresh match and controllers, keeping the sprites already on screen. A
+// replay starts again from the top.
+fn restart_match(
+    mut restart_events: EventReader<RestartMatch>,
+    mut current: ResMut<Match>,
+    mut last: ResMut<LastMatch>,
+    mut sim: ResMut<PongSim>,
+    mut clock: ResMut<SimClock>,
+    mut seats: ResMut<Seats>,
+    mut playback: ResMut<Playback>,
+    mut recording: ResMut<Recording>,
+    physics: Res<PhysicsConfig>,
+    rules: Res<MatchRules>,
+    difficulty: Res<Difficulty>,
+) {
+    if restart_events.iter().count() == 0 {
+        return;
+    }
+    if let Some(player) = &mut playback.0 {
+        *sim = player.seek(0);
+        *clock = SimClock::new(&sim);
+        return;
     }
+    current.archive(&mut last);
+    recording.save();
+    *current = Match::new(current.mode, current.setup);
+    let seed = new_seed();
+    *sim = PongSim::new(*physics, *rules, seed);
+    *recording = Recording::new(&current.setup, *difficulty, seed, *physics, *rules);
+    *clock = SimClock::new(&sim);
+    *seats = Seats::new(&current.setup, *difficulty, new_seed());
+    current.start();
 }
 
+// Seed for a new match. Recordings keep it, so the match can be replayed.
+pub fn new_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+// Clear the field away, finished match or not
 fn teardown_ingame(
     mut commands: Commands,    
-    query: Query<Entity, With<GameEntity>>
+    query: Query<Entity, With<GameEntity>>,
+    mut current: ResMut<Match>,
+    mut last: ResMut<LastMatch>,
+    mut recording: ResMut<Recording>,
 ) {
+    current.archive(&mut last);
+    recording.save();
     for query_entity in query.iter() {
-        commands.entity(query_entity).despawn();
+        commands.entity(query_entity).despawn_recursive();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+
+use crate::GameState;
+use crate::bindings::{Action, Bindings};
+use crate::controllers::GameMode;
+use crate::main_menu::{spawn_button, spawn_menu_column};
+use crate::match_state::{Match, MatchResult};
+use crate::menu_nav::back_pressed;
+
+pub struct GameOverPlugin;
+
+#[derive(Component)]
+struct GameOverEntity;
+
+#[derive(Component)]
+struct RematchButton;
+
+#[derive(Component)]
+struct MenuButton;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        println!("Building game over screen!");
+        app
+        .add_system_set(
+            SystemSet::on_enter(GameState::GameOver)
+                .with_system(setup_results)
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::GameOver)
+                .with_system(rematch_button_sys)
+                .with_system(menu_button_sys)
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::GameOver)
+                .with_system(teardown_results)
+        );
+    }
+}
+
+fn get_results_str(result: Option<&MatchResult>) -> String {
+    let result = match result {
+        Some(result) => result,
+        None => return "Match over".to_string(),
+    };
+    let winner = format!("{} wins!", result.setup.side_name(result.winner));
+    let scoreboard = &result.scoreboard;
+    if result.best_of > 1 {
+        format!("{}\nGames {} - {}", winner,
+            scoreboard.player_games, scoreboard.opponent_games)
+    } else {
+        format!("{}\n{} - {}", winner,
+            scoreboard.player_score, scoreboard.opponent_score)
+    }
+}
+
+fn setup_results(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    current: Res<Match>,
+) {
+    let title = commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                get_results_str(current.result.as_ref()),
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 60.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            )
+            .with_text_alignment(TextAlignment::CENTER)
+            .with_style(Style {
+                margin: UiRect::all(Val::Px(20.0)),
+                ..default()
+            }),
+        )
+        .id();
+    let rematch = spawn_button(&mut commands, &asset_server, "Rematch")
+        .insert(RematchButton)
+        .id();
+    let menu = spawn_button(&mut commands, &asset_server, "Main Menu")
+        .insert(MenuButton)
+        .id();
+
+    // Stack the title and buttons in the middle of the screen
+    spawn_menu_column(&mut commands)
+        .push_children(&[title, rematch, menu])
+        .insert(GameOverEntity);
+}
+
+// Online, both sides connect again for the rematch
+fn rematch_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<RematchButton>)>,
+    mode: Res<GameMode>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            let next = if *mode == GameMode::Online { GameState::Connecting } else { GameState::InGame };
+            state.set(next).expect("Failed to start rematch");
+        }
+    }
+}
+
+fn menu_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<MenuButton>)>,
+    mut keys: ResMut<Input<KeyCode>>,
+    bindings: Res<Bindings>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let clicked = interaction_query.iter().any(|interaction| *interaction == Interaction::Clicked);
+    if clicked || back_pressed(&keys, &bindings, &gamepad_buttons) {
+        state.set(GameState::MainMenu).expect("Failed to exit to menu");
+        keys.reset(bindings.key(Action::Back));
+    }
+}
+
+fn teardown_results(
+    mut commands: Commands,
+    query: Query<Entity, With<GameOverEntity>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
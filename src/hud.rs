@@ -0,0 +1,537 @@
+use bevy::{prelude::*, ecs::system::EntityCommands};
+
+use pong::collision::Surface;
+use pong::net::NetStats;
+use pong::physics::Arena;
+use pong::server::{ServerClient, ServerStatus};
+use pong::settings::GameSettings;
+use pong::sim::{Paddle, PongSim, SimEvent};
+
+use crate::GameState;
+use crate::controllers::Seats;
+use crate::game::{RestartMatch, SimStep};
+use crate::online::Online;
+use crate::replays::SeekReplay;
+use crate::theme::palette;
+
+pub struct HudPlugin;
+
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const SCORE_FONT_SIZE: f32 = 90.0;
+const LABEL_FONT_SIZE: f32 = 25.0;
+
+const NET_DASH: f32 = 20.0;
+const NET_WIDTH: f32 = 6.0;
+// Behind the ball and paddles, but not behind the camera's far plane
+const NET_DEPTH: f32 = -0.05;
+
+// How long "POINT!" stays up after a point
+const BANNER_TIME: f32 = 1.0;
+
+#[derive(Component)]
+struct HudEntity;
+
+#[derive(Component)]
+struct SideName(Paddle);
+
+#[derive(Component)]
+struct ScoreText(Paddle);
+
+// Games won, shown in matches of more than one game
+#[derive(Component)]
+struct GamesText(Paddle);
+
+#[derive(Component)]
+struct ServerMarker(Paddle);
+
+#[derive(Component)]
+struct RallyText;
+
+#[derive(Component)]
+struct ClockText;
+
+// Ping, rollback and input delay of an online match, or how a match
+// server is doing
+#[derive(Component)]
+struct NetText;
+
+#[derive(Component)]
+struct Countdown;
+
+#[derive(Component)]
+struct PointBanner;
+
+// What the HUD is showing, so text is only rewritten when it changes
+#[derive(Default)]
+struct HudState {
+    // Everything needs writing again, e.g. after the match was restarted
+    // or retuned behind the pause menu
+    stale: bool,
+    rally: u32,
+    clock: u32,
+    countdown: Option<u32>,
+    banner_left: f32,
+    net: String,
+    // A rollback can take back a point already shown
+    rollbacks: u64,
+}
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        println!("Building HUD!");
+        app
+        .init_resource::<HudState>()
+        .add_event::<SimEvent>()
+        .add_system_set(
+            SystemSet::on_enter(GameState::InGame)
+                .with_system(setup_hud)
+        )
+        .add_system_set(
+            SystemSet::on_resume(GameState::InGame)
+                .with_system(mark_hud_stale)
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::InGame)
+                .with_system(refresh_hud.after(SimStep))
+                .with_system(update_hud.after(refresh_hud))
+                .with_system(update_clock.after(refresh_hud))
+                .with_system(update_countdown.after(refresh_hud))
+                .with_system(update_net_stats.after(refresh_hud))
+                .with_system(hide_banner.after(update_hud))
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::MainMenu)
+                .with_system(teardown_hud)
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::GameOver)
+                .with_system(teardown_hud)
+        );
+    }
+}
+
+fn get_clock_str(sim: &PongSim) -> String {
+    // Count down when the match has a time limit
+    let seconds = match sim.rules.time_limit {
+        Some(limit) => (limit - sim.elapsed()).max(0.0).ceil(),
+        None => sim.elapsed().floor(),
+    } as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn clock_seconds(sim: &PongSim) -> u32 {
+    sim.elapsed() as u32
+}
+
+fn get_games_str(sim: &PongSim, paddle: Paddle) -> String {
+    if sim.rules.best_of > 1 {
+        format!("Games: {}", sim.scoreboard.games(paddle))
+    } else {
+        String::new()
+    }
+}
+
+fn get_server_str(sim: &PongSim, paddle: Paddle) -> &'static str {
+    if sim.server() == paddle { "Serving" } else { "" }
+}
+
+fn get_net_str(stats: &NetStats) -> String {
+    let ping = match stats.ping {
+        Some(ping) => format!("{} ms", ping.as_millis()),
+        None => "-".to_string(),
+    };
+    let mut net = format!("Ping {}   Rollback {}   Delay {}",
+        ping, stats.rollback.last_rollback, stats.input_delay);
+    if stats.rollback.desync.is_some() {
+        net.push_str("   DESYNC");
+    }
+    net
+}
+
+fn get_match_server_str(client: &ServerClient) -> String {
+    let mut net = match client.ping() {
+        Some(ping) => format!("Ping {} ms", ping.as_millis()),
+        None => "Ping -".to_string(),
+    };
+    if let ServerStatus::Reconnecting { seconds_left, .. } = client.status() {
+        net.push_str(&format!("   Waiting {} s for the other side", seconds_left));
+    }
+    net
+}
+
+fn text_style(asset_server: &AssetServer, font_size: f32, color: Color) -> TextStyle {
+    TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size,
+        color,
+    }
+}
+
+// Side name, score, games and serve marker, stacked in the middle of one
+// half of the screen
+fn spawn_side(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    paddle: Paddle,
+    color: Color,
+) -> Entity {
+    let name = commands
+        .spawn_bundle(TextBundle::from_section("", text_style(asset_server, LABEL_FONT_SIZE, color)))
+        .insert(SideName(paddle))
+        .id();
+    let score = commands
+        .spawn_bundle(TextBundle::from_section("", text_style(asset_server, SCORE_FONT_SIZE, color)))
+        .insert(ScoreText(paddle))
+        .id();
+    let games = commands
+        .spawn_bundle(TextBundle::from_section("", text_style(asset_server, LABEL_FONT_SIZE, color)))
+        .insert(GamesText(paddle))
+        .id();
+    let server = commands
+        .spawn_bundle(TextBundle::from_section("", text_style(asset_server, LABEL_FONT_SIZE, TEXT_COLOR)))
+        .insert(ServerMarker(paddle))
+        .id();
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(50.0), Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .push_children(&[name, score, games, server])
+        .id()
+}
+
+// A line of text across the whole screen, `top` pixels down
+fn spawn_line<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    top: f32,
+) -> EntityCommands<'w, 's, 'a> {
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Auto),
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(top),
+                ..default()
+            },
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        color: Color::NONE.into(),
+        ..default()
+    })
+}
+
+// Dashes down the middle of the arena, between the top and bottom walls
+fn spawn_net(commands: &mut Commands, arena: &Arena, color: Color) {
+    let height = arena.inner_size().y;
+    let dashes = (height / (2. * NET_DASH)).floor() as usize;
+    let top = dashes as f32 * NET_DASH - NET_DASH / 2.;
+    for index in 0..dashes {
+        let y = top - index as f32 * 2. * NET_DASH - NET_DASH / 2.;
+        commands
+            .spawn_bundle(SpriteBundle {
+                transform: Transform {
+                    translation: Vec3::new(0.0, y, NET_DEPTH),
+                    scale: Vec3::new(NET_WIDTH, NET_DASH, 1.0),
+                    ..default()
+                },
+                sprite: Sprite {
+                    color,
+                    ..default()
+                },
+                ..default()
+            })
+            .insert(HudEntity);
+    }
+}
+
+// The text starts out empty and `refresh_hud` fills it in once the match
+// is set up
+fn setup_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    sim: Res<PongSim>,
+    settings: Res<GameSettings>,
+    mut hud: ResMut<HudState>,
+) {
+    *hud = HudState { stale: true, ..default() };
+    let colors = palette(&settings.window);
+    let arena = sim.physics.arena;
+    // Inner edges of the top and bottom walls, in pixels from the top
+    let field_top = arena.vertical_offset + arena.wall_thickness / 2.;
+    let field_bottom = arena.height - field_top;
+
+    spawn_net(&mut commands, &arena, colors.wall);
+
+    let player = spawn_side(&mut commands, &asset_server, Paddle::Player, colors.player);
+    let opponent = spawn_side(&mut commands, &asset_server, Paddle::Opponent, colors.opponent);
+    spawn_line(&mut commands, field_top + 10.)
+        .push_children(&[player, opponent])
+        .insert(HudEntity);
+
+    // Clock on the top wall, rally count on the bottom one
+    spawn_line(&mut commands, field_top / 2. - LABEL_FONT_SIZE / 2.)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle::from_section("",
+                    text_style(&asset_server, LABEL_FONT_SIZE, TEXT_COLOR)))
+                .insert(ClockText);
+        })
+        .insert(HudEntity);
+    spawn_line(&mut commands, field_bottom + field_top / 2. - LABEL_FONT_SIZE / 2.)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle::from_section("",
+                    text_style(&asset_server, LABEL_FONT_SIZE, TEXT_COLOR)))
+                .insert(RallyText);
+        })
+        .insert(HudEntity);
+
+    // Connection stats at the right end of the top wall, empty offline
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(field_top / 2. - LABEL_FONT_SIZE / 2.),
+                    right: Val::Px(20.0),
+                    ..default()
+                },
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle::from_section("",
+                    text_style(&asset_server, LABEL_FONT_SIZE, TEXT_COLOR)))
+                .insert(NetText);
+        })
+        .insert(HudEntity);
+
+    // Serve countdown in the middle of the screen, with the point banner
+    // above it
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle::from_section("POINT!",
+                    text_style(&asset_server, 60.0, TEXT_COLOR)))
+                .insert(Visibility { is_visible: false })
+                .insert(PointBanner);
+            parent
+                .spawn_bundle(TextBundle::from_section("",
+                    text_style(&asset_server, 100.0, TEXT_COLOR)))
+                .insert(Countdown);
+        })
+        .insert(HudEntity);
+}
+
+fn mark_hud_stale(mut hud: ResMut<HudState>) {
+    hud.stale = true;
+}
+
+fn get_rally_str(hud: &HudState) -> String {
+    format!("Rally: {}", hud.rally)
+}
+
+// Write every piece of text from the simulation
+fn refresh_hud(
+    mut restart_events: EventReader<RestartMatch>,
+    mut seek_events: EventReader<SeekReplay>,
+    mut hud: ResMut<HudState>,
+    sim: Res<PongSim>,
+    seats: Res<Seats>,
+    online: Res<Online>,
+    mut text_query: ParamSet<(
+        Query<(&mut Text, &SideName)>,
+        Query<(&mut Text, &ScoreText)>,
+        Query<(&mut Text, &GamesText)>,
+        Query<(&mut Text, &ServerMarker)>,
+        Query<&mut Text, With<RallyText>>,
+        Query<&mut Text, With<ClockText>>,
+    )>,
+) {
+    if restart_events.iter().count() + seek_events.iter().count() > 0 {
+        hud.rally = 0;
+        hud.stale = true;
+    }
+    if let Some(session) = online.peer() {
+        let rollbacks = session.stats().rollback.rollbacks;
+        if rollbacks != hud.rollbacks {
+            hud.rollbacks = rollbacks;
+            hud.stale = true;
+        }
+    }
+    if !hud.stale {
+        return;
+    }
+    hud.stale = false;
+    hud.clock = clock_seconds(&sim);
+
+    for (mut text, side) in &mut text_query.p0() {
+        text.sections[0].value = seats.setup.side_name(side.0).to_string();
+    }
+    for (mut text, side) in &mut text_query.p1() {
+        text.sections[0].value = sim.scoreboard.points(side.0).to_string();
+    }
+    for (mut text, side) in &mut text_query.p2() {
+        text.sections[0].value = get_games_str(&sim, side.0);
+    }
+    for (mut text, side) in &mut text_query.p3() {
+        text.sections[0].value = get_server_str(&sim, side.0).to_string();
+    }
+    for mut text in &mut text_query.p4() {
+        text.sections[0].value = get_rally_str(&hud);
+    }
+    for mut text in &mut text_query.p5() {
+        text.sections[0].value = get_clock_str(&sim);
+    }
+}
+
+// React to what happened in the simulation this frame
+fn update_hud(
+    mut sim_events: EventReader<SimEvent>,
+    mut hud: ResMut<HudState>,
+    sim: Res<PongSim>,
+    mut text_query: ParamSet<(
+        Query<(&mut Text, &ScoreText)>,
+        Query<(&mut Text, &GamesText)>,
+        Query<(&mut Text, &ServerMarker)>,
+        Query<&mut Text, With<RallyText>>,
+    )>,
+    mut banner_query: Query<&mut Visibility, With<PointBanner>>,
+) {
+    for event in sim_events.iter() {
+        match event {
+            SimEvent::Collision(collision) => {
+                if let Surface::Paddle(_) = collision.surface {
+                    hud.rally += 1;
+                    for mut text in &mut text_query.p3() {
+                        text.sections[0].value = get_rally_str(&hud);
+                    }
+                }
+            }
+            SimEvent::Served(_) => {
+                hud.rally = 0;
+                for mut text in &mut text_query.p3() {
+                    text.sections[0].value = get_rally_str(&hud);
+                }
+            }
+            SimEvent::PointScored(_) | SimEvent::GameWon(_) => {
+                for (mut text, side) in &mut text_query.p0() {
+                    text.sections[0].value = sim.scoreboard.points(side.0).to_string();
+                }
+                for (mut text, side) in &mut text_query.p1() {
+                    text.sections[0].value = get_games_str(&sim, side.0);
+                }
+                hud.banner_left = BANNER_TIME;
+                for mut visibility in &mut banner_query {
+                    visibility.is_visible = true;
+                }
+            }
+            SimEvent::ServeStarted(_) => {
+                for (mut text, side) in &mut text_query.p2() {
+                    text.sections[0].value = get_server_str(&sim, side.0).to_string();
+                }
+            }
+            SimEvent::MatchWon(_) => { }
+        }
+    }
+}
+
+fn hide_banner(
+    time: Res<Time>,
+    mut hud: ResMut<HudState>,
+    mut banner_query: Query<&mut Visibility, With<PointBanner>>,
+) {
+    if hud.banner_left <= 0.0 {
+        return;
+    }
+    hud.banner_left -= time.delta_seconds();
+    if hud.banner_left <= 0.0 {
+        for mut visibility in &mut banner_query {
+            visibility.is_visible = false;
+        }
+    }
+}
+
+// Only rewritten when the shown second changes
+fn update_clock(
+    mut hud: ResMut<HudState>,
+    sim: Res<PongSim>,
+    mut clock_query: Query<&mut Text, With<ClockText>>,
+) {
+    let seconds = clock_seconds(&sim);
+    if seconds == hud.clock {
+        return;
+    }
+    hud.clock = seconds;
+    for mut text in &mut clock_query {
+        text.sections[0].value = get_clock_str(&sim);
+    }
+}
+
+fn update_countdown(
+    mut hud: ResMut<HudState>,
+    sim: Res<PongSim>,
+    mut countdown_query: Query<&mut Text, With<Countdown>>,
+) {
+    let countdown = sim.countdown();
+    if countdown == hud.countdown {
+        return;
+    }
+    hud.countdown = countdown;
+    for mut text in &mut countdown_query {
+        text.sections[0].value = match countdown {
+            Some(seconds) => seconds.to_string(),
+            None => String::new(),
+        };
+    }
+}
+
+fn update_net_stats(
+    mut hud: ResMut<HudState>,
+    online: Res<Online>,
+    mut net_query: Query<&mut Text, With<NetText>>,
+) {
+    let net = match (online.peer(), online.server()) {
+        (Some(session), _) => get_net_str(&session.stats()),
+        (None, Some(client)) => get_match_server_str(client),
+        (None, None) => String::new(),
+    };
+    if net == hud.net {
+        return;
+    }
+    for mut text in &mut net_query {
+        text.sections[0].value = net.clone();
+    }
+    hud.net = net;
+}
+
+fn teardown_hud(
+    mut commands: Commands,
+    query: Query<Entity, With<HudEntity>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,15 @@
+//! Game logic that does not depend on Bevy, shared by the game and
+//! the headless tools.
+
+pub mod ai;
+pub mod collision;
+pub mod controller;
+pub mod net;
+pub mod physics;
+pub mod replay;
+pub mod rng;
+pub mod rollback;
+pub mod rules;
+pub mod server;
+pub mod settings;
+pub mod sim;
@@ -1,29 +1,56 @@
-use bevy::{
-    prelude::*,
-    sprite::{collide_aabb::{collide, Collision}, Rect},    
-    time::FixedTimestep, input::keyboard::KeyboardInput,    
-    ui::FocusPolicy,    
-};
+// Bevy system parameters are routinely "complex types", and many of them
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
 
-const SCREEN_WIDTH: f32 = 1000.0;
-const SCREEN_HEIGHT: f32 = 700.0;
-const BG_COLOR: Color = Color::rgb(0.20, 0.20, 0.10);
+use bevy::prelude::*;
+
+use pong::net::{LinkShim, NetConfig};
+use pong::settings::GameSettings;
 
 use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+mod bindings;
+mod controllers;
+mod controls;
 mod main_menu;
+mod match_state;
 mod game;
+mod game_over;
+mod hud;
+mod menu_nav;
+mod online;
 mod paused;
+mod replays;
+mod settings_menu;
+mod theme;
+mod widgets;
 
 use main_menu::MainMenuPlugin;
+use bindings::{Action, Bindings};
+use controllers::{GameMode, SeatKind, SeatSetup};
+use controls::ControlsPlugin;
 use game::InGamePlugin;
+use game_over::GameOverPlugin;
+use hud::HudPlugin;
+use menu_nav::MenuNavPlugin;
+use online::{OnlinePlugin, OnlineSetup};
 use paused::PausedPlugin;
+use replays::ReplaysPlugin;
+use settings_menu::{present_mode, window_mode, SettingsFile, SettingsMenuPlugin};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum GameState {
     MainMenu,
     InGame,
     Paused,
+    GameOver,
+    Controls,
+    Settings,
+    Replays,
+    Connecting,
 }
 
 fn main() {
@@ -31,45 +58,200 @@ fn main() {
     // this method needs to be inside main() method
     env::set_var("RUST_BACKTRACE", "1");
 
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    let settings = match GameSettings::load_or_default(args.config.as_deref()) {
+        Ok(settings) => settings,
+        Err(message) => {
+            eprintln!("Bad settings: {}", message);
+            process::exit(2);
+        }
+    };
+    // Changes made in the game go back to the file they came from
+    let settings_file = SettingsFile(args.config.clone().or_else(GameSettings::default_path));
+
+    // The command line can override the network settings for this run
+    let mut network = settings.network;
+    network.port = args.host.unwrap_or(network.port);
+    network.input_delay = args.input_delay.unwrap_or(network.input_delay);
+    if let Err(message) = network.validate().and_then(|_| args.shim.validate()) {
+        eprintln!("{}\n{}", message, USAGE);
+        process::exit(2);
+    }
+    let online_setup = OnlineSetup {
+        join: args.join.clone(),
+        server: args.server.clone(),
+        port: network.port,
+        token: new_token(),
+        config: NetConfig {
+            input_delay: network.input_delay,
+            max_prediction: network.max_prediction,
+            shim: args.shim,
+        },
+    };
+    // Straight to the connecting screen when hosting or joining from the
+    // command line
+    let first_state = if args.host.is_some() || args.join.is_some() || args.server.is_some() {
+        GameState::Connecting
+    } else {
+        GameState::MainMenu
+    };
+
     App::new()    
-        .add_state(GameState::MainMenu)
+        .add_state(first_state)
         .insert_resource(WindowDescriptor {
-            width: SCREEN_WIDTH,
-            height: SCREEN_HEIGHT,
+            width: settings.physics.arena.width,
+            height: settings.physics.arena.height,
             title: "Pong!".to_string(),            
             resizable: false,        
+            mode: window_mode(&settings),
+            present_mode: present_mode(&settings),
             ..default()
         })        
-        .insert_resource(ClearColor(BG_COLOR))     
+        .insert_resource(ClearColor(theme::palette(&settings.window).background))
+        .insert_resource(settings)
+        .insert_resource(settings_file)
+        .insert_resource(settings.difficulty)
+        .insert_resource(settings.physics)
+        .insert_resource(settings.rules)
+        .insert_resource(args.seats)
+        .insert_resource(online_setup)
+        .insert_resource(Bindings::load())     
         .add_plugins(DefaultPlugins)
         .add_startup_system(spawn_camera)
-        .add_plugin(MainMenuPlugin)         
-        .add_plugin(InGamePlugin)               
+        .add_plugin(MenuNavPlugin)
+        .add_plugin(MainMenuPlugin)
+        .add_plugin(ControlsPlugin)
+        .add_plugin(SettingsMenuPlugin)
+        .add_plugin(ReplaysPlugin)         
+        .add_plugin(OnlinePlugin)
+        .add_plugin(InGamePlugin)
+        .add_plugin(HudPlugin)               
         .add_plugin(PausedPlugin)           
+        .add_plugin(GameOverPlugin)
         .run();
 
     println!("Program finished.");
 }
 
+const USAGE: &str = "\
+Usage: pong [--config <path>] [--player <controller>] [--opponent <controller>]
+            [--host <port> | --join <address> | --server <address>]
+            [--input-delay <ticks>]
+
+  --config <path>   settings file (default: settings.toml in the user config directory)
+  --player, --opponent
+                    keyboard, gamepad, mouse, touch or computer
+  --host <port>     wait for someone to join an online match on this UDP port
+  --join <address>  join an online match, e.g. 192.168.1.20:7777; the main
+                    menu's Online button joins it again
+  --server <address>
+                    play on a match server, e.g. 192.168.1.20:7778, instead
+                    of with someone directly
+  --input-delay <ticks>
+                    hold online inputs back this many ticks (default 2)
+  --net-latency <ms>, --net-jitter <ms>, --net-loss <share>
+                    make the connection worse on purpose, for testing";
+
+struct Args {
+    config: Option<PathBuf>,
+    seats: SeatSetup,
+    host: Option<u16>,
+    join: Option<String>,
+    server: Option<String>,
+    input_delay: Option<usize>,
+    shim: LinkShim,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args {
+            config: None,
+            seats: SeatSetup::default(),
+            host: None,
+            join: None,
+            server: None,
+            input_delay: None,
+            shim: LinkShim::default(),
+        };
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "--config" => parsed.config = Some(PathBuf::from(value)),
+                "--player" => parsed.seats.player = value.parse::<SeatKind>()?,
+                "--opponent" => parsed.seats.opponent = value.parse::<SeatKind>()?,
+                "--host" => parsed.host = Some(parse_number(&flag, &value)?),
+                "--join" => parsed.join = Some(value),
+                "--server" => parsed.server = Some(value),
+                "--input-delay" => parsed.input_delay = Some(parse_number(&flag, &value)?),
+                "--net-latency" => parsed.shim.latency = Duration::from_millis(parse_number(&flag, &value)?),
+                "--net-jitter" => parsed.shim.jitter = Duration::from_millis(parse_number(&flag, &value)?),
+                "--net-loss" => parsed.shim.loss = parse_number(&flag, &value)?,
+                _ => return Err(format!("unknown option `{}`", flag)),
+            }
+        }
+        let ways = [parsed.host.is_some(), parsed.join.is_some(), parsed.server.is_some()];
+        if ways.into_iter().filter(|&way| way).count() > 1 {
+            return Err("only one of --host, --join and --server can be used".to_string());
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} needs a number (got `{}`)", flag, value))
+}
+
+// Tells this game apart from others on a match server, so it gets its seat
+// back if it drops out
+fn new_token() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or_default()
+        ^ process::id() as u64
+}
+
 fn spawn_camera(mut commands: Commands) {
     commands.spawn_bundle(Camera2dBundle::default());
 }
 
 fn pause_game(
     mut keyboard_input: ResMut<Input<KeyCode>>,    
+    bindings: Res<Bindings>,
+    mut gamepad_input: ResMut<Input<GamepadButton>>,
+    mode: Res<GameMode>,
     mut state: ResMut<State<GameState>>
 ) {    
-    if keyboard_input.pressed(KeyCode::Space) {
+    // Start on any gamepad works too
+    let start = gamepad_input.get_just_pressed()
+        .find(|button| button.button_type == GamepadButtonType::Start)
+        .copied();
+
+    if bindings.pressed(&keyboard_input, Action::Pause) || start.is_some() {
         match state.current() {
-            GameState::MainMenu => { /* ... */}
+            GameState::MainMenu | GameState::GameOver
+            | GameState::Controls | GameState::Settings
+            | GameState::Replays | GameState::Connecting => { /* ... */}
+            // The other side wouldn't stop, so online matches don't either
+            GameState::InGame if *mode == GameMode::Online => { }
+            // Resume, or losing focus, may have queued a transition this
+            // frame already; that one wins
             GameState::InGame => {
-                state.push(GameState::Paused).unwrap();
-                keyboard_input.reset(KeyCode::Space);
+                let _ = state.push(GameState::Paused);
             }
             GameState::Paused => {
-                state.pop().unwrap();
-                keyboard_input.reset(KeyCode::Space);
+                let _ = state.pop();
             }
         }
+        keyboard_input.reset(bindings.key(Action::Pause));
+        if let Some(start) = start {
+            gamepad_input.reset(start);
+        }
     }
 }
\ No newline at end of file
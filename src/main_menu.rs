@@ -1,18 +1,50 @@
 use bevy::{
     prelude::*,
     app::AppExit,
+    ecs::system::EntityCommands,
 };
 
 
+use pong::ai::Difficulty;
+use pong::settings::GameSettings;
+
 use crate::GameState;
+use crate::controllers::{GameMode, SeatSetup};
+use crate::menu_nav::MenuFocus;
+use crate::online::OnlineSetup;
+use crate::settings_menu::SettingsFile;
 
 pub struct MainMenuPlugin;
 
 const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
 
+#[derive(Component)]
+struct MainMenuEntity;
+
 #[derive(Component)]
 struct StartGameButton;
 
+#[derive(Component)]
+struct VersusButton;
+
+#[derive(Component)]
+struct OnlineButton;
+
+#[derive(Component)]
+struct DifficultyButton;
+
+#[derive(Component)]
+struct InputButton;
+
+#[derive(Component)]
+struct ControlsButton;
+
+#[derive(Component)]
+struct SettingsButton;
+
+#[derive(Component)]
+struct ReplaysButton;
+
 #[derive(Component)]
 struct QuitButton;
 
@@ -20,14 +52,22 @@ impl Plugin for MainMenuPlugin {
     fn build(&self, app: &mut App) {
         println!{"Building main menu!"};
         app
+        // Hover and click colours for every menu's buttons
+        .add_system(button_system)
         .add_system_set(
             SystemSet::on_enter(GameState::MainMenu)
                 .with_system(setup_menu)
         )
         .add_system_set(
             SystemSet::on_update(GameState::MainMenu)
-            .with_system(button_system)
             .with_system(start_button_sys)
+            .with_system(versus_button_sys)
+            .with_system(online_button_sys)
+            .with_system(difficulty_button_sys)
+            .with_system(input_button_sys)
+            .with_system(controls_button_sys)
+            .with_system(settings_button_sys)
+            .with_system(replays_button_sys)
             .with_system(quit_button_sys)                    
         )
         .add_system_set(
@@ -39,30 +79,34 @@ impl Plugin for MainMenuPlugin {
 
 fn teardown_main_menu(
     mut commands: Commands,
-    query: Query<Entity, With<Button>>
+    query: Query<Entity, With<MainMenuEntity>>
 ) {
     for entity in query.iter() {
         commands.entity(entity).despawn_recursive();
     }
 }
 
+// The focused button, from the mouse, keyboard or a gamepad, shows the
+// hover colour
 fn button_system(
     mut interaction_query: Query<
-    (&Interaction, &mut UiColor, &Children),
-    (Changed<Interaction>, With<Button>),
+    (Entity, &Interaction, ChangeTrackers<Interaction>, &mut UiColor),
+    With<Button>,
     >,
-    mut text_query: Query<&mut Text>
+    focus: Res<MenuFocus>,
 ) {
-    for (interaction, mut color, children) in &mut interaction_query {
-        let mut text = text_query.get_mut(children[0]).unwrap();
+    for (entity, interaction, tracker, mut color) in &mut interaction_query {
+        if !tracker.is_changed() && !focus.is_changed() {
+            continue;
+        }
         match *interaction {
             Interaction::Clicked => {                
                 *color = Color::rgb(0.9,0.9,0.1).into();
             }
-            Interaction::Hovered => {                
+            _ if focus.0 == Some(entity) => {                
                 *color = Color::rgb(0.9,0.1,0.1).into();
             }
-            Interaction::None => {                
+            _ => {                
                 *color = NORMAL_BUTTON.into();
             }
         }
@@ -72,11 +116,13 @@ fn button_system(
 fn start_button_sys(
     mut interaction_query: Query<
     &Interaction, With<StartGameButton>>,
-    mut state: ResMut<State<GameState>>
+    mut state: ResMut<State<GameState>>,
+    mut mode: ResMut<GameMode>,
 ) {
     for interaction in &mut interaction_query {
         match *interaction {
             Interaction::Clicked    => {                
+                *mode = GameMode::Solo;
                 state.set(GameState::InGame).expect("Failed to enter game");                
             }
             Interaction::Hovered    => { }
@@ -85,6 +131,118 @@ fn start_button_sys(
     }
 }
 
+// Two players on one keyboard
+fn versus_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<VersusButton>)>,
+    mut state: ResMut<State<GameState>>,
+    mut mode: ResMut<GameMode>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            *mode = GameMode::Versus;
+            state.set(GameState::InGame).expect("Failed to enter game");
+        }
+    }
+}
+
+fn get_online_str(online_setup: &OnlineSetup) -> &'static str {
+    match (&online_setup.server, &online_setup.join) {
+        (Some(_), _) => "Join Server",
+        (None, Some(_)) => "Join Online",
+        (None, None) => "Host Online",
+    }
+}
+
+// Against someone on another machine, hosting, joining whoever the
+// command line named or playing on its match server
+fn online_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<OnlineButton>)>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            state.set(GameState::Connecting).expect("Failed to go online");
+        }
+    }
+}
+
+fn get_difficulty_str(difficulty: &Difficulty) -> String {
+    format!("AI: {}", difficulty.name())
+}
+
+// Step through the AI presets, one per click
+fn difficulty_button_sys(
+    interaction_query: Query<
+        (&Interaction, &Children), (Changed<Interaction>, With<DifficultyButton>)>,
+    mut text_query: Query<&mut Text>,
+    mut difficulty: ResMut<Difficulty>,
+    mut settings: ResMut<GameSettings>,
+    settings_file: Res<SettingsFile>,
+) {
+    for (interaction, children) in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            *difficulty = difficulty.next();
+            settings.difficulty = *difficulty;
+            settings_file.save(&settings);
+            let mut text = text_query.get_mut(children[0]).unwrap();
+            text.sections[0].value = get_difficulty_str(&difficulty);
+        }
+    }
+}
+
+fn get_input_str(seat_setup: &SeatSetup) -> String {
+    format!("Input: {}", seat_setup.player.name())
+}
+
+// Step through keyboard, mouse or touch, and gamepad for single player
+fn input_button_sys(
+    interaction_query: Query<
+        (&Interaction, &Children), (Changed<Interaction>, With<InputButton>)>,
+    mut text_query: Query<&mut Text>,
+    mut seat_setup: ResMut<SeatSetup>,
+) {
+    for (interaction, children) in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            seat_setup.player = seat_setup.player.next_human();
+            let mut text = text_query.get_mut(children[0]).unwrap();
+            text.sections[0].value = get_input_str(&seat_setup);
+        }
+    }
+}
+
+fn controls_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<ControlsButton>)>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            state.set(GameState::Controls).expect("Failed to open controls");
+        }
+    }
+}
+
+fn settings_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<SettingsButton>)>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            state.set(GameState::Settings).expect("Failed to open settings");
+        }
+    }
+}
+
+fn replays_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<ReplaysButton>)>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            state.set(GameState::Replays).expect("Failed to open replays");
+        }
+    }
+}
+
 fn quit_button_sys(
     mut interaction_query: Query<
         &Interaction, With<QuitButton>>,
@@ -102,16 +260,28 @@ fn quit_button_sys(
 }
 
 
-fn setup_menu(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>
-) {    
-    commands                
+// Spawn a menu button with a text label, styled like the main menu
+pub fn spawn_button<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    asset_server: &AssetServer,
+    label: &str,
+) -> EntityCommands<'w, 's, 'a> {
+    spawn_sized_button(commands, asset_server, label, Size::new(Val::Px(300.0), Val::Px(65.0)), 40.0)
+}
+
+// A menu button with a different size or font size
+pub fn spawn_sized_button<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    asset_server: &AssetServer,
+    label: &str,
+    size: Size<Val>,
+    font_size: f32,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut button = commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(200.0), Val::Px(65.0)),
-                // center button
-                margin: UiRect::all(Val::Auto),
+                size,
+                margin: UiRect::all(Val::Px(10.0)),
                 // horizontally center child text
                 justify_content: JustifyContent::Center,
                 // vertically center child text
@@ -120,44 +290,94 @@ fn setup_menu(
             },
             color: NORMAL_BUTTON.into(),
             ..default()
-        })        
-        .with_children(|parent| {
+        });
+    button.with_children(|parent| {
             parent.spawn_bundle(TextBundle::from_section(
-                "Start Game",
+                label,
                 TextStyle {
                     font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                    font_size: 40.0,
+                    font_size,
                     color: Color::rgb(0.9, 0.9, 0.9),
                 },
             ));
-        })
-        .insert(StartGameButton);
+        });
+    button
+}
 
-        // Quit Button
-        commands                
-        .spawn_bundle(ButtonBundle {
-            style: Style {
-                size: Size::new(Val::Px(200.0), Val::Px(65.0)),
-                // center button
-                margin: UiRect::all(Val::Auto),
-                // horizontally center child text
-                justify_content: JustifyContent::Center,
-                // vertically center child text
-                align_items: AlignItems::Center,
-                ..default()
-            },
-            color: NORMAL_BUTTON.into(),
+// Full-screen node that stacks its children down the middle of the screen
+pub fn spawn_menu_column<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+) -> EntityCommands<'w, 's, 'a> {
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::ColumnReverse,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
             ..default()
-        })        
-        .with_children(|parent| {
-            parent.spawn_bundle(TextBundle::from_section(
-                "Quit Game",
-                TextStyle {
-                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                    font_size: 40.0,
-                    color: Color::rgb(0.9, 0.9, 0.9),
-                },
-            ));
-        })
-        .insert(QuitButton);
+        },
+        color: Color::NONE.into(),
+        ..default()
+    })
+}
+
+// A little smaller than the other menus' buttons, so they all fit
+fn spawn_main_button<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    asset_server: &AssetServer,
+    label: &str,
+) -> EntityCommands<'w, 's, 'a> {
+    spawn_sized_button(commands, asset_server, label, Size::new(Val::Px(300.0), Val::Px(55.0)), 36.0)
+}
+
+fn setup_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    difficulty: Res<Difficulty>,
+    seat_setup: Res<SeatSetup>,
+    online_setup: Res<OnlineSetup>,
+) {    
+    let start = spawn_main_button(&mut commands, &asset_server, "Start Game")
+        .insert(StartGameButton)
+        .id();
+
+    let versus = spawn_main_button(&mut commands, &asset_server, "2 Player Versus")
+        .insert(VersusButton)
+        .id();
+
+    let online = spawn_main_button(&mut commands, &asset_server, get_online_str(&online_setup))
+        .insert(OnlineButton)
+        .id();
+
+    let difficulty = spawn_main_button(&mut commands, &asset_server,
+        &get_difficulty_str(&difficulty))
+        .insert(DifficultyButton)
+        .id();
+
+    let input = spawn_main_button(&mut commands, &asset_server,
+        &get_input_str(&seat_setup))
+        .insert(InputButton)
+        .id();
+
+    let controls = spawn_main_button(&mut commands, &asset_server, "Controls")
+        .insert(ControlsButton)
+        .id();
+
+    let settings = spawn_main_button(&mut commands, &asset_server, "Settings")
+        .insert(SettingsButton)
+        .id();
+
+    let replays = spawn_main_button(&mut commands, &asset_server, "Replays")
+        .insert(ReplaysButton)
+        .id();
+
+    // Quit Button
+    let quit = spawn_main_button(&mut commands, &asset_server, "Quit Game")
+        .insert(QuitButton)
+        .id();
+
+    spawn_menu_column(&mut commands)
+        .push_children(&[start, versus, online, difficulty, input, controls, settings, replays, quit])
+        .insert(MainMenuEntity);
 }
\ No newline at end of file
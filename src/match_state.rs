@@ -0,0 +1,97 @@
+use pong::sim::{Paddle, PongSim, Scoreboard};
+
+use crate::controllers::{GameMode, SeatSetup};
+
+// Where the current match is in its life. Each match goes through these
+// in order, and a new one starts from `Created` again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchPhase {
+    // Simulation and seats ready, nothing on screen yet
+    #[default]
+    Created,
+    // On the field, paused or not
+    Running,
+    // Someone has won and the results are up
+    Finished,
+    // Cleared away. A finished match's result lives on in `LastMatch`.
+    Archived,
+}
+
+// How a finished match turned out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchResult {
+    pub winner: Paddle,
+    pub scoreboard: Scoreboard,
+    pub best_of: usize,
+    // Match time in seconds
+    pub elapsed: f32,
+    pub mode: GameMode,
+    pub setup: SeatSetup,
+}
+
+impl MatchResult {
+    fn of(sim: &PongSim, winner: Paddle, mode: GameMode, setup: SeatSetup) -> MatchResult {
+        MatchResult {
+            winner,
+            scoreboard: sim.scoreboard,
+            best_of: sim.rules.best_of,
+            elapsed: sim.elapsed(),
+            mode,
+            setup,
+        }
+    }
+}
+
+// The match being played, from setting up the field to clearing it away
+#[derive(Debug, Default)]
+pub struct Match {
+    pub phase: MatchPhase,
+    pub mode: GameMode,
+    pub setup: SeatSetup,
+    // Set once the match is finished
+    pub result: Option<MatchResult>,
+}
+
+// The most recent match to finish, kept after it is archived
+#[derive(Default)]
+pub struct LastMatch(pub Option<MatchResult>);
+
+impl Match {
+    pub fn new(mode: GameMode, setup: SeatSetup) -> Match {
+        Match {
+            phase: MatchPhase::Created,
+            mode,
+            setup,
+            result: None,
+        }
+    }
+
+    pub fn start(&mut self) {
+        if self.phase == MatchPhase::Created {
+            self.phase = MatchPhase::Running;
+        }
+    }
+
+    pub fn finish(&mut self, sim: &PongSim) {
+        if let (MatchPhase::Running, Some(winner)) = (self.phase, sim.winner()) {
+            self.phase = MatchPhase::Finished;
+            self.result = Some(MatchResult::of(sim, winner, self.mode, self.setup));
+        }
+    }
+
+    // A win by forfeit, whatever the score stood at
+    pub fn award(&mut self, sim: &PongSim, winner: Paddle) {
+        if self.phase == MatchPhase::Running {
+            self.phase = MatchPhase::Finished;
+            self.result = Some(MatchResult::of(sim, winner, self.mode, self.setup));
+        }
+    }
+
+    // Done with the match, whether it was finished or abandoned
+    pub fn archive(&mut self, last: &mut LastMatch) {
+        if let Some(result) = self.result {
+            last.0 = Some(result);
+        }
+        self.phase = MatchPhase::Archived;
+    }
+}
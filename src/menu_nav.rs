@@ -0,0 +1,126 @@
+use bevy::{prelude::*, input::InputSystem, ui::UiSystem};
+
+use crate::bindings::{Action, Bindings};
+use crate::controls::Rebinding;
+
+pub struct MenuNavPlugin;
+
+// The button picked out by the keyboard or a gamepad, if any. Hovering over
+// a button with the mouse picks it too, so only one is ever highlighted.
+#[derive(Default)]
+pub struct MenuFocus(pub Option<Entity>);
+
+impl Plugin for MenuNavPlugin {
+    fn build(&self, app: &mut App) {
+        println!("Building menu navigation!");
+        app
+        .init_resource::<MenuFocus>()
+        // After the mouse has had its say, so a key press that clicks a
+        // button is seen by the button systems in the same frame
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            navigate_menu_sys.after(InputSystem).after(UiSystem::Focus),
+        );
+    }
+}
+
+// Escape (or whatever Back is bound to) or B on any gamepad
+pub fn back_pressed(
+    keys: &Input<KeyCode>,
+    bindings: &Bindings,
+    buttons: &Input<GamepadButton>,
+) -> bool {
+    bindings.just_pressed(keys, Action::Back)
+        || buttons.get_just_pressed().any(|button| button.button_type == GamepadButtonType::East)
+}
+
+fn gamepad_just_pressed(buttons: &Input<GamepadButton>, button_type: GamepadButtonType) -> bool {
+    buttons.get_just_pressed().any(|button| button.button_type == button_type)
+}
+
+// Arrow keys or the D-pad move the focus through the buttons on screen in
+// reading order, wrapping round at either end. Enter or A clicks the
+// focused button until the next frame.
+fn navigate_menu_sys(
+    mut focus: ResMut<MenuFocus>,
+    mut clicked: Local<Option<Entity>>,
+    mut keys: ResMut<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    rebinding: Res<Rebinding>,
+    mut button_query: Query<
+        (Entity, &mut Interaction, &GlobalTransform, &ComputedVisibility),
+        With<Button>,
+    >,
+) {
+    let released = clicked.take();
+    if let Some(entity) = released {
+        if let Ok((_, mut interaction, _, _)) = button_query.get_mut(entity) {
+            *interaction = Interaction::None;
+        }
+    }
+
+    let mut buttons: Vec<(Entity, Vec3)> = button_query
+        .iter()
+        .filter(|(_, _, _, visibility)| visibility.is_visible())
+        .map(|(entity, _, transform, _)| (entity, transform.translation()))
+        .collect();
+    // UI y runs up the screen, so the top row comes first
+    buttons.sort_by(|(_, a), (_, b)| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+
+    // Follow the mouse, and let go when it moves off the focused button
+    for (entity, interaction, _, _) in &mut button_query {
+        if !interaction.is_changed() || released == Some(entity) {
+            continue;
+        }
+        match *interaction {
+            Interaction::Hovered => focus.0 = Some(entity),
+            Interaction::None if focus.0 == Some(entity) => focus.0 = None,
+            _ => {}
+        }
+    }
+    if focus.0.is_some_and(|entity| !buttons.iter().any(|(button, _)| *button == entity)) {
+        focus.0 = None;
+    }
+
+    // The controls screen wants the next key for itself
+    if rebinding.0.is_some() || buttons.is_empty() {
+        return;
+    }
+
+    let gamepad_buttons = &*gamepad_buttons;
+    let pressed = |keys: &Input<KeyCode>, key: KeyCode, button_type: GamepadButtonType| {
+        keys.just_pressed(key) || gamepad_just_pressed(gamepad_buttons, button_type)
+    };
+    let step = if pressed(&keys, KeyCode::Up, GamepadButtonType::DPadUp)
+        || pressed(&keys, KeyCode::Left, GamepadButtonType::DPadLeft)
+    {
+        -1
+    } else if pressed(&keys, KeyCode::Down, GamepadButtonType::DPadDown)
+        || pressed(&keys, KeyCode::Right, GamepadButtonType::DPadRight)
+    {
+        1
+    } else {
+        0
+    };
+
+    if step != 0 {
+        let count = buttons.len() as i32;
+        let next = match focus.0.and_then(|entity| buttons.iter().position(|(button, _)| *button == entity)) {
+            Some(index) => (index as i32 + step).rem_euclid(count) as usize,
+            // Nothing focused yet: start from the first button
+            None => 0,
+        };
+        focus.0 = Some(buttons[next].0);
+    }
+
+    if pressed(&keys, KeyCode::Return, GamepadButtonType::South) {
+        if let Some(entity) = focus.0 {
+            if let Ok((_, mut interaction, _, _)) = button_query.get_mut(entity) {
+                *interaction = Interaction::Clicked;
+                *clicked = Some(entity);
+                // Or the controls screen would take it as the new key
+                keys.reset(KeyCode::Return);
+            }
+        }
+    }
+}
@@ -0,0 +1,687 @@
+//! Online play between two machines over UDP, peer to peer. One side
+//! hosts and plays the left paddle, the other joins it and plays the
+//! right. The host's seed, physics and rules are sent over when the
+//! joining side says hello, and from then on each side sends its inputs
+//! and [`RollbackSession`] keeps the two matches in step.
+//!
+//! Inputs are sent again in every packet until the other side says it has
+//! them, so lost packets only cost time. [`LinkShim`] can add latency,
+//! jitter and loss to everything sent, to try bad connections out on one
+//! machine.
+//!
+//! Every packet is the magic bytes `PONG`, a message tag and the message,
+//! little-endian like replays.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::physics::PhysicsConfig;
+use crate::replay::{seed_bits, Reader};
+use crate::rng::SimRng;
+use crate::rollback::{ConfirmedTick, RollbackSession, RollbackStats};
+use crate::rules::MatchRules;
+use crate::sim::{Paddle, PaddleInput, PongSim, SimEvent};
+
+/// Both sides must speak the same version.
+pub const NET_VERSION: u32 = 1;
+
+/// Port hosts listen on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 7777;
+
+const MAGIC: &[u8; 4] = b"PONG";
+
+// Most inputs sent in one packet. More than this unacknowledged and the
+// oldest go first; the rest follow in later packets.
+const MAX_INPUTS_PER_PACKET: usize = 64;
+
+const HELLO_INTERVAL: Duration = Duration::from_millis(250);
+const PING_INTERVAL: Duration = Duration::from_millis(500);
+// Joining gives up after this long without an answer, long enough for the
+// host to come back to the connecting screen for a rematch
+const JOIN_TIMEOUT: Duration = Duration::from_secs(30);
+// A match ends after this long without hearing from the other side
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Artificial trouble for everything sent, to test with on loopback.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinkShim {
+    /// Added to every packet.
+    pub latency: Duration,
+    /// Up to this much more is added at random, so packets can arrive out
+    /// of order.
+    pub jitter: Duration,
+    /// Share of packets dropped, from 0 to 1.
+    pub loss: f32,
+}
+
+impl LinkShim {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.loss) {
+            return Err(format!("loss must be from 0 to 1 (got {})", self.loss));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NetConfig {
+    /// Ticks local inputs are held back. More delay means fewer rollbacks
+    /// but controls that feel slower.
+    pub input_delay: usize,
+    /// Ticks that may be played ahead of the other side's inputs.
+    pub max_prediction: usize,
+    pub shim: LinkShim,
+}
+
+/// How the connection is doing, for the HUD.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetStats {
+    /// Round trip time, smoothed. None until the first ping comes back.
+    pub ping: Option<Duration>,
+    pub rollback: RollbackStats,
+    pub input_delay: usize,
+}
+
+// What the host sends a joining side to start the match with. The match
+// server sends the same.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct MatchSetup {
+    #[serde(with = "seed_bits")]
+    pub(crate) seed: u64,
+    pub(crate) physics: PhysicsConfig,
+    pub(crate) rules: MatchRules,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Message {
+    Hello { version: u32 },
+    Welcome { setup: String },
+    Reject { reason: String },
+    Inputs {
+        first: u32,
+        inputs: Vec<PaddleInput>,
+        // Inputs received from the other side so far
+        ack: u32,
+        // Latest confirmed tick and its hash, or tick 0 before there is one
+        confirmed: u32,
+        hash: u32,
+    },
+    Ping { id: u32 },
+    Pong { id: u32 },
+    Bye,
+}
+
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        match self {
+            Message::Hello { version } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&version.to_le_bytes());
+            }
+            Message::Welcome { setup } => {
+                bytes.push(2);
+                write_str(&mut bytes, setup);
+            }
+            Message::Reject { reason } => {
+                bytes.push(3);
+                write_str(&mut bytes, reason);
+            }
+            Message::Inputs { first, inputs, ack, confirmed, hash } => {
+                bytes.push(4);
+                bytes.extend_from_slice(&first.to_le_bytes());
+                bytes.push(inputs.len() as u8);
+                for input in inputs {
+                    bytes.extend_from_slice(&input.direction.to_bits().to_le_bytes());
+                    bytes.push(input.serve as u8);
+                }
+                bytes.extend_from_slice(&ack.to_le_bytes());
+                bytes.extend_from_slice(&confirmed.to_le_bytes());
+                bytes.extend_from_slice(&hash.to_le_bytes());
+            }
+            Message::Ping { id } => {
+                bytes.push(5);
+                bytes.extend_from_slice(&id.to_le_bytes());
+            }
+            Message::Pong { id } => {
+                bytes.push(6);
+                bytes.extend_from_slice(&id.to_le_bytes());
+            }
+            Message::Bye => bytes.push(7),
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Message, String> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("not a pong packet".to_string());
+        }
+        let message = match reader.u8()? {
+            1 => Message::Hello { version: reader.u32()? },
+            2 => Message::Welcome { setup: read_str(&mut reader)? },
+            3 => Message::Reject { reason: read_str(&mut reader)? },
+            4 => {
+                let first = reader.u32()?;
+                let mut inputs = Vec::new();
+                for _ in 0..reader.u8()? {
+                    // Anything else would end up in the match's replay too
+                    let direction = reader.direction()?;
+                    inputs.push(PaddleInput { direction, serve: reader.u8()? != 0 });
+                }
+                Message::Inputs {
+                    first,
+                    inputs,
+                    ack: reader.u32()?,
+                    confirmed: reader.u32()?,
+                    hash: reader.u32()?,
+                }
+            }
+            5 => Message::Ping { id: reader.u32()? },
+            6 => Message::Pong { id: reader.u32()? },
+            7 => Message::Bye,
+            tag => return Err(format!("unknown message {}", tag)),
+        };
+        if !reader.bytes.is_empty() {
+            return Err("packet has trailing bytes".to_string());
+        }
+        Ok(message)
+    }
+}
+
+pub(crate) fn write_str(bytes: &mut Vec<u8>, text: &str) {
+    bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+pub(crate) fn read_str(reader: &mut Reader) -> Result<String, String> {
+    let len = reader.u32()? as usize;
+    String::from_utf8(reader.take(len)?.to_vec()).map_err(|_| "text is not UTF-8".to_string())
+}
+
+// Messages that have arrived. Anything that isn't one of ours is ignored.
+fn receive(link: &mut Link) -> Vec<(SocketAddr, Message)> {
+    link.poll()
+        .into_iter()
+        .filter_map(|(from, bytes)| Message::decode(&bytes).ok().map(|message| (from, message)))
+        .collect()
+}
+
+// A non-blocking socket with the shim in front of it. The match server
+// uses it too, with messages of its own.
+pub(crate) struct Link {
+    socket: UdpSocket,
+    shim: LinkShim,
+    rng: SimRng,
+    // Packets the shim is holding back, with when to send them
+    delayed: Vec<(Instant, SocketAddr, Vec<u8>)>,
+}
+
+impl Link {
+    pub(crate) fn bind(address: SocketAddr, shim: LinkShim, seed: u64) -> io::Result<Link> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Link { socket, shim, rng: SimRng::new(seed), delayed: Vec::new() })
+    }
+
+    pub(crate) fn send(&mut self, to: SocketAddr, bytes: Vec<u8>) {
+        if self.shim.loss > 0.0 && self.rng.next_f32() < self.shim.loss {
+            return;
+        }
+        let jitter = self.shim.jitter.mul_f32(self.rng.next_f32());
+        let delay = self.shim.latency + jitter;
+        if delay.is_zero() {
+            // Nothing to be done about a full buffer; the input is sent again
+            let _ = self.socket.send_to(&bytes, to);
+        } else {
+            self.delayed.push((Instant::now() + delay, to, bytes));
+        }
+    }
+
+    pub(crate) fn local_address(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    // Sent straight past the shim, for when nothing will be flushing it
+    pub(crate) fn send_now(&self, to: SocketAddr, bytes: &[u8]) {
+        let _ = self.socket.send_to(bytes, to);
+    }
+
+    // Send what the shim has held back long enough
+    pub(crate) fn flush(&mut self) {
+        let now = Instant::now();
+        let socket = &self.socket;
+        self.delayed.retain(|(due, to, bytes)| {
+            if *due > now {
+                return true;
+            }
+            let _ = socket.send_to(bytes, *to);
+            false
+        });
+    }
+
+    // Every packet that has arrived
+    pub(crate) fn poll(&mut self) -> Vec<(SocketAddr, Vec<u8>)> {
+        self.flush();
+        let mut received = Vec::new();
+        let mut buffer = [0; 2048];
+        // Stops at errors too, including those from packets to a peer that
+        // has gone, which the timeout deals with
+        while let Ok((len, from)) = self.socket.recv_from(&mut buffer) {
+            received.push((from, buffer[..len].to_vec()));
+        }
+        received
+    }
+}
+
+enum Role {
+    Host { setup: MatchSetup },
+    Join { peer: SocketAddr, physics: PhysicsConfig, last_hello: Option<Instant> },
+}
+
+/// Waiting for the other side to turn up.
+pub struct Connecting {
+    link: Link,
+    role: Role,
+    config: NetConfig,
+    started: Instant,
+}
+
+/// How connecting is going, each time it is polled.
+pub enum Progress {
+    Waiting(Box<Connecting>),
+    Connected(Box<NetSession>),
+}
+
+impl Connecting {
+    /// Wait for someone to join on `port`. They play the match with this
+    /// seed, physics and rules.
+    pub fn host(
+        port: u16,
+        seed: u64,
+        physics: PhysicsConfig,
+        rules: MatchRules,
+        config: NetConfig,
+    ) -> io::Result<Connecting> {
+        let link = Link::bind(SocketAddr::from(([0, 0, 0, 0], port)), config.shim, seed)?;
+        Ok(Connecting {
+            link,
+            role: Role::Host { setup: MatchSetup { seed, physics, rules } },
+            config,
+            started: Instant::now(),
+        })
+    }
+
+    /// Join the host at `address`, such as `192.168.1.20:7777`. Its arena
+    /// must be the same size as `physics`' so the match fits the window.
+    pub fn join(address: &str, physics: PhysicsConfig, config: NetConfig) -> io::Result<Connecting> {
+        let peer = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address not found"))?;
+        let local = if peer.is_ipv4() { SocketAddr::from(([0, 0, 0, 0], 0)) }
+            else { SocketAddr::from(([0u16; 8], 0)) };
+        let link = Link::bind(local, config.shim, peer.port() as u64)?;
+        Ok(Connecting {
+            link,
+            role: Role::Join { peer, physics, last_hello: None },
+            config,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn is_host(&self) -> bool {
+        matches!(self.role, Role::Host { .. })
+    }
+
+    /// Say hello again if it's time, and see what came back. An error if
+    /// the match can't go ahead.
+    pub fn poll(mut self) -> Result<Progress, String> {
+        let received = receive(&mut self.link);
+        match &mut self.role {
+            Role::Host { setup } => {
+                for (from, message) in received {
+                    if let Message::Hello { version } = message {
+                        if version != NET_VERSION {
+                            let reason = format!("host speaks version {}, not {}", NET_VERSION, version);
+                            self.link.send(from, Message::Reject { reason }.encode());
+                            continue;
+                        }
+                        let welcome = Message::Welcome {
+                            setup: toml::to_string(setup).expect("match setup is always valid TOML"),
+                        };
+                        self.link.send(from, welcome.encode());
+                        let sim = PongSim::new(setup.physics, setup.rules, setup.seed);
+                        let seed = setup.seed;
+                        return Ok(Progress::Connected(Box::new(NetSession::new(
+                            self.link, from, Paddle::Player, sim, seed, self.config, Some(welcome)))));
+                    }
+                }
+            }
+            Role::Join { peer, physics, last_hello } => {
+                for (from, message) in received {
+                    if from != *peer {
+                        continue;
+                    }
+                    match message {
+                        Message::Welcome { setup } => {
+                            let setup: MatchSetup = toml::from_str(&setup)
+                                .map_err(|error| format!("bad match setup from host: {}", error))?;
+                            setup.physics.validate().map_err(|error| format!("physics.{}", error))?;
+                            setup.rules.validate().map_err(|error| format!("rules.{}", error))?;
+                            if setup.physics.arena != physics.arena {
+                                return Err("the host's arena is a different size".to_string());
+                            }
+                            let sim = PongSim::new(setup.physics, setup.rules, setup.seed);
+                            let peer = *peer;
+                            return Ok(Progress::Connected(Box::new(NetSession::new(
+                                self.link, peer, Paddle::Opponent, sim, setup.seed, self.config, None))));
+                        }
+                        Message::Reject { reason } => return Err(reason),
+                        _ => { }
+                    }
+                }
+                if self.started.elapsed() > JOIN_TIMEOUT {
+                    return Err(format!("no answer from {}", peer));
+                }
+                if last_hello.is_none_or(|sent| sent.elapsed() >= HELLO_INTERVAL) {
+                    *last_hello = Some(Instant::now());
+                    let peer = *peer;
+                    self.link.send(peer, Message::Hello { version: NET_VERSION }.encode());
+                }
+            }
+        }
+        Ok(Progress::Waiting(Box::new(self)))
+    }
+}
+
+/// A match under way with the other side.
+pub struct NetSession {
+    link: Link,
+    peer: SocketAddr,
+    session: RollbackSession,
+    /// The match seed, for recording it.
+    pub seed: u64,
+    // The host's answer to hello, sent again if the joining side didn't
+    // get it
+    welcome: Option<Message>,
+    // The other side has every local input before this
+    acked: usize,
+    last_heard: Instant,
+    next_ping: u32,
+    ping_sent: Option<(u32, Instant)>,
+    last_ping: Instant,
+    ping: Option<Duration>,
+    closed: Option<String>,
+}
+
+impl NetSession {
+    fn new(
+        link: Link,
+        peer: SocketAddr,
+        side: Paddle,
+        sim: PongSim,
+        seed: u64,
+        config: NetConfig,
+        welcome: Option<Message>,
+    ) -> NetSession {
+        NetSession {
+            link,
+            peer,
+            session: RollbackSession::new(sim, side, config.input_delay, config.max_prediction),
+            seed,
+            welcome,
+            acked: 0,
+            last_heard: Instant::now(),
+            next_ping: 0,
+            ping_sent: None,
+            last_ping: Instant::now(),
+            ping: None,
+            closed: None,
+        }
+    }
+
+    pub fn local_side(&self) -> Paddle {
+        self.session.local_side()
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// The simulation to show.
+    pub fn sim(&self) -> &PongSim {
+        self.session.sim()
+    }
+
+    /// The simulation as far as both sides agree on it. Only this can be
+    /// trusted to say who won.
+    pub fn confirmed(&self) -> &PongSim {
+        self.session.confirmed()
+    }
+
+    pub fn take_confirmed(&mut self) -> Vec<ConfirmedTick> {
+        self.session.take_confirmed()
+    }
+
+    pub fn stats(&self) -> NetStats {
+        NetStats {
+            ping: self.ping,
+            rollback: self.session.stats(),
+            input_delay: self.session.input_delay(),
+        }
+    }
+
+    /// Why the match is over, if the other side left or went quiet.
+    pub fn closed(&self) -> Option<&str> {
+        self.closed.as_deref()
+    }
+
+    /// Play the next tick; see [`RollbackSession::step`].
+    pub fn step(&mut self, input: impl FnOnce(&PongSim) -> PaddleInput) -> Option<Vec<SimEvent>> {
+        self.session.step(input)
+    }
+
+    /// Take in everything that has arrived and answer it.
+    pub fn receive(&mut self) {
+        for (from, message) in receive(&mut self.link) {
+            if from != self.peer {
+                continue;
+            }
+            self.last_heard = Instant::now();
+            match message {
+                Message::Hello { .. } => {
+                    if let Some(welcome) = &self.welcome {
+                        self.link.send(self.peer, welcome.encode());
+                    }
+                }
+                Message::Inputs { first, inputs, ack, confirmed, hash } => {
+                    self.session.receive(first as usize, &inputs);
+                    self.acked = self.acked.max(ack as usize);
+                    if confirmed > 0 {
+                        self.session.check_hash(confirmed as usize, hash);
+                    }
+                }
+                Message::Ping { id } => self.link.send(self.peer, Message::Pong { id }.encode()),
+                Message::Pong { id } => {
+                    if let Some((sent_id, sent)) = self.ping_sent {
+                        if id == sent_id {
+                            let rtt = sent.elapsed();
+                            self.ping = Some(match self.ping {
+                                Some(ping) => ping.mul_f32(0.8) + rtt.mul_f32(0.2),
+                                None => rtt,
+                            });
+                            self.ping_sent = None;
+                        }
+                    }
+                }
+                Message::Bye => self.closed = Some("the other player left".to_string()),
+                Message::Welcome { .. } | Message::Reject { .. } => { }
+            }
+        }
+        if self.closed.is_none() && self.last_heard.elapsed() > TIMEOUT {
+            self.closed = Some("lost the connection".to_string());
+        }
+    }
+
+    /// Send the inputs the other side hasn't got yet, and a ping now and
+    /// then. Call once a frame, after stepping.
+    pub fn send(&mut self) {
+        let local = self.session.local_inputs();
+        let first = self.acked.min(local.len());
+        let last = local.len().min(first + MAX_INPUTS_PER_PACKET);
+        let (confirmed, hash) = self.session.latest_hash().unwrap_or((0, 0));
+        let message = Message::Inputs {
+            first: first as u32,
+            inputs: local[first..last].to_vec(),
+            ack: self.session.remote_received() as u32,
+            confirmed: confirmed as u32,
+            hash,
+        };
+        self.link.send(self.peer, message.encode());
+
+        if self.last_ping.elapsed() >= PING_INTERVAL {
+            self.last_ping = Instant::now();
+            self.next_ping = self.next_ping.wrapping_add(1);
+            self.ping_sent = Some((self.next_ping, Instant::now()));
+            self.link.send(self.peer, Message::Ping { id: self.next_ping }.encode());
+        }
+        self.link.flush();
+    }
+
+    /// Tell the other side we're going.
+    pub fn close(&mut self) {
+        self.link.send_now(self.peer, &Message::Bye.encode());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::ai::{Difficulty, OpponentAi};
+
+    const SHIM: LinkShim = LinkShim {
+        latency: Duration::from_millis(20),
+        jitter: Duration::from_millis(15),
+        loss: 0.1,
+    };
+
+    fn config() -> NetConfig {
+        NetConfig { input_delay: 2, max_prediction: 8, shim: SHIM }
+    }
+
+    // A side that's connected keeps taking packets in, to answer the
+    // other and get its own held-up packets out
+    fn advance(progress: Progress) -> Progress {
+        match progress {
+            Progress::Waiting(connecting) => connecting.poll().expect("connecting"),
+            Progress::Connected(mut session) => {
+                session.receive();
+                Progress::Connected(session)
+            }
+        }
+    }
+
+    // Poll both sides until each has a session
+    fn connect(host: Connecting, join: Connecting) -> (NetSession, NetSession) {
+        let (mut host, mut join) = (Progress::Waiting(Box::new(host)), Progress::Waiting(Box::new(join)));
+        let started = Instant::now();
+        loop {
+            host = advance(host);
+            join = advance(join);
+            if let (Progress::Connected(host), Progress::Connected(join)) = (&host, &join) {
+                assert_eq!(host.local_side(), Paddle::Player);
+                assert_eq!(join.local_side(), Paddle::Opponent);
+                break;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10), "never connected");
+            thread::sleep(Duration::from_millis(1));
+        }
+        match (host, join) {
+            (Progress::Connected(host), Progress::Connected(join)) => (*host, *join),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn host_and_join_on_loopback_play_the_same_match() {
+        let host = Connecting::host(0, 99, PhysicsConfig::default(), MatchRules::default(), config())
+            .expect("bind");
+        let port = host.link.local_address().expect("bound").port();
+        let join = Connecting::join(&format!("127.0.0.1:{}", port), PhysicsConfig::default(), config())
+            .expect("bind");
+        let (mut host, mut join) = connect(host, join);
+        assert_eq!(join.seed, 99);
+
+        let mut sides = [
+            (host.local_side(), OpponentAi::with_difficulty(Difficulty::Hard, 1), Vec::new()),
+            (join.local_side(), OpponentAi::with_difficulty(Difficulty::Normal, 2), Vec::new()),
+        ];
+        let tick_time = PhysicsConfig::default().time_step();
+        let started = Instant::now();
+        let mut next_tick = Instant::now();
+        while sides.iter().any(|(_, _, confirmed): &(_, _, Vec<ConfirmedTick>)| confirmed.len() < 300) {
+            assert!(started.elapsed() < Duration::from_secs(30), "the match never got going");
+            for (session, (side, ai, confirmed)) in [&mut host, &mut join].into_iter().zip(&mut sides) {
+                session.receive();
+                assert_eq!(session.closed(), None);
+                if Instant::now() >= next_tick {
+                    session.step(|shown| ai.input(shown, *side));
+                }
+                confirmed.extend(session.take_confirmed());
+                session.send();
+            }
+            if Instant::now() >= next_tick {
+                next_tick += Duration::from_secs_f32(tick_time);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let [(_, _, hosted), (_, _, joined)] = &sides;
+        for (tick, (ours, theirs)) in hosted.iter().zip(joined).enumerate() {
+            assert_eq!(ours.sim.state(), theirs.sim.state(), "states differ at tick {}", tick + 1);
+        }
+        assert_eq!(host.stats().rollback.desync, None);
+        assert_eq!(join.stats().rollback.desync, None);
+        assert!(host.stats().ping.is_some());
+
+        // Leaving is noticed on the other side
+        host.close();
+        let started = Instant::now();
+        while join.closed().is_none() {
+            assert!(started.elapsed() < Duration::from_secs(2), "the other side never noticed");
+            join.receive();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn joining_needs_the_same_arena() {
+        let mut wide = PhysicsConfig::default();
+        wide.arena.width += 200.0;
+        let host = Connecting::host(0, 1, wide, MatchRules::default(), NetConfig::default()).expect("bind");
+        let port = host.link.local_address().expect("bound").port();
+        let mut host = Progress::Waiting(Box::new(host));
+        let mut join = Connecting::join(&format!("127.0.0.1:{}", port), PhysicsConfig::default(),
+            NetConfig::default()).expect("bind");
+        let started = Instant::now();
+        let error = loop {
+            if let Progress::Waiting(connecting) = host {
+                host = connecting.poll().expect("hosting");
+            }
+            match join.poll() {
+                Ok(Progress::Waiting(connecting)) => join = *connecting,
+                Ok(Progress::Connected(_)) => panic!("joined a host with a different arena"),
+                Err(error) => break error,
+            }
+            assert!(started.elapsed() < Duration::from_secs(5), "no answer from the host");
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert!(error.contains("arena"), "{}", error);
+    }
+}
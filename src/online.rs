@@ -0,0 +1,359 @@
+use bevy::prelude::*;
+
+use pong::net::{Connecting, NetConfig, NetSession, Progress};
+use pong::physics::PhysicsConfig;
+use pong::rules::MatchRules;
+use pong::server::{ServerClient, ServerStatus};
+use pong::sim::{PongSim, SimEvent};
+
+use crate::GameState;
+use crate::bindings::{Action, Bindings};
+use crate::controllers::GameMode;
+use crate::game::{new_seed, SimStep};
+use crate::main_menu::{spawn_button, spawn_menu_column};
+use crate::match_state::{Match, MatchPhase};
+use crate::menu_nav::back_pressed;
+use crate::replays::Recording;
+
+pub struct OnlinePlugin;
+
+// Who to play online, from the command line and settings. Hosts unless
+// there's an address to join or a match server to play on.
+pub struct OnlineSetup {
+    pub join: Option<String>,
+    pub server: Option<String>,
+    pub port: u16,
+    pub config: NetConfig,
+    // Who this game is to a match server
+    pub token: u64,
+}
+
+// A match played with someone on another machine: directly, with both
+// sides running it, or on a match server that runs it for both
+pub enum OnlineMatch {
+    Peer(Box<NetSession>),
+    Server(Box<ServerClient>),
+}
+
+// The match being played online, in place of one played here
+#[derive(Default)]
+pub struct Online(pub Option<OnlineMatch>);
+
+impl Online {
+    pub fn peer(&self) -> Option<&NetSession> {
+        match &self.0 {
+            Some(OnlineMatch::Peer(session)) => Some(session),
+            _ => None,
+        }
+    }
+
+    pub fn server(&self) -> Option<&ServerClient> {
+        match &self.0 {
+            Some(OnlineMatch::Server(client)) => Some(client),
+            _ => None,
+        }
+    }
+}
+
+// On the way to a match
+enum Joining {
+    Peer(Box<Connecting>),
+    Server(Box<ServerClient>),
+}
+
+// Waiting for the other side, until it turns up or connecting fails
+#[derive(Default)]
+struct Pending(Option<Joining>);
+
+#[derive(Component)]
+struct ConnectingEntity;
+
+#[derive(Component)]
+struct StatusText;
+
+#[derive(Component)]
+struct CancelButton;
+
+impl Plugin for OnlinePlugin {
+    fn build(&self, app: &mut App) {
+        println!("Building online play!");
+        app
+        .init_resource::<Online>()
+        .init_resource::<Pending>()
+        .add_system_set(
+            SystemSet::on_enter(GameState::Connecting)
+                .with_system(setup_connecting)
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Connecting)
+                .with_system(poll_connection)
+                .with_system(cancel_button_sys)
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Connecting)
+                .with_system(teardown_connecting)
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::InGame)
+                .with_system(receive_online.before(SimStep))
+                .with_system(exchange_online.after(SimStep))
+        )
+        // The other side may still need our last inputs to see the end
+        .add_system_set(
+            SystemSet::on_update(GameState::GameOver)
+                .with_system(keep_alive)
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::MainMenu)
+                .with_system(leave_online)
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::GameOver)
+                .with_system(leave_online)
+        );
+    }
+}
+
+// Start hosting or joining, and say which on screen
+fn setup_connecting(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    online_setup: Res<OnlineSetup>,
+    physics: Res<PhysicsConfig>,
+    rules: Res<MatchRules>,
+    mut pending: ResMut<Pending>,
+) {
+    let config = online_setup.config;
+    let joining = match (&online_setup.server, &online_setup.join) {
+        (Some(address), _) => ServerClient::connect(address, *physics, online_setup.token, config.shim)
+            .map(|client| Joining::Server(Box::new(client)))
+            .map_err(|error| format!("Could not join {}: {}", address, error)),
+        (None, Some(address)) => Connecting::join(address, *physics, config)
+            .map(|connecting| Joining::Peer(Box::new(connecting)))
+            .map_err(|error| format!("Could not join {}: {}", address, error)),
+        (None, None) => Connecting::host(online_setup.port, new_seed(), *physics, *rules, config)
+            .map(|connecting| Joining::Peer(Box::new(connecting)))
+            .map_err(|error| format!("Could not host on port {}: {}", online_setup.port, error)),
+    };
+    let status = match &joining {
+        Ok(_) => match (&online_setup.server, &online_setup.join) {
+            (Some(address), _) => format!("Joining the server at {}", address),
+            (None, Some(address)) => format!("Joining {}", address),
+            (None, None) => format!("Waiting for a player on port {}", online_setup.port),
+        },
+        Err(error) => error.clone(),
+    };
+    pending.0 = joining.ok();
+
+    let title = commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "Online",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 60.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            )
+            .with_style(Style {
+                margin: UiRect::all(Val::Px(10.0)),
+                ..default()
+            }),
+        )
+        .id();
+    let status = commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                status,
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            )
+            .with_style(Style {
+                margin: UiRect::all(Val::Px(20.0)),
+                ..default()
+            }),
+        )
+        .insert(StatusText)
+        .id();
+    let cancel = spawn_button(&mut commands, &asset_server, "Back")
+        .insert(CancelButton)
+        .id();
+
+    spawn_menu_column(&mut commands)
+        .push_children(&[title, status, cancel])
+        .insert(ConnectingEntity);
+}
+
+// Start the match once the other side is there
+fn poll_connection(
+    mut pending: ResMut<Pending>,
+    mut online: ResMut<Online>,
+    mut mode: ResMut<GameMode>,
+    mut state: ResMut<State<GameState>>,
+    mut status_query: Query<&mut Text, With<StatusText>>,
+) {
+    let mut status = None;
+    match pending.0.take() {
+        None => return,
+        Some(Joining::Peer(connecting)) => match connecting.poll() {
+            Ok(Progress::Waiting(connecting)) => pending.0 = Some(Joining::Peer(connecting)),
+            Ok(Progress::Connected(session)) => {
+                println!("Connected to {}", session.peer());
+                online.0 = Some(OnlineMatch::Peer(session));
+            }
+            Err(error) => status = Some(format!("Could not connect: {}", error)),
+        },
+        Some(Joining::Server(mut client)) => {
+            client.receive();
+            let started = matches!(client.status(), ServerStatus::Playing | ServerStatus::Reconnecting { .. });
+            if let Some(reason) = client.closed() {
+                status = Some(format!("Could not join: {}", reason));
+            } else if started && client.view(0.0).is_some() {
+                println!("Joined the match server");
+                online.0 = Some(OnlineMatch::Server(client));
+            } else {
+                if client.side().is_some() {
+                    status = Some("Waiting for another player".to_string());
+                }
+                pending.0 = Some(Joining::Server(client));
+            }
+        }
+    }
+
+    // Back may have been pressed this frame already. Then that wins, and
+    // arriving at the menu closes the connection again.
+    if online.0.is_some() && state.set(GameState::InGame).is_ok() {
+        *mode = GameMode::Online;
+    }
+    if let Some(status) = status {
+        for mut text in &mut status_query {
+            if text.sections[0].value != status {
+                text.sections[0].value = status.clone();
+            }
+        }
+    }
+}
+
+fn cancel_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<CancelButton>)>,
+    mut keys: ResMut<Input<KeyCode>>,
+    bindings: Res<Bindings>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let clicked = interaction_query.iter().any(|interaction| *interaction == Interaction::Clicked);
+    if clicked || back_pressed(&keys, &bindings, &gamepad_buttons) {
+        // The other side may have turned up this frame already; then the
+        // match starts
+        let _ = state.set(GameState::MainMenu);
+        keys.reset(bindings.key(Action::Back));
+    }
+}
+
+fn teardown_connecting(
+    mut commands: Commands,
+    query: Query<Entity, With<ConnectingEntity>>,
+    mut pending: ResMut<Pending>,
+) {
+    // Give up the seat at once rather than have the server wait on it
+    if let Some(Joining::Server(mut client)) = pending.0.take() {
+        client.leave();
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Take in the other side's inputs before this frame's ticks, showing any
+// rollback they caused. A match server's snapshots are shown as the ticks
+// go by instead.
+fn receive_online(
+    mut online: ResMut<Online>,
+    mut sim: ResMut<PongSim>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let closed = match &mut online.0 {
+        Some(OnlineMatch::Peer(session)) => {
+            session.receive();
+            if session.closed().is_none() {
+                *sim = session.sim().clone();
+            }
+            session.closed().map(str::to_string)
+        }
+        Some(OnlineMatch::Server(client)) => {
+            client.receive();
+            client.closed().map(str::to_string)
+        }
+        None => None,
+    };
+    if let Some(reason) = closed {
+        println!("Online match over: {}", reason);
+        // Another transition may already be queued this frame
+        let _ = state.set(GameState::MainMenu);
+    }
+}
+
+// Record the ticks both sides now agree on, end the match once they agree
+// it's won, and send this frame's inputs. On a match server the server
+// says how it ended, forfeits included.
+fn exchange_online(
+    mut online: ResMut<Online>,
+    sim: Res<PongSim>,
+    mut current: ResMut<Match>,
+    mut recording: ResMut<Recording>,
+    mut state: ResMut<State<GameState>>,
+) {
+    match &mut online.0 {
+        Some(OnlineMatch::Peer(session)) => {
+            for confirmed in session.take_confirmed() {
+                if current.phase != MatchPhase::Running {
+                    break;
+                }
+                recording.record(confirmed.inputs, &confirmed.sim, &confirmed.events);
+                if confirmed.events.iter().any(|event| matches!(event, SimEvent::MatchWon(_))) {
+                    current.finish(&confirmed.sim);
+                    let _ = state.set(GameState::GameOver);
+                }
+            }
+            session.send();
+        }
+        Some(OnlineMatch::Server(client)) => {
+            if let (ServerStatus::Over { winner, .. }, MatchPhase::Running) = (client.status(), current.phase) {
+                match winner {
+                    Some(winner) => {
+                        current.award(&sim, winner);
+                        let _ = state.set(GameState::GameOver);
+                    }
+                    None => {
+                        println!("Online match over: both sides dropped out");
+                        let _ = state.set(GameState::MainMenu);
+                    }
+                }
+            }
+        }
+        None => { }
+    }
+}
+
+// A match server needs nothing more once the match is over, and would
+// seat this side in the next one if it asked
+fn keep_alive(mut online: ResMut<Online>) {
+    if let Some(OnlineMatch::Peer(session)) = &mut online.0 {
+        session.receive();
+        session.send();
+    }
+}
+
+// Say goodbye and free the port for the next match. Whatever comes next
+// is played here unless it connects again.
+fn leave_online(mut online: ResMut<Online>, mut mode: ResMut<GameMode>) {
+    match online.0.take() {
+        Some(OnlineMatch::Peer(mut session)) => session.close(),
+        Some(OnlineMatch::Server(mut client)) => client.leave(),
+        None => return,
+    }
+    *mode = GameMode::Solo;
+}
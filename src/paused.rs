@@ -1,18 +1,181 @@
-use bevy::{prelude::*};
+use bevy::{prelude::*, window::WindowFocused};
 
 use crate::GameState;
+use crate::bindings::{Action, Bindings};
+use crate::controllers::GameMode;
+use crate::game::RestartMatch;
+use crate::main_menu::{spawn_button, spawn_menu_column};
+use crate::menu_nav::back_pressed;
 use crate::pause_game;
 
 pub struct PausedPlugin;
 
+const OVERLAY_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+
+#[derive(Component)]
+struct PausedEntity;
+
+#[derive(Component)]
+struct ResumeButton;
+
+#[derive(Component)]
+struct RestartButton;
+
+#[derive(Component)]
+struct PauseSettingsButton;
+
+#[derive(Component)]
+struct QuitToMenuButton;
+
 impl Plugin for PausedPlugin {
     fn build(&self, app: &mut App ) {
         println!("Pausing game!");
-        app.
-        add_system_set(
+        app
+        .add_system(pause_on_focus_lost)
+        .add_system_set(
+            SystemSet::on_enter(GameState::Paused)
+            .with_system(setup_pause_menu)
+        )
+        // Settings opens on top of the pause menu, so make way for it
+        .add_system_set(
+            SystemSet::on_resume(GameState::Paused)
+            .with_system(setup_pause_menu)
+        )
+        .add_system_set(
             SystemSet::on_update(GameState::Paused)
             .with_system(pause_game)
+            .with_system(resume_button_sys)
+            .with_system(restart_button_sys)
+            .with_system(pause_settings_button_sys)
+            .with_system(quit_to_menu_button_sys)
+        )
+        .add_system_set(
+            SystemSet::on_pause(GameState::Paused)
+            .with_system(teardown_pause_menu)
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Paused)
+            .with_system(teardown_pause_menu)
         );
     }
 }
 
+// Stop play when the window goes to the background, unless it's an online
+// match that goes on regardless
+fn pause_on_focus_lost(
+    mut focus_events: EventReader<WindowFocused>,
+    mode: Res<GameMode>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let lost = focus_events.iter().any(|event| !event.focused);
+    if lost && *state.current() == GameState::InGame && *mode != GameMode::Online {
+        // Nothing to do if the game is already on its way elsewhere
+        let _ = state.push(GameState::Paused);
+    }
+}
+
+fn setup_pause_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let title = commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "Paused",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 60.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            )
+            .with_style(Style {
+                margin: UiRect::all(Val::Px(20.0)),
+                ..default()
+            }),
+        )
+        .id();
+    let resume = spawn_button(&mut commands, &asset_server, "Resume")
+        .insert(ResumeButton)
+        .id();
+    let restart = spawn_button(&mut commands, &asset_server, "Restart Match")
+        .insert(RestartButton)
+        .id();
+    let settings = spawn_button(&mut commands, &asset_server, "Settings")
+        .insert(PauseSettingsButton)
+        .id();
+    let quit = spawn_button(&mut commands, &asset_server, "Quit to Menu")
+        .insert(QuitToMenuButton)
+        .id();
+
+    // Dim the frozen game behind the menu
+    spawn_menu_column(&mut commands)
+        .insert(UiColor(OVERLAY_COLOR))
+        .push_children(&[title, resume, restart, settings, quit])
+        .insert(PausedEntity);
+}
+
+// The Back key or B resumes too
+fn resume_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<ResumeButton>)>,
+    mut keys: ResMut<Input<KeyCode>>,
+    bindings: Res<Bindings>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let clicked = interaction_query.iter().any(|interaction| *interaction == Interaction::Clicked);
+    if clicked || back_pressed(&keys, &bindings, &gamepad_buttons) {
+        // The pause key may have resumed the game this frame already
+        let _ = state.pop();
+        keys.reset(bindings.key(Action::Back));
+    }
+}
+
+fn restart_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<RestartButton>)>,
+    mut restart_events: EventWriter<RestartMatch>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            // The pause key may have resumed the game this frame already,
+            // and then the match carries on as it was
+            if state.pop().is_ok() {
+                restart_events.send(RestartMatch);
+            }
+        }
+    }
+}
+
+fn pause_settings_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<PauseSettingsButton>)>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            // The pause key may have queued a transition this frame already
+            let _ = state.push(GameState::Settings);
+        }
+    }
+}
+
+fn quit_to_menu_button_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<QuitToMenuButton>)>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            // Drop the paused match underneath as well. The pause key may
+            // have queued a transition this frame already; that one wins.
+            let _ = state.replace(GameState::MainMenu);
+        }
+    }
+}
+
+fn teardown_pause_menu(
+    mut commands: Commands,
+    query: Query<Entity, With<PausedEntity>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
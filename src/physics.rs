@@ -0,0 +1,163 @@
+//! Tuning values for how the ball and paddles move.
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhysicsConfig {
+    /// Simulation ticks per second.
+    pub tick_rate: f32,
+    /// Ball speed at the start of every rally, in pixels per second.
+    pub ball_speed: f32,
+    /// Each paddle hit multiplies the ball speed by this much.
+    pub ball_speedup: f32,
+    /// The ball never gets faster than this.
+    pub max_ball_speed: f32,
+    pub ball_size: f32,
+    /// Angle away from horizontal for a ball hitting the very end of a
+    /// paddle, in degrees. Hits nearer the middle come off flatter.
+    pub max_bounce_angle: f32,
+    pub paddle_width: f32,
+    pub paddle_height: f32,
+    /// Distance from a paddle to the inner surface of its wall.
+    pub paddle_offset: f32,
+    /// Top speed of each paddle, in pixels per second.
+    pub player_speed: f32,
+    pub opponent_speed: f32,
+    pub paddle_movement: PaddleMovement,
+    /// Share of a paddle's vertical speed passed on to the ball it hits.
+    pub paddle_spin: f32,
+    pub arena: Arena,
+}
+
+/// How a paddle responds to its input.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PaddleMovement {
+    /// Full speed for as long as a direction is held, and an instant stop.
+    Direct,
+    /// The paddle speeds up towards the held direction by `acceleration`
+    /// and coasts to a stop by `friction`, both in pixels per second squared.
+    Accelerated { acceleration: f32, friction: f32 },
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        PhysicsConfig {
+            tick_rate: 60.0,
+            ball_speed: 450.0,
+            ball_speedup: 1.05,
+            max_ball_speed: 900.0,
+            ball_size: 25.0,
+            max_bounce_angle: 60.0,
+            paddle_width: 15.0,
+            paddle_height: 100.0,
+            paddle_offset: 50.0,
+            player_speed: 200.0,
+            opponent_speed: 250.0,
+            paddle_movement: PaddleMovement::Direct,
+            paddle_spin: 0.3,
+            arena: Arena::default(),
+        }
+    }
+}
+
+/// The playing field, centred on the origin. Walls sit just inside its
+/// edges.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Arena {
+    pub width: f32,
+    pub height: f32,
+    pub wall_thickness: f32,
+    /// Gap between the top and bottom edges and the middle of their walls.
+    pub vertical_offset: f32,
+    /// Gap between the side edges and the middle of their walls.
+    pub horizontal_offset: f32,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena {
+            width: 1000.0,
+            height: 700.0,
+            wall_thickness: 100.0,
+            vertical_offset: 50.0,
+            horizontal_offset: 25.0,
+        }
+    }
+}
+
+impl Arena {
+    /// Space between the inner faces of the walls.
+    pub fn inner_size(&self) -> Vec2 {
+        Vec2::new(
+            self.width - 2. * self.horizontal_offset - self.wall_thickness,
+            self.height - 2. * self.vertical_offset - self.wall_thickness,
+        )
+    }
+}
+
+impl PhysicsConfig {
+    /// Seconds simulated by one tick.
+    pub fn time_step(&self) -> f32 {
+        1.0 / self.tick_rate
+    }
+
+    pub fn ball_size(&self) -> Vec2 {
+        Vec2::splat(self.ball_size)
+    }
+
+    pub fn paddle_size(&self) -> Vec2 {
+        Vec2::new(self.paddle_width, self.paddle_height)
+    }
+}
+
+/// Check a value read from outside, naming it in the error.
+pub(crate) fn check(ok: bool, name: &str, requirement: &str, value: impl std::fmt::Display) -> Result<(), String> {
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("{} must be {} (got {})", name, requirement, value))
+    }
+}
+
+impl PhysicsConfig {
+    /// Reject settings the simulation can't run with.
+    pub fn validate(&self) -> Result<(), String> {
+        check(self.tick_rate > 0.0, "tick_rate", "positive", self.tick_rate)?;
+        check(self.ball_speed > 0.0, "ball_speed", "positive", self.ball_speed)?;
+        check(self.ball_speedup >= 1.0, "ball_speedup", "at least 1", self.ball_speedup)?;
+        check(self.max_ball_speed >= self.ball_speed, "max_ball_speed",
+            "at least ball_speed", self.max_ball_speed)?;
+        check(self.ball_size > 0.0, "ball_size", "positive", self.ball_size)?;
+        check((0.0..90.0).contains(&self.max_bounce_angle), "max_bounce_angle",
+            "from 0 up to 90 degrees", self.max_bounce_angle)?;
+        check(self.paddle_width > 0.0, "paddle_width", "positive", self.paddle_width)?;
+        check(self.paddle_height > 0.0, "paddle_height", "positive", self.paddle_height)?;
+        check(self.paddle_offset >= 0.0, "paddle_offset", "zero or more", self.paddle_offset)?;
+        check(self.player_speed > 0.0, "player_speed", "positive", self.player_speed)?;
+        check(self.opponent_speed > 0.0, "opponent_speed", "positive", self.opponent_speed)?;
+        check(self.paddle_spin >= 0.0, "paddle_spin", "zero or more", self.paddle_spin)?;
+        if let PaddleMovement::Accelerated { acceleration, friction } = self.paddle_movement {
+            check(acceleration > 0.0, "acceleration", "positive", acceleration)?;
+            check(friction >= 0.0, "friction", "zero or more", friction)?;
+        }
+
+        let arena = &self.arena;
+        check(arena.wall_thickness > 0.0, "arena.wall_thickness", "positive", arena.wall_thickness)?;
+        check(arena.vertical_offset >= 0.0, "arena.vertical_offset", "zero or more",
+            arena.vertical_offset)?;
+        check(arena.horizontal_offset >= 0.0, "arena.horizontal_offset", "zero or more",
+            arena.horizontal_offset)?;
+
+        // Both paddles and a ball between them have to fit inside the walls
+        let inner = arena.inner_size();
+        let needed_width = 2. * (self.paddle_offset + self.paddle_width) + self.ball_size;
+        check(inner.x > needed_width, "arena.width",
+            "wide enough for both paddles and the ball", arena.width)?;
+        check(inner.y > self.paddle_height.max(self.ball_size), "arena.height",
+            "taller than a paddle inside the walls", arena.height)?;
+        Ok(())
+    }
+}
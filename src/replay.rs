@@ -0,0 +1,820 @@
+//! Recorded matches. A replay holds everything the simulation was started
+//! with and the inputs of every tick, so stepping a fresh [`PongSim`]
+//! through those inputs plays the match out exactly as it happened.
+//!
+//! Snapshots of the whole simulation are taken every few seconds and at
+//! the start of each point, so playback can jump anywhere without
+//! simulating from the first tick.
+//!
+//! A hash of the simulation after every tick is kept too. [`Replay::verify`]
+//! plays the inputs through again and checks each hash, so anything that
+//! makes the simulation play out differently from how it was recorded
+//! shows up at the tick it happened.
+//!
+//! On disk a replay is the magic bytes `PONGRPLY`, the header as TOML
+//! behind its length, the inputs as runs of identical ticks, and then the
+//! point markers and snapshots behind their own version number, then the
+//! tick hashes. All numbers are little-endian. Version 1 files have no
+//! snapshots; they are rebuilt when the file is loaded. Files before
+//! version 3 have no hashes and can't be verified.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::physics::PhysicsConfig;
+use crate::rules::MatchRules;
+use crate::sim::{
+    Ball, PaddleInput, Paddle, PaddleState, Phase, PongSim, Scoreboard, SimEvent, SimInputs,
+    SimState,
+};
+
+/// Format version written to new replays.
+pub const REPLAY_VERSION: u32 = 3;
+
+/// Layout of the snapshots in version 2 replays and later.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Match time between snapshots.
+pub const SNAPSHOT_SECONDS: f32 = 5.0;
+
+/// File extension for replays, without the dot.
+pub const REPLAY_EXTENSION: &str = "pongreplay";
+
+const MAGIC: &[u8; 8] = b"PONGRPLY";
+
+// Longest recording a file may hold: a day of play at 60 ticks a second.
+// Stops a damaged file from asking for billions of ticks.
+const MAX_TICKS: usize = 24 * 60 * 60 * 60;
+
+/// How a recorded match was set up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReplayHeader {
+    pub version: u32,
+    #[serde(with = "seed_bits")]
+    pub seed: u64,
+    /// What controlled each side, for showing the replay. Playback
+    /// doesn't need them: the inputs are recorded, not the controllers.
+    pub player: String,
+    pub opponent: String,
+    pub physics: PhysicsConfig,
+    pub rules: MatchRules,
+    /// Seeds `player` and `opponent` were built with, when they are
+    /// computer controllers that can be built again to check they still
+    /// make the same moves. Only `pong-sim` records these.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "seed_pair_bits")]
+    pub controller_seeds: Option<[u64; 2]>,
+}
+
+// TOML integers are signed, so seeds are written as the i64 with the same
+// bits. Seeds below 2^63 look the same either way.
+pub(crate) mod seed_bits {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        (*seed as i64).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        Ok(i64::deserialize(deserializer)? as u64)
+    }
+}
+
+mod seed_pair_bits {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(seeds: &Option<[u64; 2]>, serializer: S)
+        -> Result<S::Ok, S::Error>
+    {
+        seeds.map(|seeds| seeds.map(|seed| seed as i64)).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D)
+        -> Result<Option<[u64; 2]>, D::Error>
+    {
+        let seeds = Option::<[i64; 2]>::deserialize(deserializer)?;
+        Ok(seeds.map(|seeds| seeds.map(|seed| seed as u64)))
+    }
+}
+
+/// The simulation as it was after the first `tick` inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snapshot {
+    pub tick: usize,
+    pub state: SimState,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    /// Inputs for each tick, in order.
+    pub inputs: Vec<SimInputs>,
+    /// Ticks at which each point after the first started, in order.
+    pub points: Vec<usize>,
+    /// In tick order.
+    pub snapshots: Vec<Snapshot>,
+    /// [`state_hash`] after each tick, or none for replays recorded before
+    /// hashes were kept.
+    pub hashes: Vec<u32>,
+}
+
+/// Where a replay played out differently from how it was recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Desync {
+    /// First tick whose hash didn't match, counting the state after the
+    /// first input as tick 1.
+    pub tick: usize,
+    pub recorded_hash: u32,
+    pub replayed_hash: u32,
+    /// The recording's state at `tick`, if a snapshot was taken right
+    /// there. Hashes alone can't say what changed.
+    pub recorded: Option<SimState>,
+    /// The state at the tick before, the last one both agree on, played
+    /// on from the snapshot before it.
+    pub before: SimState,
+    /// The replayed simulation at `tick`.
+    pub replayed: SimState,
+}
+
+impl Desync {
+    /// One line for each part of the ball, paddles or score that differs
+    /// at `tick` between the recording and the replay. Without a snapshot
+    /// at `tick` the recording's state there isn't known, so it is what
+    /// the replay changed from the tick before instead.
+    pub fn diff(&self) -> Vec<String> {
+        let (label, recorded) = match &self.recorded {
+            Some(recorded) => ("recorded".to_string(), recorded),
+            None => (format!("at tick {}", self.tick - 1), &self.before),
+        };
+        let replayed = &self.replayed;
+        let mut lines = Vec::new();
+        let mut compare = |name: &str, recorded: String, replayed: String| {
+            if recorded != replayed {
+                lines.push(format!("{}: {} {}, replayed {}", name, label, recorded, replayed));
+            }
+        };
+        compare("ball.position", format!("{:?}", recorded.ball.position),
+            format!("{:?}", replayed.ball.position));
+        compare("ball.velocity", format!("{:?}", recorded.ball.velocity),
+            format!("{:?}", replayed.ball.velocity));
+        for (name, recorded, replayed) in [
+            ("player", &recorded.player, &replayed.player),
+            ("opponent", &recorded.opponent, &replayed.opponent),
+        ] {
+            compare(&format!("{}.position", name), format!("{:?}", recorded.position),
+                format!("{:?}", replayed.position));
+            compare(&format!("{}.velocity", name), format!("{:?}", recorded.velocity),
+                format!("{:?}", replayed.velocity));
+        }
+        compare("scoreboard", format!("{:?}", recorded.scoreboard),
+            format!("{:?}", replayed.scoreboard));
+        compare("phase", format!("{:?}", recorded.phase), format!("{:?}", replayed.phase));
+        compare("rng", format!("{:#018x}", recorded.rng), format!("{:#018x}", replayed.rng));
+        lines
+    }
+}
+
+/// A 32-bit FNV-1a hash of everything in `state`, bit for bit. Stable
+/// across platforms and builds, unlike the standard library's hashers.
+pub fn state_hash(state: &SimState) -> u32 {
+    let mut bytes = Vec::with_capacity(128);
+    write_state(&mut bytes, state);
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193))
+}
+
+impl Replay {
+    /// An empty recording of a match started with these settings.
+    pub fn new(
+        seed: u64,
+        physics: PhysicsConfig,
+        rules: MatchRules,
+        player: &str,
+        opponent: &str,
+    ) -> Replay {
+        Replay {
+            header: ReplayHeader {
+                version: REPLAY_VERSION,
+                seed,
+                player: player.to_string(),
+                opponent: opponent.to_string(),
+                physics,
+                rules,
+                controller_seeds: None,
+            },
+            inputs: Vec::new(),
+            points: Vec::new(),
+            snapshots: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    /// `replays` in the user's data directory.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("pong").join("replays"))
+    }
+
+    /// Add the next tick's inputs, given the simulation and its events
+    /// after stepping with them.
+    pub fn record(&mut self, inputs: SimInputs, sim: &PongSim, events: &[SimEvent]) {
+        self.inputs.push(inputs);
+        self.hashes.push(state_hash(&sim.state()));
+        let tick = self.inputs.len();
+        let point_started = events.iter().any(|event| matches!(event, SimEvent::ServeStarted(_)));
+        if point_started {
+            self.points.push(tick);
+        }
+        if point_started || tick.is_multiple_of(self.snapshot_interval()) {
+            self.snapshots.push(Snapshot { tick, state: sim.state() });
+        }
+    }
+
+    fn snapshot_interval(&self) -> usize {
+        ((SNAPSHOT_SECONDS / self.header.physics.time_step()).round() as usize).max(1)
+    }
+
+    /// Work the point markers, snapshots and hashes out again by playing
+    /// the inputs through.
+    pub fn reindex(&mut self) {
+        let inputs = std::mem::take(&mut self.inputs);
+        self.points.clear();
+        self.snapshots.clear();
+        self.hashes.clear();
+        let mut sim = self.start();
+        for tick in inputs {
+            let events = sim.step(tick);
+            self.record(tick, &sim, &events);
+        }
+    }
+
+    /// Number of ticks recorded.
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Length of the recording in seconds.
+    pub fn duration(&self) -> f32 {
+        self.inputs.len() as f32 * self.header.physics.time_step()
+    }
+
+    /// The simulation as it was before the first tick.
+    pub fn start(&self) -> PongSim {
+        PongSim::new(self.header.physics, self.header.rules, self.header.seed)
+    }
+
+    /// The simulation after the first `tick` inputs, from the nearest
+    /// snapshot before it.
+    pub fn seek(&self, tick: usize) -> PongSim {
+        let tick = tick.min(self.inputs.len());
+        let index = self.snapshots.partition_point(|snapshot| snapshot.tick <= tick);
+        let (mut sim, from) = match index.checked_sub(1).map(|index| &self.snapshots[index]) {
+            Some(snapshot) => (PongSim::from_state(self.header.physics, self.header.rules,
+                snapshot.state), snapshot.tick),
+            None => (self.start(), 0),
+        };
+        for inputs in &self.inputs[from..tick] {
+            sim.step(*inputs);
+        }
+        sim
+    }
+
+    /// Play every tick again, checking each against its recorded hash.
+    /// Replays without hashes pass, as there is nothing to check.
+    pub fn verify(&self) -> Result<(), Box<Desync>> {
+        let mut sim = self.start();
+        for (index, (inputs, &recorded_hash)) in self.inputs.iter().zip(&self.hashes).enumerate() {
+            sim.step(*inputs);
+            let replayed_hash = state_hash(&sim.state());
+            if replayed_hash == recorded_hash {
+                continue;
+            }
+            let tick = index + 1;
+            let recorded = self.snapshots.iter()
+                .find(|snapshot| snapshot.tick == tick)
+                .map(|snapshot| snapshot.state);
+            return Err(Box::new(Desync {
+                tick,
+                recorded_hash,
+                replayed_hash,
+                recorded,
+                before: self.seek(tick - 1).state(),
+                replayed: sim.state(),
+            }));
+        }
+        Ok(())
+    }
+
+    /// Play every tick, returning the simulation as it ended.
+    pub fn play(&self) -> PongSim {
+        let mut sim = self.start();
+        for inputs in &self.inputs {
+            sim.step(*inputs);
+        }
+        sim
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = toml::to_string(&self.header).expect("replay header is always valid TOML");
+        let runs = runs(&self.inputs);
+
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 8 + header.len() + runs.len() * 13);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+        for (count, inputs) in runs {
+            bytes.extend_from_slice(&count.to_le_bytes());
+            bytes.extend_from_slice(&inputs.player.direction.to_bits().to_le_bytes());
+            bytes.extend_from_slice(&inputs.opponent.direction.to_bits().to_le_bytes());
+            bytes.push(inputs.player.serve as u8 | (inputs.opponent.serve as u8) << 1);
+        }
+
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.points.len() as u32).to_le_bytes());
+        for tick in &self.points {
+            bytes.extend_from_slice(&(*tick as u32).to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.snapshots.len() as u32).to_le_bytes());
+        for snapshot in &self.snapshots {
+            bytes.extend_from_slice(&(snapshot.tick as u32).to_le_bytes());
+            write_state(&mut bytes, &snapshot.state);
+        }
+        bytes.extend_from_slice(&(self.hashes.len() as u32).to_le_bytes());
+        for hash in &self.hashes {
+            bytes.extend_from_slice(&hash.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, String> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("not a replay file".to_string());
+        }
+        let header_len = reader.u32()? as usize;
+        let header = std::str::from_utf8(reader.take(header_len)?)
+            .map_err(|_| "replay header is not text".to_string())?;
+        let header: ReplayHeader = toml::from_str(header)
+            .map_err(|error| format!("bad replay header: {}", error))?;
+        if header.version == 0 || header.version > REPLAY_VERSION {
+            return Err(format!("replay version {} is not supported (this build reads up to \
+                version {})", header.version, REPLAY_VERSION));
+        }
+        header.physics.validate().map_err(|error| format!("physics.{}", error))?;
+        header.rules.validate().map_err(|error| format!("rules.{}", error))?;
+
+        let mut inputs = Vec::new();
+        for _ in 0..reader.u32()? {
+            let count = reader.u32()? as usize;
+            if count > MAX_TICKS - inputs.len() {
+                return Err(format!("replay is longer than {} ticks", MAX_TICKS));
+            }
+            let player = reader.direction()?;
+            let opponent = reader.direction()?;
+            let serves = reader.u8()?;
+            let tick = SimInputs {
+                player: PaddleInput { direction: player, serve: serves & 1 != 0 },
+                opponent: PaddleInput { direction: opponent, serve: serves & 2 != 0 },
+            };
+            inputs.extend(std::iter::repeat_n(tick, count));
+        }
+
+        let mut replay = Replay {
+            header,
+            inputs,
+            points: Vec::new(),
+            snapshots: Vec::new(),
+            hashes: Vec::new(),
+        };
+        if replay.header.version == 1 {
+            replay.reindex();
+            // Hashes from this build would only prove it agrees with itself
+            replay.hashes.clear();
+        } else {
+            read_snapshots(&mut reader, &mut replay)?;
+        }
+        if replay.header.version >= 3 {
+            read_hashes(&mut reader, &mut replay)?;
+        }
+        // Saved again, it is saved in the current format
+        replay.header.version = REPLAY_VERSION;
+        if !reader.bytes.is_empty() {
+            return Err("replay has trailing bytes".to_string());
+        }
+        Ok(replay)
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let bytes = fs::read(path)
+            .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+        Replay::from_bytes(&bytes).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_bytes())
+    }
+}
+
+fn read_snapshots(reader: &mut Reader, replay: &mut Replay) -> Result<(), String> {
+    let version = reader.u32()?;
+    if version != SNAPSHOT_VERSION {
+        return Err(format!("replay snapshots are version {}, but this build reads version {}",
+            version, SNAPSHOT_VERSION));
+    }
+    let len = replay.inputs.len();
+    for _ in 0..reader.u32()? {
+        replay.points.push(reader.u32()? as usize);
+    }
+    for _ in 0..reader.u32()? {
+        let tick = reader.u32()? as usize;
+        let state = read_state(reader)?;
+        replay.snapshots.push(Snapshot { tick, state });
+    }
+    let in_order = |ticks: &mut dyn Iterator<Item = usize>| {
+        let ticks: Vec<usize> = ticks.collect();
+        ticks.windows(2).all(|pair| pair[0] <= pair[1]) && ticks.iter().all(|tick| *tick <= len)
+    };
+    if !in_order(&mut replay.points.iter().copied())
+        || !in_order(&mut replay.snapshots.iter().map(|snapshot| snapshot.tick))
+    {
+        return Err("replay markers are out of order or past the end".to_string());
+    }
+    Ok(())
+}
+
+fn read_hashes(reader: &mut Reader, replay: &mut Replay) -> Result<(), String> {
+    let count = reader.u32()? as usize;
+    if count != 0 && count != replay.inputs.len() {
+        return Err(format!("replay has {} tick hashes for {} ticks", count, replay.inputs.len()));
+    }
+    for _ in 0..count {
+        replay.hashes.push(reader.u32()?);
+    }
+    Ok(())
+}
+
+pub(crate) fn write_vec2(bytes: &mut Vec<u8>, value: Vec2) {
+    bytes.extend_from_slice(&value.x.to_bits().to_le_bytes());
+    bytes.extend_from_slice(&value.y.to_bits().to_le_bytes());
+}
+
+fn write_paddle_state(bytes: &mut Vec<u8>, paddle: &PaddleState) {
+    write_vec2(bytes, paddle.position);
+    write_vec2(bytes, paddle.size);
+    bytes.extend_from_slice(&paddle.speed.to_bits().to_le_bytes());
+    bytes.extend_from_slice(&paddle.velocity.to_bits().to_le_bytes());
+}
+
+pub(crate) fn paddle_code(paddle: Option<Paddle>) -> u8 {
+    match paddle {
+        None => 0,
+        Some(Paddle::Player) => 1,
+        Some(Paddle::Opponent) => 2,
+    }
+}
+
+// The match server sends states this way too
+pub(crate) fn write_state(bytes: &mut Vec<u8>, state: &SimState) {
+    write_vec2(bytes, state.ball.position);
+    write_vec2(bytes, state.ball.velocity);
+    write_vec2(bytes, state.ball.size);
+    write_paddle_state(bytes, &state.player);
+    write_paddle_state(bytes, &state.opponent);
+    let scoreboard = &state.scoreboard;
+    for count in [scoreboard.player_score, scoreboard.opponent_score,
+        scoreboard.player_games, scoreboard.opponent_games]
+    {
+        bytes.extend_from_slice(&(count as u32).to_le_bytes());
+    }
+    match state.phase {
+        Phase::Serving { ticks_left } => {
+            bytes.push(0);
+            bytes.extend_from_slice(&ticks_left.to_le_bytes());
+        }
+        Phase::Rally => {
+            bytes.push(1);
+            bytes.extend_from_slice(&0u32.to_le_bytes());
+        }
+    }
+    bytes.push(paddle_code(Some(state.server)));
+    bytes.push(paddle_code(Some(state.first_server)));
+    bytes.extend_from_slice(&(state.points_played as u32).to_le_bytes());
+    bytes.extend_from_slice(&state.rng.to_le_bytes());
+    bytes.push(paddle_code(state.winner));
+    bytes.extend_from_slice(&state.tick.to_le_bytes());
+}
+
+pub(crate) fn read_state(reader: &mut Reader) -> Result<SimState, String> {
+    let ball = Ball {
+        position: reader.vec2()?,
+        velocity: reader.vec2()?,
+        size: reader.vec2()?,
+    };
+    let player = reader.paddle_state()?;
+    let opponent = reader.paddle_state()?;
+    let scoreboard = Scoreboard {
+        player_score: reader.u32()? as usize,
+        opponent_score: reader.u32()? as usize,
+        player_games: reader.u32()? as usize,
+        opponent_games: reader.u32()? as usize,
+    };
+    let phase = match (reader.u8()?, reader.u32()?) {
+        (0, ticks_left) => Phase::Serving { ticks_left },
+        (1, _) => Phase::Rally,
+        (code, _) => return Err(format!("bad phase {} in replay snapshot", code)),
+    };
+    let server = reader.paddle()?.ok_or("replay snapshot has no server")?;
+    let first_server = reader.paddle()?.ok_or("replay snapshot has no first server")?;
+    Ok(SimState {
+        ball,
+        player,
+        opponent,
+        scoreboard,
+        phase,
+        server,
+        first_server,
+        points_played: reader.u32()? as usize,
+        rng: reader.u64()?,
+        winner: reader.paddle()?,
+        tick: reader.u64()?,
+    })
+}
+
+// Consecutive ticks with the same inputs, as (count, inputs)
+fn runs(inputs: &[SimInputs]) -> Vec<(u32, SimInputs)> {
+    let mut runs: Vec<(u32, SimInputs)> = Vec::new();
+    for tick in inputs {
+        match runs.last_mut() {
+            Some((count, last)) if same_bits(last, tick) && *count < u32::MAX => *count += 1,
+            _ => runs.push((1, *tick)),
+        }
+    }
+    runs
+}
+
+// Compare directions bit for bit, so -0.0 comes back as it went in
+fn same_bits(a: &SimInputs, b: &SimInputs) -> bool {
+    a.player.direction.to_bits() == b.player.direction.to_bits()
+        && a.opponent.direction.to_bits() == b.opponent.direction.to_bits()
+        && a.player.serve == b.player.serve
+        && a.opponent.serve == b.opponent.serve
+}
+
+// Also reads network messages
+pub(crate) struct Reader<'a> {
+    pub(crate) bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < len {
+            return Err("replay is cut short".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    // A paddle direction, which the simulation only takes from -1 to 1
+    pub(crate) fn direction(&mut self) -> Result<f32, String> {
+        let direction = self.f32()?;
+        if !direction.is_finite() || !(-1.0..=1.0).contains(&direction) {
+            return Err(format!("replay has a paddle direction of {}, outside -1 to 1", direction));
+        }
+        Ok(direction)
+    }
+
+    pub(crate) fn vec2(&mut self) -> Result<Vec2, String> {
+        Ok(Vec2::new(self.f32()?, self.f32()?))
+    }
+
+    pub(crate) fn paddle(&mut self) -> Result<Option<Paddle>, String> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(Paddle::Player)),
+            2 => Ok(Some(Paddle::Opponent)),
+            code => Err(format!("bad paddle {} in replay snapshot", code)),
+        }
+    }
+
+    fn paddle_state(&mut self) -> Result<PaddleState, String> {
+        Ok(PaddleState {
+            position: self.vec2()?,
+            size: self.vec2()?,
+            speed: self.f32()?,
+            velocity: self.f32()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{Difficulty, OpponentAi};
+
+    fn recorded(inputs: &[SimInputs]) -> Replay {
+        let mut replay = Replay::new(7, PhysicsConfig::default(), MatchRules::default(), "keyboard", "normal");
+        let mut sim = replay.start();
+        for tick in inputs {
+            let events = sim.step(*tick);
+            replay.record(*tick, &sim, &events);
+        }
+        replay
+    }
+
+    fn moving(player: f32, opponent: f32) -> SimInputs {
+        SimInputs {
+            player: PaddleInput { direction: player, serve: false },
+            opponent: PaddleInput { direction: opponent, serve: false },
+        }
+    }
+
+    // A match between two computer players, long enough for a few points
+    fn played(ticks: usize) -> Replay {
+        let mut player = OpponentAi::with_difficulty(Difficulty::Easy, 1);
+        let mut opponent = OpponentAi::with_difficulty(Difficulty::Hard, 2);
+        let mut replay = Replay::new(7, PhysicsConfig::default(), MatchRules::default(), "easy", "hard");
+        let mut sim = replay.start();
+        for _ in 0..ticks {
+            let inputs = SimInputs {
+                player: player.input(&sim, Paddle::Player),
+                opponent: opponent.input(&sim, Paddle::Opponent),
+            };
+            let events = sim.step(inputs);
+            replay.record(inputs, &sim, &events);
+        }
+        replay
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    // Where the first run's tick count sits in `bytes`
+    fn first_run(bytes: &[u8]) -> usize {
+        12 + u32_at(bytes, 8) as usize + 4
+    }
+
+    // Where the runs of inputs end and the snapshot version starts
+    fn runs_end(bytes: &[u8]) -> usize {
+        let runs = first_run(bytes) - 4;
+        runs + 4 + u32_at(bytes, runs) as usize * 13
+    }
+
+    #[test]
+    fn a_huge_run_of_ticks_is_rejected() {
+        let mut bytes = recorded(&[SimInputs::default(); 10]).to_bytes();
+        let run = first_run(&bytes);
+        bytes[run..run + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let error = Replay::from_bytes(&bytes).expect_err("far too long");
+        assert_eq!(error, format!("replay is longer than {} ticks", MAX_TICKS));
+    }
+
+    #[test]
+    fn bad_paddle_directions_are_rejected() {
+        for bad in [f32::NAN, f32::INFINITY, 1.5, -2.0] {
+            let mut bytes = recorded(&[moving(0.5, 0.0)]).to_bytes();
+            let direction = first_run(&bytes) + 4;
+            bytes[direction..direction + 4].copy_from_slice(&bad.to_bits().to_le_bytes());
+            let error = Replay::from_bytes(&bytes).expect_err("bad direction");
+            assert!(error.starts_with("replay has a paddle direction of"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_everything() {
+        let replay = played(3000);
+        assert!(replay.points.len() >= 2);
+        assert!(replay.snapshots.len() > replay.points.len());
+        assert_eq!(replay.hashes.len(), replay.len());
+
+        let loaded = Replay::from_bytes(&replay.to_bytes()).expect("loads");
+        assert_eq!(loaded, replay);
+    }
+
+    #[test]
+    fn version_1_replays_get_their_snapshots_back() {
+        let replay = played(3000);
+        let bytes = replay.to_bytes();
+        // A version 1 file stops after the inputs
+        let mut header = replay.header.clone();
+        header.version = 1;
+        let header = toml::to_string(&header).expect("header");
+        let mut old = MAGIC.to_vec();
+        old.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        old.extend_from_slice(header.as_bytes());
+        old.extend_from_slice(&bytes[first_run(&bytes) - 4..runs_end(&bytes)]);
+
+        let loaded = Replay::from_bytes(&old).expect("loads");
+        assert_eq!(loaded.header.version, REPLAY_VERSION);
+        assert_eq!(loaded.inputs, replay.inputs);
+        assert_eq!(loaded.points, replay.points);
+        assert_eq!(loaded.snapshots, replay.snapshots);
+        // Nothing recorded to check against
+        assert!(loaded.hashes.is_empty());
+        assert_eq!(loaded.verify(), Ok(()));
+    }
+
+    #[test]
+    fn unknown_snapshot_versions_are_refused() {
+        let mut bytes = played(600).to_bytes();
+        let at = runs_end(&bytes);
+        assert_eq!(u32_at(&bytes, at), SNAPSHOT_VERSION);
+        bytes[at..at + 4].copy_from_slice(&99u32.to_le_bytes());
+        assert_eq!(Replay::from_bytes(&bytes), Err(format!(
+            "replay snapshots are version 99, but this build reads version {}", SNAPSHOT_VERSION)));
+    }
+
+    #[test]
+    fn newer_replay_versions_are_refused() {
+        let mut replay = played(10);
+        replay.header.version = REPLAY_VERSION + 1;
+        assert_eq!(Replay::from_bytes(&replay.to_bytes()), Err(format!(
+            "replay version {} is not supported (this build reads up to version {})",
+            REPLAY_VERSION + 1, REPLAY_VERSION)));
+    }
+
+    #[test]
+    fn seeking_lands_where_playing_through_does() {
+        let replay = played(3000);
+        let mut sim = replay.start();
+        let mut checked = 0;
+        for (tick, inputs) in replay.inputs.iter().enumerate() {
+            if tick % 97 == 0 || replay.snapshots.iter().any(|snapshot| snapshot.tick == tick) {
+                assert_eq!(replay.seek(tick), sim, "at tick {}", tick);
+                checked += 1;
+            }
+            sim.step(*inputs);
+        }
+        assert_eq!(replay.seek(replay.len()), sim);
+        assert_eq!(replay.seek(replay.len() + 100), sim);
+        assert!(checked > replay.snapshots.len());
+    }
+
+    #[test]
+    fn a_wrong_hash_is_found_at_its_tick() {
+        let mut replay = played(600);
+        replay.hashes[299] ^= 1;
+        let desync = replay.verify().expect_err("wrong hash");
+        assert_eq!(desync.tick, 300);
+        assert_eq!(desync.replayed_hash, replay.hashes[299] ^ 1);
+        assert_eq!(desync.before, replay.seek(299).state());
+        assert_eq!(desync.replayed, replay.seek(300).state());
+    }
+
+    #[test]
+    fn a_changed_input_shows_up_in_the_diff_at_its_tick() {
+        let mut replay = played(600);
+        // The player holds still through the serve countdown; have it move up once instead
+        assert_eq!(replay.inputs[9].player.direction, 0.0);
+        replay.inputs[9].player.direction = 1.0;
+        let desync = replay.verify().expect_err("changed input");
+        assert_eq!(desync.tick, 10);
+        assert_eq!(desync.recorded, None);
+        let diff = desync.diff();
+        assert!(diff.contains(&format!("player.position: at tick 9 {:?}, replayed {:?}",
+            desync.before.player.position, desync.replayed.player.position)), "{:?}", diff);
+        assert!(!diff.iter().any(|line| line.starts_with("ball") || line.starts_with("opponent")),
+            "{:?}", diff);
+
+        // With a snapshot there the recording's own state is compared
+        let mut snapshotted = played(600);
+        let point = snapshotted.snapshots[0].tick;
+        let input = &mut snapshotted.inputs[point - 1].opponent;
+        // The other way, or moving if it was holding still
+        input.direction = if input.direction > 0.0 { -1.0 } else { 1.0 };
+        let desync = snapshotted.verify().expect_err("changed input");
+        assert_eq!(desync.tick, point);
+        assert_eq!(desync.recorded, Some(snapshotted.snapshots[0].state));
+        assert!(desync.diff().iter().any(|line| line.starts_with("opponent.position: recorded")));
+    }
+}
@@ -0,0 +1,664 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::{prelude::*, ui::FocusPolicy};
+
+use pong::ai::Difficulty;
+use pong::physics::PhysicsConfig;
+use pong::replay::{Replay, REPLAY_EXTENSION};
+use pong::rules::MatchRules;
+use pong::sim::{PongSim, SimEvent, SimInputs};
+
+use crate::GameState;
+use crate::bindings::{Action, Bindings};
+use crate::controllers::{SeatKind, SeatSetup};
+use crate::main_menu::{spawn_button, spawn_menu_column, spawn_sized_button};
+use crate::menu_nav::{back_pressed, MenuFocus};
+use crate::pause_game;
+use crate::widgets::spawn_group;
+
+pub struct ReplaysPlugin;
+
+// Newest first, as many to a page as fit on screen
+const LISTED_REPLAYS: usize = 6;
+
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+// Inputs of the match being played, saved to the replays directory once
+// the match is over. Empty while a replay is being watched.
+#[derive(Default)]
+pub struct Recording(pub Option<Replay>);
+
+impl Recording {
+    pub fn new(
+        setup: &SeatSetup,
+        difficulty: Difficulty,
+        seed: u64,
+        physics: PhysicsConfig,
+        rules: MatchRules,
+    ) -> Recording {
+        Recording(Some(Replay::new(seed, physics, rules,
+            &describe_seat(setup.player, difficulty),
+            &describe_seat(setup.opponent, difficulty))))
+    }
+
+    pub fn record(&mut self, inputs: SimInputs, sim: &PongSim, events: &[SimEvent]) {
+        if let Some(replay) = &mut self.0 {
+            replay.record(inputs, sim, events);
+        }
+    }
+
+    // Write the recording out, if anything happened in it, and stop
+    pub fn save(&mut self) {
+        let replay = match self.0.take() {
+            Some(replay) if !replay.is_empty() => replay,
+            _ => return,
+        };
+        let dir = match Replay::default_dir() {
+            Some(dir) => dir,
+            None => return,
+        };
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis())
+            .unwrap_or_default();
+        let path = dir.join(format!("{}.{}", millis, REPLAY_EXTENSION));
+        if let Err(error) = replay.save(&path) {
+            println!("Could not save replay to {}: {}", path.display(), error);
+        }
+    }
+}
+
+// What played a side, for the replay header and list
+fn describe_seat(kind: SeatKind, difficulty: Difficulty) -> String {
+    match kind {
+        SeatKind::Computer => format!("{} ({})", kind.name(), difficulty.name()),
+        _ => kind.name().to_string(),
+    }
+}
+
+// Back from a description to the kind of seat, so the HUD can name the
+// sides of a replay the way it did the match
+fn seat_from_description(description: &str) -> SeatKind {
+    let kind = description.split(' ').next().unwrap_or_default().to_lowercase();
+    if kind == "online" {
+        return SeatKind::Remote;
+    }
+    kind.parse().unwrap_or(SeatKind::Computer)
+}
+
+// A replay being watched in place of a match being played
+#[derive(Default)]
+pub struct Playback(pub Option<ReplayPlayer>);
+
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    // Next tick to play
+    pub tick: usize,
+    // Index into `SPEEDS`
+    speed: usize,
+    pub paused: bool,
+    // Ticks asked for one at a time while paused
+    steps: u32,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> ReplayPlayer {
+        ReplayPlayer {
+            replay,
+            tick: 0,
+            speed: 2,
+            paused: false,
+            steps: 0,
+        }
+    }
+
+    pub fn setup(&self) -> SeatSetup {
+        SeatSetup {
+            player: seat_from_description(&self.replay.header.player),
+            opponent: seat_from_description(&self.replay.header.opponent),
+        }
+    }
+
+    // The match as it was `tick` ticks in
+    pub fn seek(&mut self, tick: usize) -> PongSim {
+        self.tick = tick.min(self.replay.len());
+        self.replay.seek(self.tick)
+    }
+
+    // Start of the next point, if there is one
+    pub fn next_point(&self) -> Option<usize> {
+        self.replay.points.iter().copied().find(|tick| *tick > self.tick)
+    }
+
+    // Start of the point being played, or of the one before if this one
+    // has only just started, so pressing again keeps going back
+    pub fn previous_point(&self) -> usize {
+        let grace = (1.0 / self.replay.header.physics.time_step()) as usize;
+        self.replay.points.iter().copied()
+            .rev()
+            .find(|tick| *tick + grace < self.tick)
+            .unwrap_or(0)
+    }
+
+    // How far through the replay, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        if self.replay.is_empty() {
+            return 1.0;
+        }
+        self.tick as f32 / self.replay.len() as f32
+    }
+
+    pub fn speed(&self) -> f32 {
+        SPEEDS[self.speed]
+    }
+
+    // How fast play time runs against real time
+    pub fn time_scale(&self) -> f32 {
+        if self.paused { 0.0 } else { self.speed() }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick >= self.replay.len()
+    }
+
+    // Whether a single step is owed, using it up
+    pub fn take_step(&mut self) -> bool {
+        if self.steps == 0 {
+            return false;
+        }
+        self.steps -= 1;
+        true
+    }
+
+    // Inputs for the next tick, or none once the recording runs out
+    pub fn next_inputs(&mut self) -> Option<SimInputs> {
+        let inputs = self.replay.inputs.get(self.tick).copied();
+        if inputs.is_some() {
+            self.tick += 1;
+        }
+        inputs
+    }
+}
+
+#[derive(Component)]
+struct ReplaysEntity;
+
+#[derive(Component)]
+struct ReplayButton(PathBuf);
+
+#[derive(Component)]
+struct ReplaysBackButton;
+
+// Moves the list this many pages towards older replays
+#[derive(Component)]
+struct PageButton(i32);
+
+// Which page of the list is showing
+#[derive(Default)]
+struct ReplaysPage {
+    index: usize,
+    // The page button just clicked, to keep the focus on once the screen
+    // is rebuilt for the new page
+    stepped: Option<i32>,
+}
+
+#[derive(Component)]
+struct PlaybackEntity;
+
+#[derive(Component)]
+struct PlaybackText;
+
+// Bar along the bottom of the screen showing where playback is. Click or
+// drag on it to go somewhere else.
+#[derive(Component)]
+struct Timeline;
+
+#[derive(Component)]
+struct TimelineFill;
+
+// Jump the replay being watched to a tick
+pub struct SeekReplay(pub usize);
+
+impl Plugin for ReplaysPlugin {
+    fn build(&self, app: &mut App) {
+        println!("Building replays!");
+        app
+        .init_resource::<Recording>()
+        .init_resource::<Playback>()
+        .init_resource::<ReplaysPage>()
+        .add_event::<SeekReplay>()
+        .add_system_set(
+            SystemSet::on_enter(GameState::Replays)
+                .with_system(setup_replays)
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Replays)
+                .with_system(replay_button_sys)
+                .with_system(page_button_sys)
+                .with_system(replays_back_sys)
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Replays)
+                .with_system(teardown_replays)
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::InGame)
+                .with_system(setup_playback_ui)
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::InGame)
+                .with_system(playback_controls.before(pause_game))
+                .with_system(scrub_timeline)
+                .with_system(update_playback_text.after(playback_controls))
+                .with_system(update_timeline)
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::MainMenu)
+                .with_system(stop_playback)
+        );
+    }
+}
+
+// Replay files in the replays directory, newest first
+fn list_replays() -> Vec<PathBuf> {
+    let dir = match Replay::default_dir() {
+        Some(dir) => dir,
+        None => return Vec::new(),
+    };
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == REPLAY_EXTENSION))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    // Named by the time they were saved
+    paths.sort();
+    paths.reverse();
+    paths
+}
+
+fn get_replay_str(replay: &Replay) -> String {
+    let seconds = replay.duration() as u32;
+    format!("{} vs {}, {}:{:02}", replay.header.player, replay.header.opponent,
+        seconds / 60, seconds % 60)
+}
+
+fn setup_replays(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut page: ResMut<ReplaysPage>,
+    mut focus: ResMut<MenuFocus>,
+) {
+    let title = commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "Replays",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 60.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            )
+            .with_style(Style {
+                margin: UiRect::all(Val::Px(10.0)),
+                ..default()
+            }),
+        )
+        .id();
+
+    let paths = list_replays();
+    let pages = paths.len().div_ceil(LISTED_REPLAYS).max(1);
+    // Replays may have been deleted since the page was picked
+    page.index = page.index.min(pages - 1);
+
+    let mut children = vec![title];
+    for path in paths.into_iter().skip(page.index * LISTED_REPLAYS).take(LISTED_REPLAYS) {
+        // Unreadable files are left out rather than failing when picked
+        let replay = match Replay::load(&path) {
+            Ok(replay) => replay,
+            Err(error) => {
+                println!("Skipping replay: {}", error);
+                continue;
+            }
+        };
+        children.push(spawn_sized_button(&mut commands, &asset_server, &get_replay_str(&replay),
+            Size::new(Val::Px(600.0), Val::Px(50.0)), 28.0)
+            .insert(ReplayButton(path))
+            .id());
+    }
+    if children.len() == 1 {
+        children.push(commands
+            .spawn_bundle(TextBundle::from_section(
+                "No replays yet",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ))
+            .id());
+    }
+    if pages > 1 {
+        let button_size = Size::new(Val::Px(160.0), Val::Px(50.0));
+        let mut row = Vec::new();
+        let mut page_buttons = Vec::new();
+        if page.index > 0 {
+            let newer = spawn_sized_button(&mut commands, &asset_server, "< Newer", button_size, 28.0)
+                .insert(PageButton(-1))
+                .id();
+            row.push(newer);
+            page_buttons.push((-1, newer));
+        }
+        row.push(commands
+            .spawn_bundle(TextBundle::from_section(
+                format!("Page {} of {}", page.index + 1, pages),
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 28.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ))
+            .id());
+        if page.index + 1 < pages {
+            let older = spawn_sized_button(&mut commands, &asset_server, "Older >", button_size, 28.0)
+                .insert(PageButton(1))
+                .id();
+            row.push(older);
+            page_buttons.push((1, older));
+        }
+        children.push(spawn_group(&mut commands, FlexDirection::Row)
+            .push_children(&row)
+            .id());
+
+        // Stay on the button used to get here, or the other one at either end
+        if let Some(stepped) = page.stepped.take() {
+            focus.0 = page_buttons.iter()
+                .find(|(step, _)| *step == stepped)
+                .or(page_buttons.first())
+                .map(|(_, button)| *button);
+        }
+    }
+    children.push(spawn_button(&mut commands, &asset_server, "Back")
+        .insert(ReplaysBackButton)
+        .id());
+
+    spawn_menu_column(&mut commands)
+        .push_children(&children)
+        .insert(ReplaysEntity);
+}
+
+// Watch the picked replay on the game screen
+fn replay_button_sys(
+    interaction_query: Query<(&Interaction, &ReplayButton), Changed<Interaction>>,
+    mut playback: ResMut<Playback>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        match Replay::load(&button.0) {
+            // Back or a page button may have queued a transition this frame
+            // already; that one wins
+            Ok(replay) => {
+                if state.set(GameState::InGame).is_ok() {
+                    playback.0 = Some(ReplayPlayer::new(replay));
+                }
+            }
+            Err(error) => println!("Could not play replay: {}", error),
+        }
+    }
+}
+
+// Show the next or previous page by building the screen again
+fn page_button_sys(
+    interaction_query: Query<(&Interaction, &PageButton), Changed<Interaction>>,
+    mut page: ResMut<ReplaysPage>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for (interaction, button) in &interaction_query {
+        // Back or a replay may have been picked this frame already
+        if *interaction == Interaction::Clicked && state.restart().is_ok() {
+            page.index = page.index.saturating_add_signed(button.0 as isize);
+            page.stepped = Some(button.0);
+        }
+    }
+}
+
+fn replays_back_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<ReplaysBackButton>)>,
+    mut keys: ResMut<Input<KeyCode>>,
+    bindings: Res<Bindings>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut page: ResMut<ReplaysPage>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let clicked = interaction_query.iter().any(|interaction| *interaction == Interaction::Clicked);
+    if clicked || back_pressed(&keys, &bindings, &gamepad_buttons) {
+        // Turning the page may have queued a transition this frame already;
+        // that one wins
+        if state.set(GameState::MainMenu).is_ok() {
+            // The newest replays first next time
+            page.index = 0;
+        }
+        keys.reset(bindings.key(Action::Back));
+    }
+}
+
+fn teardown_replays(
+    mut commands: Commands,
+    query: Query<Entity, With<ReplaysEntity>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+const TIMELINE_LEFT: f32 = 5.0;
+const TIMELINE_WIDTH: f32 = 90.0;
+
+fn setup_playback_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    playback: Res<Playback>,
+) {
+    let player = match &playback.0 {
+        Some(player) => player,
+        None => return,
+    };
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 20.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(5.0),
+                    left: Val::Percent(TIMELINE_LEFT),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(PlaybackText)
+        .insert(PlaybackEntity);
+
+    let full = Size::new(Val::Percent(100.0), Val::Percent(100.0));
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(TIMELINE_WIDTH), Val::Px(14.0)),
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(32.0),
+                    left: Val::Percent(TIMELINE_LEFT),
+                    ..default()
+                },
+                ..default()
+            },
+            color: Color::rgba(0.15, 0.15, 0.15, 0.8).into(),
+            ..default()
+        })
+        .insert(Interaction::default())
+        .insert(Timeline)
+        .insert(PlaybackEntity)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                        ..default()
+                    },
+                    color: Color::rgb(0.9, 0.1, 0.1).into(),
+                    focus_policy: FocusPolicy::Pass,
+                    ..default()
+                })
+                .insert(TimelineFill);
+            // A notch where each point starts
+            for tick in &player.replay.points {
+                let at = *tick as f32 / player.replay.len().max(1) as f32 * 100.0;
+                parent.spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(2.0), full.height),
+                        position_type: PositionType::Absolute,
+                        position: UiRect {
+                            left: Val::Percent(at),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    color: Color::rgb(0.9, 0.9, 0.1).into(),
+                    focus_policy: FocusPolicy::Pass,
+                    ..default()
+                });
+            }
+        });
+}
+
+// The pause key pauses the replay rather than opening the pause menu.
+// Right steps one tick at a time, up and down change the speed, and the
+// square brackets go back and forward a point.
+fn playback_controls(
+    mut keys: ResMut<Input<KeyCode>>,
+    bindings: Res<Bindings>,
+    mut playback: ResMut<Playback>,
+    mut seek_events: EventWriter<SeekReplay>,
+) {
+    let player = match &mut playback.0 {
+        Some(player) => player,
+        None => return,
+    };
+    if bindings.just_pressed(&keys, Action::Pause) {
+        player.paused = !player.paused;
+    }
+    // Held, it would open the pause menu
+    keys.reset(bindings.key(Action::Pause));
+
+    if keys.just_pressed(KeyCode::Right) {
+        player.paused = true;
+        player.steps += 1;
+    }
+    if keys.just_pressed(KeyCode::Up) {
+        player.speed = (player.speed + 1).min(SPEEDS.len() - 1);
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        player.speed = player.speed.saturating_sub(1);
+    }
+    if keys.just_pressed(KeyCode::LBracket) {
+        seek_events.send(SeekReplay(player.previous_point()));
+    }
+    if keys.just_pressed(KeyCode::RBracket) {
+        if let Some(tick) = player.next_point() {
+            seek_events.send(SeekReplay(tick));
+        }
+    }
+}
+
+// Seek to wherever the timeline is being clicked or dragged
+fn scrub_timeline(
+    timeline_query: Query<&Interaction, With<Timeline>>,
+    windows: Res<Windows>,
+    playback: Res<Playback>,
+    mut seek_events: EventWriter<SeekReplay>,
+) {
+    let player = match &playback.0 {
+        Some(player) => player,
+        None => return,
+    };
+    let dragging = timeline_query.iter().any(|interaction| *interaction == Interaction::Clicked);
+    let window = match windows.get_primary() {
+        Some(window) if dragging => window,
+        _ => return,
+    };
+    if let Some(cursor) = window.cursor_position() {
+        let left = window.width() * TIMELINE_LEFT / 100.0;
+        let width = window.width() * TIMELINE_WIDTH / 100.0;
+        let fraction = ((cursor.x - left) / width).clamp(0.0, 1.0);
+        let tick = (fraction * player.replay.len() as f32).round() as usize;
+        if tick != player.tick {
+            seek_events.send(SeekReplay(tick));
+        }
+    }
+}
+
+fn update_timeline(
+    playback: Res<Playback>,
+    mut fill_query: Query<&mut Style, With<TimelineFill>>,
+) {
+    let player = match &playback.0 {
+        Some(player) if playback.is_changed() => player,
+        _ => return,
+    };
+    for mut style in &mut fill_query {
+        style.size.width = Val::Percent(player.progress() * 100.0);
+    }
+}
+
+fn get_playback_str(player: &ReplayPlayer, bindings: &Bindings) -> String {
+    let status = if player.is_finished() {
+        "ended".to_string()
+    } else if player.paused {
+        "paused".to_string()
+    } else {
+        format!("{}x", player.speed())
+    };
+    format!("Replay {} | {:?}: pause, Right: step, Up/Down: speed, [ ]: previous/next point",
+        status, bindings.key(Action::Pause))
+}
+
+fn update_playback_text(
+    playback: Res<Playback>,
+    bindings: Res<Bindings>,
+    mut text_query: Query<&mut Text, With<PlaybackText>>,
+) {
+    let player = match &playback.0 {
+        Some(player) => player,
+        None => return,
+    };
+    let value = get_playback_str(player, &bindings);
+    for mut text in &mut text_query {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+fn stop_playback(
+    mut commands: Commands,
+    mut playback: ResMut<Playback>,
+    query: Query<Entity, With<PlaybackEntity>>,
+) {
+    playback.0 = None;
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,376 @@
+//! Rollback for two players on different machines. Each side steps the
+//! simulation straight away with its own input and a guess at the other
+//! side's, and when the real input turns up and the guess was wrong, goes
+//! back to the last tick both inputs were known and plays forward again.
+//!
+//! This is only the bookkeeping; [`crate::net`] carries the inputs between
+//! the two sides. Both sides must start from the same [`PongSim`].
+
+use std::collections::VecDeque;
+
+use crate::replay::state_hash;
+use crate::sim::{Paddle, PaddleInput, PongSim, SimEvent, SimInputs};
+
+/// Confirmed tick hashes kept to compare against the other side's.
+const HASH_HISTORY: usize = 1024;
+
+/// A tick played with both sides' real inputs, so it will never change.
+#[derive(Debug, Clone)]
+pub struct ConfirmedTick {
+    pub inputs: SimInputs,
+    /// The simulation after the tick.
+    pub sim: PongSim,
+    pub events: Vec<SimEvent>,
+}
+
+/// How the guessing is going.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RollbackStats {
+    /// Times a wrong guess was played back over.
+    pub rollbacks: u64,
+    /// Ticks replayed by the latest rollback.
+    pub last_rollback: usize,
+    pub longest_rollback: usize,
+    /// Ticks that couldn't be played because the other side was too far
+    /// behind.
+    pub stalls: u64,
+    /// First confirmed tick the other side's hash disagreed with, if any.
+    /// From then on the two sides are playing different matches.
+    pub desync: Option<usize>,
+}
+
+pub struct RollbackSession {
+    local_side: Paddle,
+    input_delay: usize,
+    max_prediction: usize,
+    /// Every tick up to `confirmed_tick` played with real inputs.
+    confirmed: PongSim,
+    confirmed_tick: usize,
+    /// What is on screen, `tick` ticks in.
+    predicted: PongSim,
+    tick: usize,
+    /// Local inputs by tick. Runs `input_delay` ticks ahead of `tick`.
+    local: Vec<PaddleInput>,
+    /// Remote inputs by tick, as far as they have arrived in order.
+    remote: Vec<PaddleInput>,
+    /// Remote inputs `predicted` was played with, by tick.
+    guesses: Vec<PaddleInput>,
+    newly_confirmed: Vec<ConfirmedTick>,
+    hashes: VecDeque<(usize, u32)>,
+    stats: RollbackStats,
+}
+
+impl RollbackSession {
+    /// `input_delay` holds local inputs back that many ticks, giving the
+    /// other side's a head start so fewer guesses are needed. No more than
+    /// `max_prediction` ticks are played on guesses before waiting.
+    pub fn new(
+        sim: PongSim,
+        local_side: Paddle,
+        input_delay: usize,
+        max_prediction: usize,
+    ) -> RollbackSession {
+        RollbackSession {
+            local_side,
+            input_delay,
+            max_prediction: max_prediction.max(1),
+            confirmed: sim.clone(),
+            confirmed_tick: 0,
+            predicted: sim,
+            tick: 0,
+            // Nothing is pressed in the ticks before the first delayed input
+            local: vec![PaddleInput::default(); input_delay],
+            remote: Vec::new(),
+            guesses: Vec::new(),
+            newly_confirmed: Vec::new(),
+            hashes: VecDeque::new(),
+            stats: RollbackStats::default(),
+        }
+    }
+
+    pub fn local_side(&self) -> Paddle {
+        self.local_side
+    }
+
+    pub fn input_delay(&self) -> usize {
+        self.input_delay
+    }
+
+    /// The simulation to show, including guessed ticks.
+    pub fn sim(&self) -> &PongSim {
+        &self.predicted
+    }
+
+    /// The simulation as far as both sides' inputs are known.
+    pub fn confirmed(&self) -> &PongSim {
+        &self.confirmed
+    }
+
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+
+    pub fn confirmed_tick(&self) -> usize {
+        self.confirmed_tick
+    }
+
+    pub fn stats(&self) -> RollbackStats {
+        self.stats
+    }
+
+    /// Local inputs by tick, including those held back by the delay.
+    pub fn local_inputs(&self) -> &[PaddleInput] {
+        &self.local
+    }
+
+    /// Number of remote inputs received in order, which is the tick the
+    /// next one is for.
+    pub fn remote_received(&self) -> usize {
+        self.remote.len()
+    }
+
+    /// The latest confirmed tick and its hash, for the other side to check.
+    pub fn latest_hash(&self) -> Option<(usize, u32)> {
+        self.hashes.back().copied()
+    }
+
+    /// Whether the other side is too far behind to guess any further
+    /// ahead, so the next tick has to wait.
+    pub fn is_waiting(&self) -> bool {
+        self.tick >= self.remote.len() + self.max_prediction
+    }
+
+    /// Play the next tick, asking `input` for the local side's input given
+    /// the simulation shown. None, without asking, while waiting on the
+    /// other side.
+    pub fn step(&mut self, input: impl FnOnce(&PongSim) -> PaddleInput) -> Option<Vec<SimEvent>> {
+        if self.is_waiting() {
+            self.stats.stalls += 1;
+            return None;
+        }
+        let input = input(&self.predicted);
+        self.local.push(input);
+        let guess = self.guess(self.tick);
+        self.guesses.push(guess);
+        let events = self.predicted.step(self.inputs(self.local[self.tick], guess));
+        self.tick += 1;
+        self.confirm();
+        Some(events)
+    }
+
+    /// Remote inputs starting at tick `first`. Ones already seen are
+    /// skipped, and ones after a gap are dropped until the gap is filled.
+    pub fn receive(&mut self, first: usize, inputs: &[PaddleInput]) {
+        for (tick, input) in (first..).zip(inputs) {
+            if tick == self.remote.len() {
+                self.remote.push(*input);
+            }
+        }
+        self.confirm();
+    }
+
+    /// Compare the other side's hash of a confirmed tick with ours.
+    pub fn check_hash(&mut self, tick: usize, hash: u32) {
+        if self.stats.desync.is_some() {
+            return;
+        }
+        let ours = self.hashes.iter().find(|(ours, _)| *ours == tick);
+        if let Some((_, ours)) = ours {
+            if *ours != hash {
+                self.stats.desync = Some(tick);
+            }
+        }
+    }
+
+    /// Ticks confirmed since last asked, in order.
+    pub fn take_confirmed(&mut self) -> Vec<ConfirmedTick> {
+        std::mem::take(&mut self.newly_confirmed)
+    }
+
+    // The remote side is guessed to carry on doing what it last did,
+    // without serving
+    fn guess(&self, tick: usize) -> PaddleInput {
+        if let Some(input) = self.remote.get(tick) {
+            return *input;
+        }
+        let direction = self.remote.last().map_or(0.0, |input| input.direction);
+        PaddleInput { direction, serve: false }
+    }
+
+    fn inputs(&self, local: PaddleInput, remote: PaddleInput) -> SimInputs {
+        match self.local_side {
+            Paddle::Player => SimInputs { player: local, opponent: remote },
+            Paddle::Opponent => SimInputs { player: remote, opponent: local },
+        }
+    }
+
+    // Play the confirmed simulation on through every tick that now has
+    // both inputs, and if any of them was guessed wrong, play the guessed
+    // ticks again from there
+    fn confirm(&mut self) {
+        let end = self.remote.len().min(self.tick);
+        if end <= self.confirmed_tick {
+            return;
+        }
+        let wrong = (self.confirmed_tick..end).find(|tick| self.guesses[*tick] != self.remote[*tick]);
+
+        for tick in self.confirmed_tick..end {
+            let inputs = self.inputs(self.local[tick], self.remote[tick]);
+            let events = self.confirmed.step(inputs);
+            self.hashes.push_back((tick + 1, state_hash(&self.confirmed.state())));
+            if self.hashes.len() > HASH_HISTORY {
+                self.hashes.pop_front();
+            }
+            self.newly_confirmed.push(ConfirmedTick { inputs, sim: self.confirmed.clone(), events });
+        }
+        self.confirmed_tick = end;
+
+        if let Some(wrong) = wrong {
+            self.predicted = self.confirmed.clone();
+            for tick in end..self.tick {
+                let guess = self.guess(tick);
+                self.guesses[tick] = guess;
+                self.predicted.step(self.inputs(self.local[tick], guess));
+            }
+            self.stats.rollbacks += 1;
+            self.stats.last_rollback = self.tick - wrong;
+            self.stats.longest_rollback = self.stats.longest_rollback.max(self.stats.last_rollback);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{Difficulty, OpponentAi};
+    use crate::physics::PhysicsConfig;
+    use crate::rng::SimRng;
+    use crate::rules::MatchRules;
+
+    // Inputs from `first` on, and how many of the other side's have arrived
+    struct Packet {
+        arrives: usize,
+        first: usize,
+        inputs: Vec<PaddleInput>,
+        ack: usize,
+    }
+
+    struct Side {
+        session: RollbackSession,
+        ai: OpponentAi,
+        acked: usize,
+        confirmed: Vec<ConfirmedTick>,
+    }
+
+    impl Side {
+        fn new(sim: &PongSim, paddle: Paddle, difficulty: Difficulty, seed: u64) -> Side {
+            Side {
+                session: RollbackSession::new(sim.clone(), paddle, 2, 8),
+                ai: OpponentAi::with_difficulty(difficulty, seed),
+                acked: 0,
+                confirmed: Vec::new(),
+            }
+        }
+
+        fn step(&mut self) {
+            let side = self.session.local_side();
+            let ai = &mut self.ai;
+            self.session.step(|shown| ai.input(shown, side));
+            self.confirmed.extend(self.session.take_confirmed());
+        }
+
+        fn packet(&self, arrives: usize) -> Packet {
+            let local = self.session.local_inputs();
+            let first = self.acked.min(local.len());
+            Packet { arrives, first, inputs: local[first..].to_vec(), ack: self.session.remote_received() }
+        }
+
+        fn receive(&mut self, packet: &Packet) {
+            self.session.receive(packet.first, &packet.inputs);
+            self.acked = self.acked.max(packet.ack);
+            self.confirmed.extend(self.session.take_confirmed());
+        }
+    }
+
+    // Send a packet that may be lost, or held up past later ones
+    fn send(rng: &mut SimRng, queue: &mut Vec<Packet>, frame: usize, from: &Side) {
+        if rng.next_f32() < 0.25 {
+            return;
+        }
+        queue.push(from.packet(frame + 1 + rng.range_u32(0, 6) as usize));
+    }
+
+    fn deliver(queue: &mut Vec<Packet>, frame: usize, to: &mut Side) {
+        let (due, later): (Vec<_>, Vec<_>) = queue.drain(..).partition(|packet| packet.arrives <= frame);
+        *queue = later;
+        // Newest first, so they arrive out of order
+        for packet in due.iter().rev() {
+            to.receive(packet);
+        }
+    }
+
+    #[test]
+    fn both_sides_confirm_the_same_match_over_a_bad_link() {
+        let sim = PongSim::new(PhysicsConfig::default(), MatchRules::default(), 11);
+        let mut left = Side::new(&sim, Paddle::Player, Difficulty::Hard, 1);
+        let mut right = Side::new(&sim, Paddle::Opponent, Difficulty::Normal, 2);
+        let mut rng = SimRng::new(3);
+        let (mut to_left, mut to_right) = (Vec::new(), Vec::new());
+
+        for frame in 0..5000 {
+            left.step();
+            right.step();
+            send(&mut rng, &mut to_right, frame, &left);
+            send(&mut rng, &mut to_left, frame, &right);
+            deliver(&mut to_left, frame, &mut left);
+            deliver(&mut to_right, frame, &mut right);
+        }
+
+        let ticks = left.confirmed.len().min(right.confirmed.len());
+        assert!(ticks > 4000, "only {} ticks confirmed", ticks);
+        assert!(left.session.stats().rollbacks > 0 && right.session.stats().rollbacks > 0);
+        for (tick, (ours, theirs)) in left.confirmed.iter().zip(&right.confirmed).enumerate() {
+            assert_eq!(ours.inputs, theirs.inputs, "inputs differ at tick {}", tick + 1);
+            assert_eq!(ours.sim.state(), theirs.sim.state(), "states differ at tick {}", tick + 1);
+        }
+
+        // The same as playing the confirmed inputs straight through
+        let mut straight = sim;
+        for confirmed in &left.confirmed[..ticks] {
+            straight.step(confirmed.inputs);
+        }
+        assert_eq!(straight.state(), left.confirmed[ticks - 1].sim.state());
+
+        // And the hashes each side sends agree too
+        let (tick, hash) = left.session.latest_hash().expect("confirmed ticks are hashed");
+        right.session.check_hash(tick, hash);
+        assert_eq!(right.session.stats().desync, None);
+    }
+
+    #[test]
+    fn a_wrong_hash_is_a_desync() {
+        let sim = PongSim::default();
+        let mut session = RollbackSession::new(sim, Paddle::Player, 0, 8);
+        session.step(|_| PaddleInput::default());
+        session.receive(0, &[PaddleInput { direction: 1.0, serve: false }]);
+        let (tick, hash) = session.latest_hash().expect("tick 1 is confirmed");
+        session.check_hash(tick, hash ^ 1);
+        assert_eq!(session.stats().desync, Some(tick));
+        assert_eq!(session.stats().rollbacks, 1);
+    }
+
+    #[test]
+    fn waits_once_too_far_ahead() {
+        let mut session = RollbackSession::new(PongSim::default(), Paddle::Player, 0, 4);
+        for _ in 0..4 {
+            assert!(session.step(|_| PaddleInput::default()).is_some());
+        }
+        assert!(session.is_waiting());
+        assert!(session.step(|_| PaddleInput::default()).is_none());
+        assert_eq!(session.stats().stalls, 1);
+
+        session.receive(0, &[PaddleInput::default(); 2]);
+        assert!(!session.is_waiting());
+        assert_eq!(session.confirmed_tick(), 2);
+    }
+}
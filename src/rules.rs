@@ -0,0 +1,160 @@
+//! How games and matches are won.
+
+use serde::{Deserialize, Serialize};
+
+use crate::physics::check;
+use crate::sim::{Paddle, Scoreboard};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MatchRules {
+    /// Points needed to take a game.
+    pub points_to_win: usize,
+    /// How many points clear of the other side the winner of a game must be.
+    pub win_by: usize,
+    /// Number of games in the match; the first side to win a majority takes it.
+    pub best_of: usize,
+    /// Match length in seconds. When it runs out whoever is ahead wins,
+    /// and a tie is settled by the next point.
+    pub time_limit: Option<f32>,
+    pub serve: ServeRules,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules {
+            points_to_win: 11,
+            win_by: 2,
+            best_of: 1,
+            time_limit: None,
+            serve: ServeRules::default(),
+        }
+    }
+}
+
+/// Who serves the next point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServeRotation {
+    /// Sides swap serve after this many points.
+    AlternateEvery(usize),
+    /// Whoever won the last point serves.
+    WinnerServes,
+    /// Whoever lost the last point serves.
+    LoserServes,
+}
+
+/// Where the ball waits during the countdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServePosition {
+    Centre,
+    /// In front of the serving paddle, following it until launch.
+    Server,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServeRules {
+    pub rotation: ServeRotation,
+    pub position: ServePosition,
+    /// Seconds between the ball being placed and launched.
+    pub countdown: f32,
+    /// Largest launch angle away from horizontal, in degrees.
+    pub max_angle: f32,
+}
+
+impl Default for ServeRules {
+    fn default() -> Self {
+        ServeRules {
+            rotation: ServeRotation::AlternateEvery(2),
+            position: ServePosition::Centre,
+            countdown: 3.0,
+            max_angle: 30.0,
+        }
+    }
+}
+
+impl ServeRules {
+    /// The side to serve after `points_played` points, the last of which
+    /// went to `last_point`.
+    pub fn next_server(&self, first_server: Paddle, points_played: usize,
+        last_point: Option<Paddle>) -> Paddle {
+        match (self.rotation, last_point) {
+            (ServeRotation::WinnerServes, Some(winner)) => winner,
+            (ServeRotation::LoserServes, Some(winner)) => winner.other(),
+            (ServeRotation::AlternateEvery(every), _) => {
+                if (points_played / every.max(1)).is_multiple_of(2) {
+                    first_server
+                } else {
+                    first_server.other()
+                }
+            }
+            (_, None) => first_server,
+        }
+    }
+}
+
+impl MatchRules {
+    /// Reject rules a match can't be played under.
+    pub fn validate(&self) -> Result<(), String> {
+        check(self.points_to_win >= 1, "points_to_win", "at least 1", self.points_to_win)?;
+        check(self.win_by >= 1, "win_by", "at least 1", self.win_by)?;
+        check(self.best_of >= 1, "best_of", "at least 1", self.best_of)?;
+        if let Some(time_limit) = self.time_limit {
+            check(time_limit > 0.0, "time_limit", "positive", time_limit)?;
+        }
+
+        let serve = &self.serve;
+        if let ServeRotation::AlternateEvery(every) = serve.rotation {
+            check(every >= 1, "serve.rotation", "at least 1 point per server", every)?;
+        }
+        check(serve.countdown >= 0.0, "serve.countdown", "zero or more", serve.countdown)?;
+        check((0.0..90.0).contains(&serve.max_angle), "serve.max_angle",
+            "from 0 up to 90 degrees", serve.max_angle)?;
+        Ok(())
+    }
+
+    pub fn games_to_win(&self) -> usize {
+        self.best_of / 2 + 1
+    }
+
+    /// The side that has just taken the current game, if any.
+    pub fn game_winner(&self, scoreboard: &Scoreboard) -> Option<Paddle> {
+        let player = scoreboard.player_score;
+        let opponent = scoreboard.opponent_score;
+
+        if player >= self.points_to_win && player >= opponent + self.win_by {
+            Some(Paddle::Player)
+        } else if opponent >= self.points_to_win && opponent >= player + self.win_by {
+            Some(Paddle::Opponent)
+        } else {
+            None
+        }
+    }
+
+    /// The side that has won enough games to take the match, if any.
+    pub fn match_winner(&self, scoreboard: &Scoreboard) -> Option<Paddle> {
+        if scoreboard.player_games >= self.games_to_win() {
+            Some(Paddle::Player)
+        } else if scoreboard.opponent_games >= self.games_to_win() {
+            Some(Paddle::Opponent)
+        } else {
+            None
+        }
+    }
+
+    pub fn time_is_up(&self, elapsed: f32) -> bool {
+        matches!(self.time_limit, Some(limit) if elapsed >= limit)
+    }
+}
+
+/// The side ahead on games, then on points in the current game.
+pub fn leader(scoreboard: &Scoreboard) -> Option<Paddle> {
+    let player = (scoreboard.player_games, scoreboard.player_score);
+    let opponent = (scoreboard.opponent_games, scoreboard.opponent_score);
+
+    match player.cmp(&opponent) {
+        std::cmp::Ordering::Greater => Some(Paddle::Player),
+        std::cmp::Ordering::Less => Some(Paddle::Opponent),
+        std::cmp::Ordering::Equal => None,
+    }
+}
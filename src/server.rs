@@ -0,0 +1,1166 @@
+//! Matches played on a dedicated server instead of peer to peer. The
+//! server runs the only simulation that counts: two clients join it, send
+//! it their inputs, and get back a snapshot of the match every few ticks,
+//! which they draw a little behind real time so there is always a later
+//! snapshot to interpolate towards.
+//!
+//! Inputs are checked before they are used, and a client that goes quiet
+//! holds the match up for [`ServerConfig::grace`] before it forfeits. It
+//! gets its side back by joining again with the same token, or just by
+//! being heard from again.
+//!
+//! Packets are the magic bytes `PGSV`, a message tag and the message,
+//! little-endian like [`crate::net`]'s.
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::collision::{CollisionEvent, Surface};
+use crate::net::{read_str, write_str, Link, LinkShim, MatchSetup};
+use crate::physics::{check, PhysicsConfig};
+use crate::replay::{paddle_code, read_state, write_state, write_vec2, Reader};
+use crate::rng::SimRng;
+use crate::rules::MatchRules;
+use crate::sim::{Paddle, PaddleInput, PongSim, Scoreboard, SimEvent, SimInputs, SimState, WallLocation};
+
+/// Clients must speak the server's version.
+pub const SERVER_VERSION: u32 = 1;
+
+/// Port the server listens on unless told otherwise.
+pub const DEFAULT_SERVER_PORT: u16 = 7778;
+
+const MAGIC: &[u8; 4] = b"PGSV";
+
+// Clients ask to join this often until the server answers, and again
+// whenever it goes quiet
+const JOIN_INTERVAL: Duration = Duration::from_millis(250);
+const JOIN_TIMEOUT: Duration = Duration::from_secs(30);
+// A client is taken to have dropped out after this long without a packet
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+// The server hears this much less often between ticks
+const IDLE_SNAPSHOT_INTERVAL: Duration = Duration::from_millis(100);
+// A finished match is shown this long before the server makes way for
+// the next one
+const LINGER: Duration = Duration::from_secs(3);
+// After a stall the server slows down rather than catching up all at once
+const MAX_TICKS_PER_UPDATE: u32 = 8;
+
+// Clients draw the match this many snapshots behind the latest
+const INTERPOLATION_SNAPSHOTS: u32 = 2;
+const KEPT_SNAPSHOTS: usize = 32;
+// Further off than this many ticks the view jumps rather than easing back
+const MAX_DRIFT: f64 = 30.0;
+const CATCH_UP: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServerConfig {
+    pub physics: PhysicsConfig,
+    pub rules: MatchRules,
+    /// How long a side that drops out has to come back before it forfeits.
+    pub grace: Duration,
+    /// Ticks between snapshots.
+    pub snapshot_interval: u32,
+    pub shim: LinkShim,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            physics: PhysicsConfig::default(),
+            rules: MatchRules::default(),
+            grace: Duration::from_secs(30),
+            snapshot_interval: 2,
+            shim: LinkShim::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        self.physics.validate().map_err(|error| format!("physics.{}", error))?;
+        self.rules.validate().map_err(|error| format!("rules.{}", error))?;
+        check((1..=60).contains(&self.snapshot_interval), "snapshot_interval", "from 1 to 60",
+            self.snapshot_interval)?;
+        self.shim.validate()
+    }
+}
+
+/// Where the server's match is at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServerStatus {
+    /// Waiting for both sides to join.
+    Waiting,
+    Playing,
+    /// Held while `side` is away. It forfeits in `seconds_left` unless it
+    /// comes back.
+    Reconnecting { side: Paddle, seconds_left: u32 },
+    /// Won by `winner`, or by nobody if both sides went away. A forfeit
+    /// if the other side left or didn't come back in time.
+    Over { winner: Option<Paddle>, forfeit: bool },
+}
+
+/// A match the server saw through to the end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchRecord {
+    /// Counting from 1 since the server started.
+    pub number: u32,
+    pub seed: u64,
+    /// Where each side last played from, left paddle first.
+    pub addresses: [SocketAddr; 2],
+    pub winner: Option<Paddle>,
+    pub forfeit: bool,
+    pub scoreboard: Scoreboard,
+    pub ticks: u64,
+    /// Inputs thrown out as invalid, left paddle first.
+    pub rejected_inputs: [u32; 2],
+}
+
+/// What happened on the server, for its log.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+    Joined { side: Paddle, address: SocketAddr },
+    Started { number: u32, seed: u64 },
+    /// Went quiet; see [`ServerStatus::Reconnecting`].
+    Dropped { side: Paddle },
+    Rejoined { side: Paddle, address: SocketAddr },
+    /// Said it was going.
+    Left { side: Paddle },
+    Finished(MatchRecord),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Message {
+    Join { version: u32, token: u64 },
+    Input { sequence: u32, input: PaddleInput },
+    Leave,
+    Welcome { side: Paddle, snapshot_interval: u32, grace_seconds: u32, setup: String },
+    Reject { reason: String },
+    Snapshot {
+        // Latest input sequence number the server has from this client
+        ack: u32,
+        status: ServerStatus,
+        state: SimState,
+        // What happened since the last snapshot, with the tick it
+        // happened on
+        events: Vec<(u64, SimEvent)>,
+    },
+}
+
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        match self {
+            Message::Join { version, token } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&version.to_le_bytes());
+                bytes.extend_from_slice(&token.to_le_bytes());
+            }
+            Message::Input { sequence, input } => {
+                bytes.push(2);
+                bytes.extend_from_slice(&sequence.to_le_bytes());
+                bytes.extend_from_slice(&input.direction.to_bits().to_le_bytes());
+                bytes.push(input.serve as u8);
+            }
+            Message::Leave => bytes.push(3),
+            Message::Welcome { side, snapshot_interval, grace_seconds, setup } => {
+                bytes.push(4);
+                bytes.push(paddle_code(Some(*side)));
+                bytes.extend_from_slice(&snapshot_interval.to_le_bytes());
+                bytes.extend_from_slice(&grace_seconds.to_le_bytes());
+                write_str(&mut bytes, setup);
+            }
+            Message::Reject { reason } => {
+                bytes.push(5);
+                write_str(&mut bytes, reason);
+            }
+            Message::Snapshot { ack, status, state, events } => {
+                bytes.push(6);
+                bytes.extend_from_slice(&ack.to_le_bytes());
+                write_status(&mut bytes, status);
+                write_state(&mut bytes, state);
+                bytes.extend_from_slice(&(events.len() as u32).to_le_bytes());
+                for (tick, event) in events {
+                    bytes.extend_from_slice(&tick.to_le_bytes());
+                    write_event(&mut bytes, event);
+                }
+            }
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Message, String> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("not a pong server packet".to_string());
+        }
+        let message = match reader.u8()? {
+            1 => Message::Join { version: reader.u32()?, token: reader.u64()? },
+            2 => {
+                let sequence = reader.u32()?;
+                let direction = reader.f32()?;
+                Message::Input { sequence, input: PaddleInput { direction, serve: reader.u8()? != 0 } }
+            }
+            3 => Message::Leave,
+            4 => Message::Welcome {
+                side: reader.paddle()?.ok_or("welcome has no side")?,
+                snapshot_interval: reader.u32()?,
+                grace_seconds: reader.u32()?,
+                setup: read_str(&mut reader)?,
+            },
+            5 => Message::Reject { reason: read_str(&mut reader)? },
+            6 => {
+                let ack = reader.u32()?;
+                let status = read_status(&mut reader)?;
+                let state = read_state(&mut reader)?;
+                let mut events = Vec::new();
+                for _ in 0..reader.u32()? {
+                    let tick = reader.u64()?;
+                    events.push((tick, read_event(&mut reader)?));
+                }
+                Message::Snapshot { ack, status, state, events }
+            }
+            tag => return Err(format!("unknown message {}", tag)),
+        };
+        if !reader.bytes.is_empty() {
+            return Err("packet has trailing bytes".to_string());
+        }
+        Ok(message)
+    }
+}
+
+fn write_status(bytes: &mut Vec<u8>, status: &ServerStatus) {
+    let (code, paddle, number) = match *status {
+        ServerStatus::Waiting => (0, None, 0),
+        ServerStatus::Playing => (1, None, 0),
+        ServerStatus::Reconnecting { side, seconds_left } => (2, Some(side), seconds_left),
+        ServerStatus::Over { winner, forfeit } => (3, winner, forfeit as u32),
+    };
+    bytes.push(code);
+    bytes.push(paddle_code(paddle));
+    bytes.extend_from_slice(&number.to_le_bytes());
+}
+
+fn read_status(reader: &mut Reader) -> Result<ServerStatus, String> {
+    let (code, paddle, number) = (reader.u8()?, reader.paddle()?, reader.u32()?);
+    Ok(match code {
+        0 => ServerStatus::Waiting,
+        1 => ServerStatus::Playing,
+        2 => ServerStatus::Reconnecting {
+            side: paddle.ok_or("reconnecting status has no side")?,
+            seconds_left: number,
+        },
+        3 => ServerStatus::Over { winner: paddle, forfeit: number != 0 },
+        code => return Err(format!("bad server status {}", code)),
+    })
+}
+
+const SURFACES: [Surface; 6] = [
+    Surface::Paddle(Paddle::Player),
+    Surface::Paddle(Paddle::Opponent),
+    Surface::Wall(WallLocation::Left),
+    Surface::Wall(WallLocation::Right),
+    Surface::Wall(WallLocation::Bottom),
+    Surface::Wall(WallLocation::Top),
+];
+
+fn write_event(bytes: &mut Vec<u8>, event: &SimEvent) {
+    let (code, paddle) = match *event {
+        SimEvent::ServeStarted(paddle) => (0, paddle),
+        SimEvent::Served(paddle) => (1, paddle),
+        SimEvent::Collision(collision) => {
+            bytes.push(2);
+            let surface = SURFACES.iter().position(|surface| *surface == collision.surface);
+            bytes.push(surface.unwrap_or_default() as u8);
+            write_vec2(bytes, collision.point);
+            write_vec2(bytes, collision.normal);
+            return;
+        }
+        SimEvent::PointScored(paddle) => (3, paddle),
+        SimEvent::GameWon(paddle) => (4, paddle),
+        SimEvent::MatchWon(paddle) => (5, paddle),
+    };
+    bytes.push(code);
+    bytes.push(paddle_code(Some(paddle)));
+}
+
+fn read_event(reader: &mut Reader) -> Result<SimEvent, String> {
+    let code = reader.u8()?;
+    if code == 2 {
+        let surface = *SURFACES.get(reader.u8()? as usize).ok_or("bad collision surface")?;
+        return Ok(SimEvent::Collision(CollisionEvent {
+            surface,
+            point: reader.vec2()?,
+            normal: reader.vec2()?,
+        }));
+    }
+    let paddle = reader.paddle()?.ok_or("event has no side")?;
+    Ok(match code {
+        0 => SimEvent::ServeStarted(paddle),
+        1 => SimEvent::Served(paddle),
+        3 => SimEvent::PointScored(paddle),
+        4 => SimEvent::GameWon(paddle),
+        5 => SimEvent::MatchWon(paddle),
+        code => return Err(format!("unknown event {}", code)),
+    })
+}
+
+fn receive(link: &mut Link) -> Vec<(SocketAddr, Message)> {
+    link.poll()
+        .into_iter()
+        .filter_map(|(from, bytes)| Message::decode(&bytes).ok().map(|message| (from, message)))
+        .collect()
+}
+
+fn side_index(side: Paddle) -> usize {
+    match side {
+        Paddle::Player => 0,
+        Paddle::Opponent => 1,
+    }
+}
+
+// A client playing one side
+struct Seat {
+    // Picked by the client, so it can claim the seat back from another
+    // address
+    token: u64,
+    address: SocketAddr,
+    last_heard: Instant,
+    // When it went quiet, while it is away
+    dropped: Option<Instant>,
+    sequence: u32,
+    input: PaddleInput,
+    // A serve asked for since the last tick, kept even if a later input
+    // without it arrives first
+    serve: bool,
+    rejected: u32,
+}
+
+/// The authoritative side: one match at a time between the first two
+/// clients to join, then the next.
+pub struct MatchServer {
+    link: Link,
+    config: ServerConfig,
+    seeds: SimRng,
+    number: u32,
+    seed: u64,
+    sim: PongSim,
+    seats: [Option<Seat>; 2],
+    status: ServerStatus,
+    // Real time owed to the simulation, in seconds
+    accumulator: f32,
+    last_update: Instant,
+    over_at: Option<Instant>,
+    // Since the last snapshot
+    events: Vec<(u64, SimEvent)>,
+    last_snapshot: Instant,
+}
+
+impl MatchServer {
+    /// Listen on `port`. Each match gets its seed from `seed`.
+    pub fn bind(port: u16, config: ServerConfig, seed: u64) -> io::Result<MatchServer> {
+        let link = Link::bind(SocketAddr::from(([0, 0, 0, 0], port)), config.shim, seed)?;
+        let mut server = MatchServer {
+            link,
+            config,
+            seeds: SimRng::new(seed),
+            number: 0,
+            seed: 0,
+            sim: PongSim::default(),
+            seats: [None, None],
+            status: ServerStatus::Waiting,
+            accumulator: 0.0,
+            last_update: Instant::now(),
+            over_at: None,
+            events: Vec::new(),
+            last_snapshot: Instant::now(),
+        };
+        server.next_match();
+        Ok(server)
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        self.status
+    }
+
+    /// The port listened on, which the system picks if asked for port 0.
+    pub fn port(&self) -> io::Result<u16> {
+        self.link.local_address().map(|address| address.port())
+    }
+
+    /// Take in what the clients sent, play the ticks that are due and
+    /// send snapshots. Call often, at least once a tick.
+    pub fn update(&mut self) -> Vec<ServerEvent> {
+        let now = Instant::now();
+        let mut log = Vec::new();
+        for (from, message) in receive(&mut self.link) {
+            self.handle(from, message, now, &mut log);
+        }
+        self.check_seats(now, &mut log);
+
+        let elapsed = now.saturating_duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+        match self.status {
+            ServerStatus::Waiting => {
+                let seated = self.seats.iter().flatten().filter(|seat| seat.dropped.is_none()).count();
+                if seated == 2 {
+                    self.status = ServerStatus::Playing;
+                    self.accumulator = 0.0;
+                    log.push(ServerEvent::Started { number: self.number, seed: self.seed });
+                }
+            }
+            ServerStatus::Playing => {
+                if let Some((side, _)) = self.away() {
+                    self.status = ServerStatus::Reconnecting {
+                        side,
+                        seconds_left: self.config.grace.as_secs() as u32,
+                    };
+                } else {
+                    self.accumulator += elapsed;
+                    self.play(now, &mut log);
+                }
+            }
+            ServerStatus::Reconnecting { .. } => match self.away() {
+                // Carry on from where it stopped, without catching up
+                None => self.status = ServerStatus::Playing,
+                Some((side, dropped)) => {
+                    let gone = now.saturating_duration_since(dropped);
+                    if gone >= self.config.grace {
+                        let both = self.seats.iter().flatten().all(|seat| seat.dropped.is_some());
+                        let winner = if both { None } else { Some(side.other()) };
+                        self.finish(winner, true, now, &mut log);
+                    } else {
+                        let seconds_left = (self.config.grace - gone).as_secs_f32().ceil() as u32;
+                        self.status = ServerStatus::Reconnecting { side, seconds_left };
+                    }
+                }
+            },
+            ServerStatus::Over { .. } => {
+                if self.over_at.is_some_and(|over_at| now.saturating_duration_since(over_at) >= LINGER) {
+                    self.next_match();
+                }
+            }
+        }
+
+        // Between ticks the clients still need to hear how things stand
+        if self.status != ServerStatus::Playing
+            && now.saturating_duration_since(self.last_snapshot) >= IDLE_SNAPSHOT_INTERVAL
+        {
+            self.broadcast(now);
+        }
+        self.link.flush();
+        log
+    }
+
+    fn next_match(&mut self) {
+        self.number += 1;
+        self.seed = self.seeds.next_u64();
+        self.sim = PongSim::new(self.config.physics, self.config.rules, self.seed);
+        self.seats = [None, None];
+        self.status = ServerStatus::Waiting;
+        self.over_at = None;
+        self.events.clear();
+    }
+
+    fn handle(&mut self, from: SocketAddr, message: Message, now: Instant, log: &mut Vec<ServerEvent>) {
+        match message {
+            Message::Join { version, token } => {
+                if version != SERVER_VERSION {
+                    let reason = format!("server speaks version {}, not {}", SERVER_VERSION, version);
+                    self.link.send(from, Message::Reject { reason }.encode());
+                    return;
+                }
+                let side = match self.seat_with(|seat| seat.token == token) {
+                    Some(side) => {
+                        let seat = self.seats[side_index(side)].as_mut().expect("seat was just found");
+                        // From somewhere new it's a new client, counting
+                        // its inputs from the start
+                        if seat.address != from {
+                            seat.address = from;
+                            seat.sequence = 0;
+                        }
+                        seat.last_heard = now;
+                        let over = matches!(self.status, ServerStatus::Over { .. });
+                        if seat.dropped.take().is_some() && !over {
+                            log.push(ServerEvent::Rejoined { side, address: from });
+                        }
+                        side
+                    }
+                    // Seats only go to new clients before the match starts
+                    None => {
+                        let free = [Paddle::Player, Paddle::Opponent]
+                            .into_iter()
+                            .find(|side| self.seats[side_index(*side)].is_none());
+                        match (self.status, free) {
+                            (ServerStatus::Waiting, Some(side)) => {
+                                self.seats[side_index(side)] = Some(Seat {
+                                    token,
+                                    address: from,
+                                    last_heard: now,
+                                    dropped: None,
+                                    sequence: 0,
+                                    input: PaddleInput::default(),
+                                    serve: false,
+                                    rejected: 0,
+                                });
+                                log.push(ServerEvent::Joined { side, address: from });
+                                side
+                            }
+                            // The next match takes new players once this one
+                            // has been shown
+                            (ServerStatus::Over { .. }, _) => return,
+                            _ => {
+                                let reason = "a match is already under way".to_string();
+                                self.link.send(from, Message::Reject { reason }.encode());
+                                return;
+                            }
+                        }
+                    }
+                };
+                let welcome = Message::Welcome {
+                    side,
+                    snapshot_interval: self.config.snapshot_interval,
+                    grace_seconds: self.config.grace.as_secs() as u32,
+                    setup: toml::to_string(&MatchSetup {
+                        seed: self.seed,
+                        physics: self.config.physics,
+                        rules: self.config.rules,
+                    })
+                    .expect("match setup is always valid TOML"),
+                };
+                self.link.send(from, welcome.encode());
+            }
+            Message::Input { sequence, input } => {
+                let side = match self.seat_with(|seat| seat.address == from) {
+                    Some(side) => side,
+                    None => return,
+                };
+                let seat = self.seats[side_index(side)].as_mut().expect("seat was just found");
+                seat.last_heard = now;
+                if seat.dropped.take().is_some() {
+                    log.push(ServerEvent::Rejoined { side, address: from });
+                }
+                // Late or repeated packets are old news
+                if sequence <= seat.sequence {
+                    return;
+                }
+                seat.sequence = sequence;
+                if !input.direction.is_finite() || !(-1.0..=1.0).contains(&input.direction) {
+                    seat.rejected += 1;
+                    return;
+                }
+                seat.input = input;
+                seat.serve |= input.serve;
+            }
+            Message::Leave => {
+                let side = match self.seat_with(|seat| seat.address == from) {
+                    Some(side) => side,
+                    None => return,
+                };
+                match self.status {
+                    ServerStatus::Waiting => {
+                        self.seats[side_index(side)] = None;
+                        log.push(ServerEvent::Left { side });
+                    }
+                    ServerStatus::Playing | ServerStatus::Reconnecting { .. } => {
+                        log.push(ServerEvent::Left { side });
+                        self.finish(Some(side.other()), true, now, log);
+                    }
+                    // Done with it anyway
+                    ServerStatus::Over { .. } => { }
+                }
+            }
+            Message::Welcome { .. } | Message::Reject { .. } | Message::Snapshot { .. } => { }
+        }
+    }
+
+    fn seat_with(&self, matches: impl Fn(&Seat) -> bool) -> Option<Paddle> {
+        [Paddle::Player, Paddle::Opponent]
+            .into_iter()
+            .find(|side| self.seats[side_index(*side)].as_ref().is_some_and(&matches))
+    }
+
+    // Notice clients that have gone quiet. Before the match they just lose
+    // their seat.
+    fn check_seats(&mut self, now: Instant, log: &mut Vec<ServerEvent>) {
+        for side in [Paddle::Player, Paddle::Opponent] {
+            let seat = match &mut self.seats[side_index(side)] {
+                Some(seat) => seat,
+                None => continue,
+            };
+            if seat.dropped.is_some() || now.saturating_duration_since(seat.last_heard) < CLIENT_TIMEOUT {
+                continue;
+            }
+            seat.dropped = Some(now);
+            match self.status {
+                ServerStatus::Waiting => {
+                    self.seats[side_index(side)] = None;
+                    log.push(ServerEvent::Left { side });
+                }
+                ServerStatus::Playing | ServerStatus::Reconnecting { .. } => {
+                    log.push(ServerEvent::Dropped { side });
+                }
+                // Nothing left to come back for
+                ServerStatus::Over { .. } => { }
+            }
+        }
+    }
+
+    // The side that has been away longest, if any
+    fn away(&self) -> Option<(Paddle, Instant)> {
+        [Paddle::Player, Paddle::Opponent]
+            .into_iter()
+            .filter_map(|side| {
+                let dropped = self.seats[side_index(side)].as_ref()?.dropped?;
+                Some((side, dropped))
+            })
+            .min_by_key(|(_, dropped)| *dropped)
+    }
+
+    fn play(&mut self, now: Instant, log: &mut Vec<ServerEvent>) {
+        let time_step = self.config.physics.time_step();
+        let mut ticks = 0;
+        while self.accumulator >= time_step {
+            if ticks == MAX_TICKS_PER_UPDATE {
+                self.accumulator %= time_step;
+                break;
+            }
+            self.accumulator -= time_step;
+            ticks += 1;
+
+            let inputs = SimInputs {
+                player: self.take_input(Paddle::Player),
+                opponent: self.take_input(Paddle::Opponent),
+            };
+            let events = self.sim.step(inputs);
+            let tick = self.sim.tick();
+            self.events.extend(events.iter().map(|event| (tick, *event)));
+            let won = events.iter().find_map(|event| match event {
+                SimEvent::MatchWon(winner) => Some(*winner),
+                _ => None,
+            });
+            if let Some(winner) = won {
+                self.finish(Some(winner), false, now, log);
+                self.broadcast(now);
+                return;
+            }
+            if tick.is_multiple_of(self.config.snapshot_interval as u64) {
+                self.broadcast(now);
+            }
+        }
+    }
+
+    fn take_input(&mut self, side: Paddle) -> PaddleInput {
+        match &mut self.seats[side_index(side)] {
+            Some(seat) => {
+                let input = PaddleInput { direction: seat.input.direction, serve: seat.serve };
+                seat.serve = false;
+                input
+            }
+            None => PaddleInput::default(),
+        }
+    }
+
+    fn finish(&mut self, winner: Option<Paddle>, forfeit: bool, now: Instant, log: &mut Vec<ServerEvent>) {
+        self.status = ServerStatus::Over { winner, forfeit };
+        self.over_at = Some(now);
+        let [player, opponent] = &self.seats;
+        if let (Some(player), Some(opponent)) = (player, opponent) {
+            log.push(ServerEvent::Finished(MatchRecord {
+                number: self.number,
+                seed: self.seed,
+                addresses: [player.address, opponent.address],
+                winner,
+                forfeit,
+                scoreboard: self.sim.scoreboard,
+                ticks: self.sim.tick(),
+                rejected_inputs: [player.rejected, opponent.rejected],
+            }));
+        }
+    }
+
+    fn broadcast(&mut self, now: Instant) {
+        let events = std::mem::take(&mut self.events);
+        for seat in self.seats.iter().flatten() {
+            let snapshot = Message::Snapshot {
+                ack: seat.sequence,
+                status: self.status,
+                state: self.sim.state(),
+                events: events.clone(),
+            };
+            self.link.send(seat.address, snapshot.encode());
+        }
+        self.last_snapshot = now;
+    }
+}
+
+// What the server said when it took this client on
+struct Welcome {
+    side: Paddle,
+    setup: MatchSetup,
+    snapshot_interval: u32,
+    grace: Duration,
+}
+
+/// One side of a match on a [`MatchServer`].
+pub struct ServerClient {
+    link: Link,
+    server: SocketAddr,
+    // The server's arena has to be this size
+    physics: PhysicsConfig,
+    token: u64,
+    last_join: Option<Instant>,
+    // When anything last went to the server
+    last_sent: Option<Instant>,
+    last_heard: Instant,
+    welcome: Option<Welcome>,
+    status: ServerStatus,
+    // Oldest first, by tick
+    snapshots: VecDeque<SimState>,
+    events: VecDeque<(u64, SimEvent)>,
+    // Fractional tick being shown
+    view_tick: f64,
+    sequence: u32,
+    // When recent inputs were sent, to time the server's acks
+    sent: VecDeque<(u32, Instant)>,
+    ping: Option<Duration>,
+    closed: Option<String>,
+}
+
+impl ServerClient {
+    /// Join the server at `address`. `token` should be the same if this
+    /// client has to join again partway through. The server's arena must
+    /// be the same size as `physics`' so the match fits the window.
+    pub fn connect(address: &str, physics: PhysicsConfig, token: u64, shim: LinkShim)
+        -> io::Result<ServerClient> {
+        let server = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address not found"))?;
+        let local = if server.is_ipv4() { SocketAddr::from(([0, 0, 0, 0], 0)) }
+            else { SocketAddr::from(([0u16; 8], 0)) };
+        Ok(ServerClient {
+            link: Link::bind(local, shim, token)?,
+            server,
+            physics,
+            token,
+            last_join: None,
+            last_sent: None,
+            last_heard: Instant::now(),
+            welcome: None,
+            status: ServerStatus::Waiting,
+            snapshots: VecDeque::new(),
+            events: VecDeque::new(),
+            view_tick: 0.0,
+            sequence: 0,
+            sent: VecDeque::new(),
+            ping: None,
+            closed: None,
+        })
+    }
+
+    /// The side played, once the server has said.
+    pub fn side(&self) -> Option<Paddle> {
+        self.welcome.as_ref().map(|welcome| welcome.side)
+    }
+
+    /// The match seed, once the server has said.
+    pub fn seed(&self) -> Option<u64> {
+        self.welcome.as_ref().map(|welcome| welcome.setup.seed)
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        self.status
+    }
+
+    /// Round trip time for an input to be taken in, smoothed.
+    pub fn ping(&self) -> Option<Duration> {
+        self.ping
+    }
+
+    /// Why the match can't go on, if the server said no or went quiet.
+    pub fn closed(&self) -> Option<&str> {
+        self.closed.as_deref()
+    }
+
+    /// Take in everything that has arrived, and ask to join again if the
+    /// server hasn't answered or has gone quiet. Joining again also keeps
+    /// the seat while no inputs are going out, before the match and while
+    /// it's held up.
+    pub fn receive(&mut self) {
+        let now = Instant::now();
+        for (from, message) in receive(&mut self.link) {
+            if from != self.server {
+                continue;
+            }
+            self.last_heard = now;
+            match message {
+                Message::Welcome { side, snapshot_interval, grace_seconds, setup } => {
+                    let setup = match self.check_setup(&setup) {
+                        Ok(setup) => setup,
+                        Err(reason) => {
+                            self.closed = Some(reason);
+                            continue;
+                        }
+                    };
+                    self.welcome = Some(Welcome {
+                        side,
+                        setup,
+                        snapshot_interval: snapshot_interval.max(1),
+                        grace: Duration::from_secs(grace_seconds as u64),
+                    });
+                }
+                Message::Reject { reason } => self.closed = Some(reason),
+                Message::Snapshot { ack, status, state, events } => {
+                    self.take_ack(ack, now);
+                    // Anything older than the latest arrived out of order
+                    if self.snapshots.back().is_some_and(|latest| latest.tick > state.tick) {
+                        continue;
+                    }
+                    self.status = status;
+                    self.snapshots.push_back(state);
+                    if self.snapshots.len() > KEPT_SNAPSHOTS {
+                        self.snapshots.pop_front();
+                    }
+                    self.events.extend(events);
+                }
+                Message::Join { .. } | Message::Input { .. } | Message::Leave => { }
+            }
+        }
+
+        let quiet = now.saturating_duration_since(self.last_heard);
+        let limit = match &self.welcome {
+            Some(welcome) => welcome.grace + CLIENT_TIMEOUT,
+            None => JOIN_TIMEOUT,
+        };
+        if self.closed.is_none() && quiet > limit {
+            self.closed = Some(match self.welcome {
+                Some(_) => "lost the connection to the server".to_string(),
+                None => format!("no answer from {}", self.server),
+            });
+        }
+        let asking = self.welcome.is_none() || quiet >= JOIN_INTERVAL * 4;
+        let due = self.last_join.is_none_or(|sent| now.saturating_duration_since(sent) >= JOIN_INTERVAL);
+        let idle = self.last_sent.is_none_or(|sent| now.saturating_duration_since(sent) >= JOIN_INTERVAL);
+        if (asking && due) || idle {
+            self.last_join = Some(now);
+            self.last_sent = Some(now);
+            let join = Message::Join { version: SERVER_VERSION, token: self.token };
+            self.link.send(self.server, join.encode());
+        }
+        self.link.flush();
+    }
+
+    fn check_setup(&self, setup: &str) -> Result<MatchSetup, String> {
+        let setup: MatchSetup = toml::from_str(setup)
+            .map_err(|error| format!("bad match setup from server: {}", error))?;
+        setup.physics.validate().map_err(|error| format!("physics.{}", error))?;
+        setup.rules.validate().map_err(|error| format!("rules.{}", error))?;
+        if setup.physics.arena != self.physics.arena {
+            return Err("the server's arena is a different size".to_string());
+        }
+        Ok(setup)
+    }
+
+    fn take_ack(&mut self, ack: u32, now: Instant) {
+        while let Some((sequence, sent)) = self.sent.front().copied() {
+            if sequence > ack {
+                break;
+            }
+            self.sent.pop_front();
+            if sequence == ack {
+                let rtt = now.saturating_duration_since(sent);
+                self.ping = Some(match self.ping {
+                    Some(ping) => ping.mul_f32(0.8) + rtt.mul_f32(0.2),
+                    None => rtt,
+                });
+            }
+        }
+    }
+
+    /// Tell the server what this side wants to do. Call once a tick.
+    pub fn send_input(&mut self, input: PaddleInput) {
+        if self.welcome.is_none() {
+            return;
+        }
+        let now = Instant::now();
+        self.sequence += 1;
+        self.last_sent = Some(now);
+        self.sent.push_back((self.sequence, now));
+        if self.sent.len() > KEPT_SNAPSHOTS {
+            self.sent.pop_front();
+        }
+        self.link.send(self.server, Message::Input { sequence: self.sequence, input }.encode());
+        self.link.flush();
+    }
+
+    /// Move the view on by `seconds` of real time and return the match as
+    /// it should look now: between the two snapshots either side of a
+    /// point a little way behind the latest. None before the first one.
+    pub fn view(&mut self, seconds: f32) -> Option<PongSim> {
+        let welcome = self.welcome.as_ref()?;
+        let latest = self.snapshots.back()?.tick as f64;
+        let physics = welcome.setup.physics;
+        if self.status == ServerStatus::Playing {
+            self.view_tick += seconds as f64 * physics.tick_rate as f64;
+            let target = latest - (INTERPOLATION_SNAPSHOTS * welcome.snapshot_interval) as f64;
+            // Ease towards the target so the view keeps a steady pace,
+            // unless it's so far off it may as well jump
+            let behind = target - self.view_tick;
+            if behind.abs() > MAX_DRIFT {
+                self.view_tick = target;
+            } else {
+                self.view_tick += behind * CATCH_UP;
+            }
+        } else {
+            // Nothing more is coming until it starts again
+            self.view_tick = latest;
+        }
+        let first = self.snapshots.front()?.tick as f64;
+        self.view_tick = self.view_tick.clamp(first, latest);
+
+        let index = self.snapshots
+            .iter()
+            .rposition(|state| state.tick as f64 <= self.view_tick)
+            .unwrap_or(0);
+        let from = self.snapshots[index];
+        let mut state = from;
+        if let Some(to) = self.snapshots.get(index + 1) {
+            // Over a point the ball goes back to be served, so it isn't
+            // drawn flying across the field in between
+            if from.points_played == to.points_played {
+                let t = ((self.view_tick - from.tick as f64) / (to.tick - from.tick) as f64) as f32;
+                state.ball.position = from.ball.position.lerp(to.ball.position, t);
+                state.player.position = from.player.position.lerp(to.player.position, t);
+                state.opponent.position = from.opponent.position.lerp(to.opponent.position, t);
+            }
+        }
+        Some(PongSim::from_state(physics, welcome.setup.rules, state))
+    }
+
+    /// What happened up to the tick last viewed, in order.
+    pub fn take_events(&mut self) -> Vec<SimEvent> {
+        let mut events = Vec::new();
+        while let Some((tick, event)) = self.events.front().copied() {
+            if tick as f64 > self.view_tick {
+                break;
+            }
+            self.events.pop_front();
+            events.push(event);
+        }
+        events
+    }
+
+    /// Tell the server this side is going, forfeiting a match under way.
+    pub fn leave(&mut self) {
+        self.link.send_now(self.server, &Message::Leave.encode());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    const SHIM: LinkShim = LinkShim {
+        latency: Duration::from_millis(10),
+        jitter: Duration::from_millis(10),
+        loss: 0.05,
+    };
+
+    // A server on loopback and the clients playing on it
+    struct Loopback {
+        server: MatchServer,
+        address: String,
+        clients: Vec<Option<ServerClient>>,
+        log: Vec<ServerEvent>,
+        shim: LinkShim,
+    }
+
+    impl Loopback {
+        fn new(grace: Duration) -> Loopback {
+            Loopback::with_shim(grace, SHIM)
+        }
+
+        fn with_shim(grace: Duration, shim: LinkShim) -> Loopback {
+            let config = ServerConfig { grace, shim, ..ServerConfig::default() };
+            let server = MatchServer::bind(0, config, 5).expect("bind");
+            let port = server.port().expect("bound");
+            let mut loopback = Loopback {
+                server,
+                address: format!("127.0.0.1:{}", port),
+                clients: Vec::new(),
+                log: Vec::new(),
+                shim,
+            };
+            loopback.join(1);
+            loopback.join(2);
+            loopback.run_until("the match to start", |loopback| {
+                loopback.server.status() == ServerStatus::Playing
+                    && loopback.clients.iter().flatten().all(|client| client.side().is_some() && !client.snapshots.is_empty())
+            });
+            loopback
+        }
+
+        fn join(&mut self, token: u64) -> usize {
+            let client = ServerClient::connect(&self.address, PhysicsConfig::default(), token, self.shim)
+                .expect("bind");
+            self.clients.push(Some(client));
+            self.clients.len() - 1
+        }
+
+        fn client(&self, index: usize) -> &ServerClient {
+            self.clients[index].as_ref().expect("client still running")
+        }
+
+        // Update everything, with each client still running sending an input
+        fn pump(&mut self, input: PaddleInput) {
+            self.log.extend(self.server.update());
+            for client in self.clients.iter_mut().flatten() {
+                client.receive();
+                if client.status() == ServerStatus::Playing {
+                    client.send_input(input);
+                }
+                client.take_events();
+            }
+        }
+
+        fn run_until(&mut self, what: &str, mut done: impl FnMut(&mut Loopback) -> bool) {
+            let started = Instant::now();
+            while !done(self) {
+                assert!(started.elapsed() < Duration::from_secs(15), "waited too long for {}", what);
+                self.pump(PaddleInput::default());
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        fn side(&self, index: usize) -> Paddle {
+            self.client(index).side().expect("welcomed")
+        }
+    }
+
+    #[test]
+    fn clients_see_the_match_from_snapshots() {
+        let mut loopback = Loopback::new(Duration::from_secs(30));
+        assert_ne!(loopback.side(0), loopback.side(1));
+        assert_eq!(loopback.client(0).seed(), loopback.client(1).seed());
+
+        let started = loopback.client(0).snapshots.back().map(|state| state.tick).unwrap_or(0);
+        loopback.run_until("snapshots", |loopback| {
+            loopback.clients.iter().flatten().all(|client| {
+                client.snapshots.back().is_some_and(|state| state.tick >= started + 60)
+            })
+        });
+        for index in 0..2 {
+            let view = loopback.clients[index].as_mut().expect("running").view(0.0).expect("snapshots");
+            // Shown a little behind the server, never ahead of it
+            assert!(view.tick() <= loopback.server.sim.tick());
+            assert!(view.tick() > started);
+            assert_eq!(view.physics, PhysicsConfig::default());
+        }
+        assert!(loopback.client(0).ping().is_some());
+    }
+
+    #[test]
+    fn bad_inputs_are_rejected() {
+        // Each is only sent once, and one arriving after a later input would be dropped as stale
+        let shim = LinkShim { latency: SHIM.latency, ..LinkShim::default() };
+        let mut loopback = Loopback::with_shim(Duration::from_secs(30), shim);
+        let side = loopback.side(0);
+        let client = loopback.clients[0].as_mut().expect("running");
+        client.send_input(PaddleInput { direction: f32::NAN, serve: false });
+        client.send_input(PaddleInput { direction: 2.0, serve: false });
+        client.send_input(PaddleInput { direction: f32::NEG_INFINITY, serve: false });
+        loopback.run_until("the inputs to be rejected", |loopback| {
+            loopback.server.seats[side_index(side)].as_ref().is_some_and(|seat| seat.rejected == 3)
+        });
+        let other = loopback.server.seats[side_index(side.other())].as_ref().expect("seated");
+        assert_eq!(other.rejected, 0);
+        assert!(loopback.server.sim.paddle(side).position.y.is_finite());
+    }
+
+    #[test]
+    fn a_dropped_client_gets_its_seat_back() {
+        let mut loopback = Loopback::new(Duration::from_secs(30));
+        let side = loopback.side(0);
+        loopback.clients[0] = None;
+        loopback.run_until("the drop to be noticed", |loopback| {
+            matches!(loopback.server.status(), ServerStatus::Reconnecting { side: away, .. } if away == side)
+        });
+        assert!(loopback.log.contains(&ServerEvent::Dropped { side }));
+        let frozen = loopback.server.sim.tick();
+
+        // Someone else can't have it
+        let stranger = loopback.join(99);
+        loopback.run_until("the stranger to be turned away", |loopback| {
+            loopback.clients[stranger].as_mut().expect("running").receive();
+            loopback.client(stranger).closed().is_some()
+        });
+        loopback.clients[stranger] = None;
+        assert_eq!(loopback.server.sim.tick(), frozen);
+
+        // The same token from a new socket does
+        let back = loopback.join(1);
+        loopback.run_until("the match to carry on", |loopback| {
+            loopback.server.status() == ServerStatus::Playing && loopback.client(back).side().is_some()
+        });
+        assert_eq!(loopback.side(back), side);
+        assert!(loopback.log.iter().any(|event| matches!(event, ServerEvent::Rejoined { side: back, .. } if *back == side)));
+
+        // And its inputs count again
+        let started = loopback.server.sim.tick();
+        let up = PaddleInput { direction: 1.0, serve: false };
+        let started_at = Instant::now();
+        while loopback.server.sim.tick() < started + 30 {
+            assert!(started_at.elapsed() < Duration::from_secs(5));
+            loopback.pump(up);
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(loopback.server.seats[side_index(side)].as_ref().expect("seated").input, up);
+    }
+
+    #[test]
+    fn staying_away_past_the_grace_forfeits() {
+        let mut loopback = Loopback::new(Duration::from_secs(1));
+        let side = loopback.side(0);
+        loopback.clients[0] = None;
+        loopback.run_until("the forfeit", |loopback| {
+            matches!(loopback.server.status(), ServerStatus::Over { .. })
+        });
+        assert_eq!(loopback.server.status(), ServerStatus::Over { winner: Some(side.other()), forfeit: true });
+        let record = loopback.log.iter()
+            .find_map(|event| match event {
+                ServerEvent::Finished(record) => Some(record.clone()),
+                _ => None,
+            })
+            .expect("the match is logged");
+        assert_eq!(record.winner, Some(side.other()));
+        assert!(record.forfeit);
+
+        // The side still there hears about it
+        loopback.run_until("the other side to hear", |loopback| {
+            matches!(loopback.client(1).status(), ServerStatus::Over { .. })
+        });
+        assert_eq!(loopback.client(1).status(), loopback.server.status());
+    }
+
+    #[test]
+    fn joining_needs_the_same_arena() {
+        let server = MatchServer::bind(0, ServerConfig::default(), 5).expect("bind");
+        let mut wide = PhysicsConfig::default();
+        wide.arena.width += 200.0;
+        let address = format!("127.0.0.1:{}", server.port().expect("bound"));
+        let mut server = server;
+        let mut client = ServerClient::connect(&address, wide, 1, LinkShim::default()).expect("bind");
+        let started = Instant::now();
+        while client.closed().is_none() {
+            assert!(started.elapsed() < Duration::from_secs(5), "no answer from the server");
+            server.update();
+            client.receive();
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(client.closed().is_some_and(|reason| reason.contains("arena")));
+    }
+}
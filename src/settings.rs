@@ -0,0 +1,269 @@
+//! Settings file read at startup, so tuning the game doesn't need a
+//! rebuild. Every entry is optional and falls back to its default:
+//!
+//! ```toml
+//! difficulty = "Hard"
+//!
+//! [window]
+//! background = [0.2, 0.2, 0.1]
+//!
+//! [physics]
+//! ball_speed = 500.0
+//! paddle_height = 120.0
+//!
+//! [physics.arena]
+//! width = 1200.0
+//!
+//! [rules]
+//! points_to_win = 5
+//!
+//! [network]
+//! input_delay = 3
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ai::Difficulty;
+use crate::net::DEFAULT_PORT;
+use crate::physics::{check, PhysicsConfig};
+use crate::rules::MatchRules;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameSettings {
+    /// Computer opponent preset.
+    pub difficulty: Difficulty,
+    pub window: WindowSettings,
+    pub physics: PhysicsConfig,
+    pub rules: MatchRules,
+    pub network: NetworkSettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowSettings {
+    /// Red, green and blue, each from 0 to 1. Used by the classic theme;
+    /// the others bring their own.
+    pub background: [f32; 3],
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub theme: Theme,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            background: [0.20, 0.20, 0.10],
+            fullscreen: false,
+            vsync: true,
+            theme: Theme::default(),
+        }
+    }
+}
+
+/// Colours for the arena, paddles and ball.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Theme {
+    #[default]
+    Classic,
+    Night,
+    HighContrast,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Classic, Theme::Night, Theme::HighContrast];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Classic => "Classic",
+            Theme::Night => "Night",
+            Theme::HighContrast => "High Contrast",
+        }
+    }
+
+    /// The theme `steps` away in the list, wrapping round at either end.
+    pub fn step(&self, steps: i32) -> Theme {
+        let index = Theme::ALL.iter().position(|theme| theme == self).unwrap_or(0);
+        let count = Theme::ALL.len() as i32;
+        Theme::ALL[(index as i32 + steps).rem_euclid(count) as usize]
+    }
+}
+
+/// Online play. See [`crate::net`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkSettings {
+    /// UDP port to host on.
+    pub port: u16,
+    /// Ticks to hold local inputs back by. Each tick of delay is one less
+    /// tick of rollback on a slow connection.
+    pub input_delay: usize,
+    /// Most ticks to play on guessed inputs before waiting for the other
+    /// side.
+    pub max_prediction: usize,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        NetworkSettings {
+            port: DEFAULT_PORT,
+            input_delay: 2,
+            max_prediction: 8,
+        }
+    }
+}
+
+impl NetworkSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        check(self.input_delay <= 15, "input_delay", "at most 15", self.input_delay)?;
+        check((1..=60).contains(&self.max_prediction), "max_prediction", "from 1 to 60",
+            self.max_prediction)?;
+        Ok(())
+    }
+}
+
+impl GameSettings {
+    /// `settings.toml` in the user's config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("pong").join("settings.toml"))
+    }
+
+    /// Settings from `path` if given, which must then exist, or else from
+    /// the default path if there is a file there, or else the defaults.
+    pub fn load_or_default(path: Option<&Path>) -> Result<GameSettings, String> {
+        GameSettings::load_or(path, GameSettings::default_path())
+    }
+
+    fn load_or(path: Option<&Path>, default_path: Option<PathBuf>) -> Result<GameSettings, String> {
+        match path {
+            Some(path) => GameSettings::load(path),
+            None => match default_path {
+                Some(path) if path.exists() => GameSettings::load(&path),
+                _ => Ok(GameSettings::default()),
+            },
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<GameSettings, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+        GameSettings::parse(&text).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// Read and check settings written as TOML.
+    pub fn parse(text: &str) -> Result<GameSettings, String> {
+        let settings: GameSettings = toml::from_str(text).map_err(|error| error.to_string())?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (channel, value) in ["red", "green", "blue"].iter().zip(self.window.background) {
+            check((0.0..=1.0).contains(&value), &format!("window.background {}", channel),
+                "from 0 to 1", value)?;
+        }
+        self.physics.validate().map_err(|error| format!("physics.{}", error))?;
+        self.rules.validate().map_err(|error| format!("rules.{}", error))?;
+        self.network.validate().map_err(|error| format!("network.{}", error))?;
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string(self)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        fs::write(path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+    use crate::physics::PaddleMovement;
+
+    fn error(text: &str) -> String {
+        GameSettings::parse(text).expect_err("should be rejected")
+    }
+
+    #[test]
+    fn an_empty_file_is_all_defaults() {
+        assert_eq!(GameSettings::parse(""), Ok(GameSettings::default()));
+    }
+
+    #[test]
+    fn reads_what_is_given_and_defaults_the_rest() {
+        let settings = GameSettings::parse(r#"
+            difficulty = "Hard"
+
+            [physics]
+            ball_speed = 500.0
+            paddle_movement = { Accelerated = { acceleration = 900.0, friction = 600.0 } }
+
+            [physics.arena]
+            width = 1200.0
+
+            [rules]
+            points_to_win = 5
+        "#).expect("valid settings");
+        assert_eq!(settings.difficulty, Difficulty::Hard);
+        assert_eq!(settings.physics.ball_speed, 500.0);
+        assert_eq!(settings.physics.paddle_movement,
+            PaddleMovement::Accelerated { acceleration: 900.0, friction: 600.0 });
+        assert_eq!(settings.physics.arena.width, 1200.0);
+        assert_eq!(settings.physics.arena.height, PhysicsConfig::default().arena.height);
+        assert_eq!(settings.rules.points_to_win, 5);
+        assert_eq!(settings.rules.win_by, MatchRules::default().win_by);
+        assert_eq!(settings.network, NetworkSettings::default());
+    }
+
+    #[test]
+    fn unknown_keys_are_named() {
+        assert!(error("dificulty = \"Hard\"").contains("dificulty"));
+        assert!(error("[physics]\nball_sped = 500.0").contains("ball_sped"));
+        assert!(error("[physics.arena]\nwidht = 1200.0").contains("widht"));
+    }
+
+    #[test]
+    fn out_of_range_values_are_named() {
+        assert_eq!(error("[physics]\nball_speed = -1.0"), "physics.ball_speed must be positive (got -1)");
+        assert!(error("[physics.arena]\nwidth = 100.0").starts_with("physics.arena.width must be"));
+        assert!(error("[rules]\npoints_to_win = 0").starts_with("rules.points_to_win must be"));
+        assert!(error("[network]\ninput_delay = 40").starts_with("network.input_delay must be"));
+        assert!(error("[window]\nbackground = [0.2, 1.5, 0.1]").starts_with("window.background green must be"));
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_named() {
+        let message = error("[rules]\npoints_to_win = \"five\"");
+        assert!(message.contains("points_to_win"), "{}", message);
+        assert!(message.contains("invalid type"), "{}", message);
+        assert!(error("difficulty = \"Brutal\"").contains("Brutal"));
+    }
+
+    #[test]
+    fn a_missing_file_falls_back_to_the_defaults_unless_asked_for() {
+        let dir = env::temp_dir().join(format!("pong-settings-test-{}", process::id()));
+        let path = dir.join("settings.toml");
+        assert_eq!(GameSettings::load_or(None, Some(path.clone())), Ok(GameSettings::default()));
+        let message = GameSettings::load_or(Some(&path), None).expect_err("asked for a missing file");
+        assert!(message.starts_with("could not read"), "{}", message);
+
+        let mut settings = GameSettings::default();
+        settings.rules.points_to_win = 7;
+        settings.save(&path).expect("save");
+        assert_eq!(GameSettings::load_or(None, Some(path.clone())), Ok(settings));
+        fs::write(&path, "[rules]\npoints_to_win = 0").expect("write");
+        let message = GameSettings::load_or(None, Some(path.clone())).expect_err("bad file");
+        assert!(message.starts_with(&format!("{}: rules.points_to_win", path.display())), "{}", message);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
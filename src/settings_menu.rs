@@ -0,0 +1,280 @@
+use std::path::PathBuf;
+
+use bevy::{prelude::*, window::{PresentMode, WindowMode}};
+
+use pong::ai::Difficulty;
+use pong::physics::PhysicsConfig;
+use pong::rules::MatchRules;
+use pong::settings::GameSettings;
+
+use crate::GameState;
+use crate::bindings::{Action, Bindings};
+use crate::main_menu::{spawn_button, spawn_menu_column};
+use crate::match_state::{Match, MatchPhase};
+use crate::menu_nav::back_pressed;
+use crate::theme::palette;
+use crate::widgets::{spawn_slider, spawn_toggle, Stepper, ValueLabel};
+
+pub struct SettingsMenuPlugin;
+
+// Where settings are written back to: the `--config` file if one was
+// given, or else the default file in the user's config directory
+pub struct SettingsFile(pub Option<PathBuf>);
+
+impl SettingsFile {
+    pub fn save(&self, settings: &GameSettings) {
+        let path = match &self.0 {
+            Some(path) => path,
+            None => return,
+        };
+        if let Err(error) = settings.save(path) {
+            println!("Could not save settings to {}: {}", path.display(), error);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SettingOption {
+    Difficulty,
+    PointsToWin,
+    BallSpeed,
+    PaddleSize,
+    Fullscreen,
+    Vsync,
+    Theme,
+}
+
+#[derive(Component)]
+struct SettingsEntity;
+
+#[derive(Component)]
+struct SettingsBackButton;
+
+impl Plugin for SettingsMenuPlugin {
+    fn build(&self, app: &mut App) {
+        println!("Building settings menu!");
+        app
+        .add_system_set(
+            SystemSet::on_enter(GameState::Settings)
+                .with_system(setup_settings)
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Settings)
+                .with_system(step_setting_sys)
+                .with_system(update_setting_labels.after(step_setting_sys))
+                .with_system(settings_back_sys)
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Settings)
+                .with_system(teardown_settings)
+        );
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value { "On" } else { "Off" }
+}
+
+fn get_setting_str(option: SettingOption, settings: &GameSettings) -> String {
+    match option {
+        SettingOption::Difficulty => format!("AI: {}", settings.difficulty.name()),
+        SettingOption::PointsToWin => format!("Points to Win: {}", settings.rules.points_to_win),
+        SettingOption::BallSpeed => format!("Ball Speed: {:.0}", settings.physics.ball_speed),
+        SettingOption::PaddleSize => format!("Paddle Size: {:.0}", settings.physics.paddle_height),
+        SettingOption::Fullscreen => format!("Fullscreen: {}", on_off(settings.window.fullscreen)),
+        SettingOption::Vsync => format!("VSync: {}", on_off(settings.window.vsync)),
+        SettingOption::Theme => format!("Theme: {}", settings.window.theme.name()),
+    }
+}
+
+// Move one option `delta` steps along its range
+fn adjust(settings: &mut GameSettings, option: SettingOption, delta: i32) {
+    match option {
+        SettingOption::Difficulty => {
+            settings.difficulty = settings.difficulty.step(delta);
+        }
+        SettingOption::PointsToWin => {
+            let points = settings.rules.points_to_win as i32 + delta;
+            settings.rules.points_to_win = points.clamp(1, 21) as usize;
+        }
+        SettingOption::BallSpeed => {
+            let physics = &mut settings.physics;
+            physics.ball_speed = (physics.ball_speed + 50. * delta as f32).clamp(200., 900.);
+            physics.max_ball_speed = physics.max_ball_speed.max(physics.ball_speed);
+        }
+        SettingOption::PaddleSize => {
+            let physics = &mut settings.physics;
+            physics.paddle_height = (physics.paddle_height + 10. * delta as f32).clamp(40., 200.);
+        }
+        SettingOption::Fullscreen => {
+            settings.window.fullscreen = !settings.window.fullscreen;
+        }
+        SettingOption::Vsync => {
+            settings.window.vsync = !settings.window.vsync;
+        }
+        SettingOption::Theme => {
+            settings.window.theme = settings.window.theme.step(delta);
+        }
+    }
+}
+
+pub fn window_mode(settings: &GameSettings) -> WindowMode {
+    if settings.window.fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    }
+}
+
+pub fn present_mode(settings: &GameSettings) -> PresentMode {
+    if settings.window.vsync {
+        PresentMode::AutoVsync
+    } else {
+        PresentMode::AutoNoVsync
+    }
+}
+
+fn setup_settings(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<GameSettings>,
+    current: Res<Match>,
+) {
+    let title = commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "Settings",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 50.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            )
+            .with_style(Style {
+                margin: UiRect::all(Val::Px(5.0)),
+                ..default()
+            }),
+        )
+        .id();
+
+    let mut children = vec![title];
+    // Opened from the pause menu, with a match to go back to
+    if current.phase == MatchPhase::Running {
+        let note = commands
+            .spawn_bundle(
+                TextBundle::from_section(
+                    "Ball, paddle and points changes apply from the next match",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 20.0,
+                        color: Color::rgb(0.7, 0.7, 0.7),
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::all(Val::Px(5.0)),
+                    ..default()
+                }),
+            )
+            .id();
+        children.push(note);
+    }
+    for option in [
+        SettingOption::Difficulty,
+        SettingOption::PointsToWin,
+        SettingOption::BallSpeed,
+        SettingOption::PaddleSize,
+    ] {
+        let label = get_setting_str(option, &settings);
+        children.push(spawn_slider(&mut commands, &asset_server, option, &label));
+    }
+    for option in [SettingOption::Fullscreen, SettingOption::Vsync, SettingOption::Theme] {
+        let label = get_setting_str(option, &settings);
+        children.push(spawn_toggle(&mut commands, &asset_server, option, &label));
+    }
+    children.push(spawn_button(&mut commands, &asset_server, "Back")
+        .insert(SettingsBackButton)
+        .id());
+
+    spawn_menu_column(&mut commands)
+        .push_children(&children)
+        .insert(SettingsEntity);
+}
+
+// Change an option, put it into effect straight away and save it. Changes
+// that would leave the settings invalid, like a paddle too tall for the
+// arena, are ignored. Tuning and rules only take effect from the next
+// match, so one under way plays out (and is recorded) as it started.
+fn step_setting_sys(
+    interaction_query: Query<(&Interaction, &Stepper<SettingOption>), Changed<Interaction>>,
+    mut settings: ResMut<GameSettings>,
+    settings_file: Res<SettingsFile>,
+    mut physics: ResMut<PhysicsConfig>,
+    mut rules: ResMut<MatchRules>,
+    mut difficulty: ResMut<Difficulty>,
+    mut clear_color: ResMut<ClearColor>,
+    mut windows: ResMut<Windows>,
+) {
+    for (interaction, stepper) in &interaction_query {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        let mut changed = *settings;
+        adjust(&mut changed, stepper.target, stepper.delta);
+        if changed == *settings || changed.validate().is_err() {
+            continue;
+        }
+        *settings = changed;
+        settings_file.save(&settings);
+
+        *difficulty = settings.difficulty;
+        clear_color.0 = palette(&settings.window).background;
+        if let Some(window) = windows.get_primary_mut() {
+            window.set_mode(window_mode(&settings));
+            window.set_present_mode(present_mode(&settings));
+        }
+        *physics = settings.physics;
+        *rules = settings.rules;
+    }
+}
+
+fn update_setting_labels(
+    label_query: Query<(&ValueLabel<SettingOption>, &Children)>,
+    mut text_query: Query<&mut Text>,
+    settings: Res<GameSettings>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (label, children) in &label_query {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        text.sections[0].value = get_setting_str(label.0, &settings);
+    }
+}
+
+fn settings_back_sys(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<SettingsBackButton>)>,
+    mut keys: ResMut<Input<KeyCode>>,
+    bindings: Res<Bindings>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let clicked = interaction_query.iter().any(|interaction| *interaction == Interaction::Clicked);
+    if clicked || back_pressed(&keys, &bindings, &gamepad_buttons) {
+        // Back to the pause menu if that's where settings were opened
+        if state.inactives().is_empty() {
+            state.set(GameState::MainMenu).expect("Failed to exit to menu");
+        } else {
+            state.pop().expect("Failed to return to pause menu");
+        }
+        keys.reset(bindings.key(Action::Back));
+    }
+}
+
+fn teardown_settings(
+    mut commands: Commands,
+    query: Query<Entity, With<SettingsEntity>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
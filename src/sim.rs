@@ -0,0 +1,1062 @@
+//! Deterministic Pong simulation. Owns the ball, both paddles and the
+//! score, and advances one tick at a time from paddle inputs.
+
+use glam::Vec2;
+
+use crate::collision::{self, Aabb, CollisionEvent, Hit, Surface};
+use crate::physics::{Arena, PaddleMovement, PhysicsConfig};
+use crate::rng::SimRng;
+use crate::rules::{self, MatchRules, ServePosition};
+
+// Most surfaces the ball can bounce off within a single tick
+const MAX_BOUNCES_PER_TICK: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Paddle {
+    Player,
+    Opponent,
+}
+
+impl Paddle {
+    // Starting position of the paddle, next to its own wall
+    pub fn position(&self, physics: &PhysicsConfig) -> Vec2 {
+        match self {
+            Paddle::Player => {
+                Vec2::new(WallLocation::Left.inner(&physics.arena).x + physics.paddle_offset,
+                WallLocation::Left.inner(&physics.arena).y)
+            }
+            Paddle::Opponent => {
+                Vec2::new(WallLocation::Right.inner(&physics.arena).x - physics.paddle_offset,
+                WallLocation::Left.inner(&physics.arena).y)
+            }
+        }
+    }
+
+    fn speed(&self, physics: &PhysicsConfig) -> f32 {
+        match self {
+            Paddle::Player => physics.player_speed,
+            Paddle::Opponent => physics.opponent_speed,
+        }
+    }
+
+    pub fn other(&self) -> Paddle {
+        match self {
+            Paddle::Player => Paddle::Opponent,
+            Paddle::Opponent => Paddle::Player,
+        }
+    }
+
+    /// Horizontal direction the paddle hits the ball in.
+    pub fn facing(&self) -> f32 {
+        match self {
+            Paddle::Player => 1.0,
+            Paddle::Opponent => -1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WallLocation {
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+impl WallLocation {
+    pub fn position(&self, arena: &Arena) -> Vec2 {
+        let right = arena.width / 2.;
+        let top = arena.height / 2.;
+        match self {
+            WallLocation::Left => Vec2::new(-right + arena.horizontal_offset, 0.),
+            WallLocation::Right => Vec2::new(right - arena.horizontal_offset, 0.),
+            WallLocation::Bottom => Vec2::new(0., -top + arena.vertical_offset),
+            WallLocation::Top => Vec2::new(0., top - arena.vertical_offset),
+        }
+    }
+
+    pub fn size(&self, arena: &Arena) -> Vec2 {
+        match self {
+            WallLocation::Left | WallLocation::Right => {
+                Vec2::new(arena.wall_thickness, arena.height + arena.wall_thickness)
+            }
+            WallLocation::Bottom | WallLocation::Top => {
+                Vec2::new(arena.width + arena.wall_thickness, arena.wall_thickness)
+            }
+        }
+    }
+
+    // Return the position of the inner surface of a given wall
+    pub fn inner(&self, arena: &Arena) -> Vec2 {
+        let position = self.position(arena);
+        let half = arena.wall_thickness / 2.;
+        match self {
+            WallLocation::Top => Vec2::new(position.x, position.y - half),
+            WallLocation::Bottom => Vec2::new(position.x, position.y + half),
+            WallLocation::Right => Vec2::new(position.x - half, position.y),
+            WallLocation::Left => Vec2::new(position.x + half, position.y),
+        }
+    }
+}
+
+/// Points in the current game and games won so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Scoreboard {
+    pub player_score: usize,
+    pub opponent_score: usize,
+    pub player_games: usize,
+    pub opponent_games: usize,
+}
+
+impl Scoreboard {
+    pub fn points(&self, paddle: Paddle) -> usize {
+        match paddle {
+            Paddle::Player => self.player_score,
+            Paddle::Opponent => self.opponent_score,
+        }
+    }
+
+    pub fn games(&self, paddle: Paddle) -> usize {
+        match paddle {
+            Paddle::Player => self.player_games,
+            Paddle::Opponent => self.opponent_games,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ball {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub size: Vec2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaddleState {
+    pub position: Vec2,
+    pub size: Vec2,
+    /// Top speed.
+    pub speed: f32,
+    /// Current vertical velocity.
+    pub velocity: f32,
+}
+
+impl PaddleState {
+    fn new(paddle: Paddle, physics: &PhysicsConfig) -> PaddleState {
+        PaddleState {
+            position: paddle.position(physics),
+            size: physics.paddle_size(),
+            speed: paddle.speed(physics),
+            velocity: 0.0,
+        }
+    }
+}
+
+/// What one side wants its paddle to do this tick.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PaddleInput {
+    /// Vertical direction, from -1.0 (down) to 1.0 (up).
+    pub direction: f32,
+    /// Serve now instead of waiting out the countdown. Only counts from
+    /// the side that is serving.
+    pub serve: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SimInputs {
+    pub player: PaddleInput,
+    pub opponent: PaddleInput,
+}
+
+impl SimInputs {
+    pub fn get(&self, paddle: Paddle) -> PaddleInput {
+        match paddle {
+            Paddle::Player => self.player,
+            Paddle::Opponent => self.opponent,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// The ball is waiting to be served.
+    Serving { ticks_left: u32 },
+    Rally,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimEvent {
+    /// The ball has been placed and the serve countdown started.
+    ServeStarted(Paddle),
+    /// The countdown ran out and the ball was launched.
+    Served(Paddle),
+    /// The ball touched a paddle or wall.
+    Collision(CollisionEvent),
+    /// The ball reached a side wall and the given paddle was awarded the point.
+    PointScored(Paddle),
+    /// The given paddle took the current game; the points start again from zero.
+    GameWon(Paddle),
+    /// The given paddle won the match. Nothing moves after this.
+    MatchWon(Paddle),
+}
+
+/// Everything about a match that changes as it is played. With the physics
+/// and rules the match started with, enough to carry it on from part way
+/// through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimState {
+    pub ball: Ball,
+    pub player: PaddleState,
+    pub opponent: PaddleState,
+    pub scoreboard: Scoreboard,
+    pub phase: Phase,
+    pub server: Paddle,
+    pub first_server: Paddle,
+    pub points_played: usize,
+    /// State of the random number generator.
+    pub rng: u64,
+    pub winner: Option<Paddle>,
+    pub tick: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PongSim {
+    pub ball: Ball,
+    pub player: PaddleState,
+    pub opponent: PaddleState,
+    pub scoreboard: Scoreboard,
+    pub physics: PhysicsConfig,
+    pub rules: MatchRules,
+    phase: Phase,
+    server: Paddle,
+    first_server: Paddle,
+    points_played: usize,
+    rng: SimRng,
+    winner: Option<Paddle>,
+    tick: u64,
+}
+
+impl Default for PongSim {
+    fn default() -> Self {
+        PongSim::new(PhysicsConfig::default(), MatchRules::default(), 0)
+    }
+}
+
+impl PongSim {
+    /// A new match. The seed decides who serves first and every serve angle.
+    pub fn new(physics: PhysicsConfig, rules: MatchRules, seed: u64) -> PongSim {
+        let mut rng = SimRng::new(seed);
+        let first_server = if rng.next_u64().is_multiple_of(2) {
+            Paddle::Player
+        } else {
+            Paddle::Opponent
+        };
+
+        let mut sim = PongSim {
+            ball: Ball {
+                position: Vec2::ZERO,
+                velocity: Vec2::ZERO,
+                size: physics.ball_size(),
+            },
+            player: PaddleState::new(Paddle::Player, &physics),
+            opponent: PaddleState::new(Paddle::Opponent, &physics),
+            scoreboard: Scoreboard::default(),
+            physics,
+            rules,
+            phase: Phase::Rally,
+            server: first_server,
+            first_server,
+            points_played: 0,
+            rng,
+            winner: None,
+            tick: 0,
+        };
+        sim.start_serve(first_server);
+        sim
+    }
+
+    /// A match picked up from `state`.
+    pub fn from_state(physics: PhysicsConfig, rules: MatchRules, state: SimState) -> PongSim {
+        PongSim {
+            ball: state.ball,
+            player: state.player,
+            opponent: state.opponent,
+            scoreboard: state.scoreboard,
+            physics,
+            rules,
+            phase: state.phase,
+            server: state.server,
+            first_server: state.first_server,
+            points_played: state.points_played,
+            rng: SimRng::new(state.rng),
+            winner: state.winner,
+            tick: state.tick,
+        }
+    }
+
+    pub fn state(&self) -> SimState {
+        SimState {
+            ball: self.ball,
+            player: self.player,
+            opponent: self.opponent,
+            scoreboard: self.scoreboard,
+            phase: self.phase,
+            server: self.server,
+            first_server: self.first_server,
+            points_played: self.points_played,
+            rng: self.rng.state(),
+            winner: self.winner,
+            tick: self.tick,
+        }
+    }
+
+    /// Put the paddles back where a new game starts them and serve again,
+    /// leaving the score alone.
+    pub fn reset_field(&mut self) {
+        self.player = PaddleState::new(Paddle::Player, &self.physics);
+        self.opponent = PaddleState::new(Paddle::Opponent, &self.physics);
+        self.start_serve(self.server);
+    }
+
+    /// Number of ticks simulated so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Match time in seconds.
+    pub fn elapsed(&self) -> f32 {
+        self.tick as f32 * self.physics.time_step()
+    }
+
+    pub fn winner(&self) -> Option<Paddle> {
+        self.winner
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.winner.is_some()
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// The side serving the current point.
+    pub fn server(&self) -> Paddle {
+        self.server
+    }
+
+    /// Whole seconds left on the serve countdown, if there is one running.
+    pub fn countdown(&self) -> Option<u32> {
+        match self.phase {
+            Phase::Serving { ticks_left } => {
+                // Dividing keeps whole seconds exact, so a 3 second countdown starts at 3
+                Some((ticks_left as f32 / self.physics.tick_rate).ceil() as u32)
+            }
+            Phase::Rally => None,
+        }
+    }
+
+    pub fn paddle(&self, paddle: Paddle) -> &PaddleState {
+        match paddle {
+            Paddle::Player => &self.player,
+            Paddle::Opponent => &self.opponent,
+        }
+    }
+
+    fn paddle_mut(&mut self, paddle: Paddle) -> &mut PaddleState {
+        match paddle {
+            Paddle::Player => &mut self.player,
+            Paddle::Opponent => &mut self.opponent,
+        }
+    }
+
+    /// Advance the game by one tick and report what happened.
+    /// Once the match has a winner this does nothing.
+    pub fn step(&mut self, inputs: SimInputs) -> Vec<SimEvent> {
+        let mut events = Vec::new();
+        if self.is_finished() {
+            return events;
+        }
+
+        self.move_paddles(inputs);
+        match self.phase {
+            Phase::Serving { ticks_left } => {
+                self.place_ball();
+                if ticks_left <= 1 || inputs.get(self.server).serve {
+                    self.launch(&mut events);
+                } else {
+                    self.phase = Phase::Serving { ticks_left: ticks_left - 1 };
+                }
+            }
+            Phase::Rally => {
+                self.separate_from_paddles(&mut events);
+                self.apply_velocity(&mut events);
+            }
+        }
+
+        self.tick += 1;
+        self.check_time_limit(&mut events);
+        events
+    }
+
+    fn start_serve(&mut self, server: Paddle) {
+        let ticks_left = (self.rules.serve.countdown * self.physics.tick_rate)
+            .round().max(1.0) as u32;
+        self.phase = Phase::Serving { ticks_left };
+        self.server = server;
+        self.ball.velocity = Vec2::ZERO;
+        self.place_ball();
+    }
+
+    fn place_ball(&mut self) {
+        let server = self.server;
+        self.ball.position = match self.rules.serve.position {
+            ServePosition::Centre => Vec2::ZERO,
+            ServePosition::Server => {
+                let paddle = self.paddle(server);
+                let gap = paddle.size.x / 2. + self.ball.size.x / 2. + 5.;
+                Vec2::new(paddle.position.x + server.facing() * gap, paddle.position.y)
+            }
+        };
+    }
+
+    fn launch(&mut self, events: &mut Vec<SimEvent>) {
+        let server = self.server;
+        let max_angle = self.rules.serve.max_angle.to_radians();
+        let angle = self.rng.range(-max_angle, max_angle);
+        self.ball.velocity = Vec2::new(server.facing() * angle.cos(), angle.sin())
+            * self.physics.ball_speed;
+        self.phase = Phase::Rally;
+        events.push(SimEvent::Served(server));
+    }
+
+    fn score_point(&mut self, paddle: Paddle, events: &mut Vec<SimEvent>) {
+        match paddle {
+            Paddle::Player => self.scoreboard.player_score += 1,
+            Paddle::Opponent => self.scoreboard.opponent_score += 1,
+        }
+        self.points_played += 1;
+        events.push(SimEvent::PointScored(paddle));
+
+        if let Some(game_winner) = self.rules.game_winner(&self.scoreboard) {
+            match game_winner {
+                Paddle::Player => self.scoreboard.player_games += 1,
+                Paddle::Opponent => self.scoreboard.opponent_games += 1,
+            }
+            self.scoreboard.player_score = 0;
+            self.scoreboard.opponent_score = 0;
+            events.push(SimEvent::GameWon(game_winner));
+        }
+
+        if let Some(match_winner) = self.rules.match_winner(&self.scoreboard) {
+            self.finish(match_winner, events);
+        } else {
+            // Past the time limit the first point that breaks a tie ends it
+            self.check_time_limit(events);
+        }
+
+        if !self.is_finished() {
+            let server = self.rules.serve.next_server(
+                self.first_server, self.points_played, Some(paddle));
+            self.start_serve(server);
+            events.push(SimEvent::ServeStarted(server));
+        }
+    }
+
+    fn check_time_limit(&mut self, events: &mut Vec<SimEvent>) {
+        if self.is_finished() || !self.rules.time_is_up(self.elapsed()) {
+            return;
+        }
+        if let Some(leader) = rules::leader(&self.scoreboard) {
+            self.finish(leader, events);
+        }
+    }
+
+    fn finish(&mut self, winner: Paddle, events: &mut Vec<SimEvent>) {
+        self.winner = Some(winner);
+        events.push(SimEvent::MatchWon(winner));
+    }
+
+    fn move_paddles(&mut self, inputs: SimInputs) {
+        let time_step = self.physics.time_step();
+        let movement = self.physics.paddle_movement;
+        let top = WallLocation::Top.inner(&self.physics.arena).y;
+        let bottom = WallLocation::Bottom.inner(&self.physics.arena).y;
+
+        for paddle in [Paddle::Player, Paddle::Opponent] {
+            let direction = inputs.get(paddle).direction.clamp(-1.0, 1.0);
+            let state = self.paddle_mut(paddle);
+            let target = direction * state.speed;
+
+            state.velocity = match movement {
+                PaddleMovement::Direct => target,
+                PaddleMovement::Accelerated { acceleration, friction } => {
+                    let rate = if direction == 0.0 { friction } else { acceleration };
+                    approach(state.velocity, target, rate * time_step)
+                }
+            };
+            state.position.y += state.velocity * time_step;
+
+            // Keep the paddle between the top and bottom walls
+            let half = state.size.y / 2.;
+            let clamped = state.position.y.clamp(bottom + half, top - half);
+            if clamped != state.position.y {
+                state.position.y = clamped;
+                state.velocity = 0.0;
+            }
+        }
+    }
+
+    /// Move the ball along its velocity for one tick. Whenever it would
+    /// run into something it stops there, bounces and carries on with the
+    /// rest of the tick; reaching a side wall scores the point instead.
+    fn apply_velocity(&mut self, events: &mut Vec<SimEvent>) {
+        let mut remaining = 1.0;
+
+        for _ in 0..MAX_BOUNCES_PER_TICK {
+            let motion = self.ball.velocity * self.physics.time_step() * remaining;
+            let ball = Aabb::new(self.ball.position, self.ball.size);
+
+            let mut first: Option<(Surface, Hit)> = None;
+            for (surface, target) in self.colliders() {
+                if let Some(hit) = collision::sweep(&ball, motion, &target) {
+                    if first.is_none_or(|(_, best)| hit.time < best.time) {
+                        first = Some((surface, hit));
+                    }
+                }
+            }
+
+            let (surface, hit) = match first {
+                Some(first) => first,
+                None => {
+                    self.ball.position += motion;
+                    return;
+                }
+            };
+
+            self.ball.position += motion * hit.time;
+            remaining *= 1.0 - hit.time;
+            events.push(SimEvent::Collision(CollisionEvent {
+                surface,
+                point: self.ball.position - hit.normal * ball.half_size,
+                normal: hit.normal,
+            }));
+
+            match surface {
+                Surface::Wall(WallLocation::Top) | Surface::Wall(WallLocation::Bottom) => {
+                    self.ball.velocity.y = -self.ball.velocity.y;
+                }
+                // The ball getting past a paddle is a point for the other side
+                Surface::Wall(WallLocation::Right) => {
+                    self.score_point(Paddle::Player, events);
+                    return;
+                }
+                Surface::Wall(WallLocation::Left) => {
+                    self.score_point(Paddle::Opponent, events);
+                    return;
+                }
+                Surface::Paddle(paddle) => {
+                    if hit.normal.x != 0.0 {
+                        self.deflect(paddle);
+                    } else {
+                        // Caught on the top or bottom end of the paddle
+                        self.ball.velocity.y = -self.ball.velocity.y;
+                    }
+                }
+            }
+        }
+    }
+
+    // Everything the ball can hit, in the order ties are settled
+    fn colliders(&self) -> [(Surface, Aabb); 6] {
+        let arena = self.physics.arena;
+        let wall = |location: WallLocation| {
+            (Surface::Wall(location), Aabb::new(location.position(&arena), location.size(&arena)))
+        };
+        let paddle = |paddle: Paddle| {
+            let state = self.paddle(paddle);
+            (Surface::Paddle(paddle), Aabb::new(state.position, state.size))
+        };
+
+        [
+            paddle(Paddle::Player),
+            paddle(Paddle::Opponent),
+            wall(WallLocation::Top),
+            wall(WallLocation::Bottom),
+            wall(WallLocation::Left),
+            wall(WallLocation::Right),
+        ]
+    }
+
+    // A paddle can move onto the ball. If the ball is still heading into it,
+    // put the ball back in front of the paddle's face and return it.
+    fn separate_from_paddles(&mut self, events: &mut Vec<SimEvent>) {
+        for paddle in [Paddle::Player, Paddle::Opponent] {
+            let state = *self.paddle(paddle);
+            let ball = Aabb::new(self.ball.position, self.ball.size);
+            let target = Aabb::new(state.position, state.size);
+
+            if ball.overlaps(&target) && self.ball.velocity.x * paddle.facing() < 0.0 {
+                let normal = Vec2::new(paddle.facing(), 0.0);
+                self.ball.position.x = state.position.x
+                    + paddle.facing() * (target.half_size.x + ball.half_size.x);
+                self.deflect(paddle);
+                events.push(SimEvent::Collision(CollisionEvent {
+                    surface: Surface::Paddle(paddle),
+                    point: self.ball.position - normal * ball.half_size,
+                    normal,
+                }));
+            }
+        }
+    }
+
+    // Send the ball back off a paddle. Where it hits decides the angle:
+    // the middle returns it flat, the ends at up to `max_bounce_angle`.
+    // A moving paddle drags the ball along with it a little.
+    fn deflect(&mut self, paddle: Paddle) {
+        let state = *self.paddle(paddle);
+        let max_angle = self.physics.max_bounce_angle.to_radians();
+        let reach = state.size.y / 2. + self.ball.size.y / 2.;
+        let offset = ((self.ball.position.y - state.position.y) / reach).clamp(-1.0, 1.0);
+        let speed = (self.ball.velocity.length() * self.physics.ball_speedup)
+            .min(self.physics.max_ball_speed);
+
+        let aimed = Vec2::new(paddle.facing() * (offset * max_angle).cos(),
+            (offset * max_angle).sin()) * speed;
+        let spun = aimed + Vec2::new(0.0, state.velocity * self.physics.paddle_spin);
+        let angle = spun.y.atan2(spun.x.abs()).clamp(-max_angle, max_angle);
+
+        self.ball.velocity = Vec2::new(paddle.facing() * angle.cos(), angle.sin()) * speed;
+    }
+}
+
+// Move `current` towards `target` by at most `step`
+fn approach(current: f32, target: f32, step: f32) -> f32 {
+    if current < target {
+        (current + step).min(target)
+    } else {
+        (current - step).max(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::ServeRotation;
+
+    fn rules(points_to_win: usize, win_by: usize, best_of: usize) -> MatchRules {
+        MatchRules { points_to_win, win_by, best_of, ..MatchRules::default() }
+    }
+
+    // A rally already under way, with the ball at `position` moving at `velocity`
+    fn rally(sim: &mut PongSim, position: Vec2, velocity: Vec2) {
+        sim.phase = Phase::Rally;
+        sim.ball.position = position;
+        sim.ball.velocity = velocity;
+    }
+
+    fn run(sim: &mut PongSim, ticks: usize) -> Vec<SimEvent> {
+        (0..ticks).flat_map(|_| sim.step(SimInputs::default())).collect()
+    }
+
+    fn points(events: &[SimEvent]) -> Vec<Paddle> {
+        events.iter()
+            .filter_map(|event| match event {
+                SimEvent::PointScored(paddle) => Some(*paddle),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn hit(events: &[SimEvent], surface: Surface) -> bool {
+        events.iter().any(|event| matches!(event, SimEvent::Collision(collision) if collision.surface == surface))
+    }
+
+    fn score(sim: &mut PongSim, points: &[Paddle]) -> Vec<SimEvent> {
+        let mut events = Vec::new();
+        for paddle in points {
+            sim.score_point(*paddle, &mut events);
+        }
+        events
+    }
+
+    fn hold(player: f32, opponent: f32) -> SimInputs {
+        SimInputs {
+            player: PaddleInput { direction: player, serve: false },
+            opponent: PaddleInput { direction: opponent, serve: false },
+        }
+    }
+
+    // Bounce a ball off the front of the player's paddle, `offset` above its middle
+    fn bounce(sim: &mut PongSim, offset: f32) -> Vec2 {
+        let paddle = sim.player;
+        let face = paddle.position.x + (paddle.size.x + sim.ball.size.x) / 2.;
+        sim.ball.position = Vec2::new(face, paddle.position.y + offset);
+        sim.ball.velocity = Vec2::new(-sim.physics.ball_speed, 0.0);
+        sim.deflect(Paddle::Player);
+        sim.ball.velocity
+    }
+
+    fn angle(velocity: Vec2) -> f32 {
+        velocity.y.atan2(velocity.x.abs()).to_degrees()
+    }
+
+    #[test]
+    fn ball_past_the_left_paddle_scores_for_the_opponent() {
+        let mut sim = PongSim::default();
+        rally(&mut sim, Vec2::new(0.0, -200.0), Vec2::new(-450.0, 0.0));
+        let events = run(&mut sim, 120);
+        assert_eq!(points(&events), vec![Paddle::Opponent]);
+        assert_eq!(sim.scoreboard.opponent_score, 1);
+        assert_eq!(sim.scoreboard.player_score, 0);
+    }
+
+    #[test]
+    fn ball_past_the_right_paddle_scores_for_the_player() {
+        let mut sim = PongSim::default();
+        rally(&mut sim, Vec2::new(0.0, -200.0), Vec2::new(450.0, 0.0));
+        let events = run(&mut sim, 120);
+        assert_eq!(points(&events), vec![Paddle::Player]);
+        assert_eq!(sim.scoreboard.player_score, 1);
+        assert_eq!(sim.scoreboard.opponent_score, 0);
+    }
+
+    #[test]
+    fn paddle_returns_the_ball() {
+        let mut sim = PongSim::default();
+        rally(&mut sim, Vec2::ZERO, Vec2::new(-450.0, 0.0));
+        let events = run(&mut sim, 60);
+        assert!(hit(&events, Surface::Paddle(Paddle::Player)));
+        assert!(points(&events).is_empty());
+        assert!(sim.ball.velocity.x > 0.0);
+    }
+
+    #[test]
+    fn fast_ball_does_not_tunnel_through_a_paddle() {
+        let mut sim = PongSim::default();
+        sim.physics.max_ball_speed = 20000.0;
+        // Far more than a paddle and a ball wide every tick
+        let speed = 6000.0;
+        assert!(speed * sim.physics.time_step() > sim.player.size.x + sim.ball.size.x);
+        rally(&mut sim, Vec2::ZERO, Vec2::new(-speed, 0.0));
+        let events = run(&mut sim, 5);
+        assert!(hit(&events, Surface::Paddle(Paddle::Player)));
+        assert!(points(&events).is_empty());
+        assert!(sim.ball.velocity.x > 0.0);
+    }
+
+    #[test]
+    fn fast_ball_does_not_tunnel_through_a_wall() {
+        let mut sim = PongSim::default();
+        sim.physics.max_ball_speed = 20000.0;
+        rally(&mut sim, Vec2::ZERO, Vec2::new(0.0, 9000.0));
+        let events = run(&mut sim, 10);
+        assert!(hit(&events, Surface::Wall(WallLocation::Top)));
+        let top = WallLocation::Top.inner(&sim.physics.arena).y;
+        let bottom = WallLocation::Bottom.inner(&sim.physics.arena).y;
+        assert!(sim.ball.position.y < top && sim.ball.position.y > bottom);
+    }
+
+    #[test]
+    fn a_paddle_is_hit_once_at_its_face() {
+        let mut sim = PongSim::default();
+        rally(&mut sim, Vec2::ZERO, Vec2::new(-450.0, 0.0));
+        let events = run(&mut sim, 60);
+        let hits: Vec<_> = events.iter()
+            .filter_map(|event| match event {
+                SimEvent::Collision(collision) if collision.surface == Surface::Paddle(Paddle::Player) => {
+                    Some(*collision)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(hits.len(), 1);
+        let face = sim.player.position.x + sim.player.size.x / 2.;
+        assert!((hits[0].point.x - face).abs() < 0.01);
+        assert_eq!(hits[0].normal, Vec2::X);
+    }
+
+    #[test]
+    fn where_the_ball_hits_the_paddle_sets_the_angle() {
+        let mut sim = PongSim::default();
+        let middle = bounce(&mut sim, 0.0);
+        assert!(middle.x > 0.0);
+        assert_eq!(middle.y, 0.0);
+
+        let near = angle(bounce(&mut sim, 20.0));
+        let far = angle(bounce(&mut sim, 40.0));
+        assert!(near > 0.0 && far > near);
+        assert!((angle(bounce(&mut sim, -40.0)) + far).abs() < 0.01);
+    }
+
+    #[test]
+    fn bounce_angle_stays_within_the_maximum() {
+        let mut sim = PongSim::default();
+        let max = sim.physics.max_bounce_angle;
+        let end = angle(bounce(&mut sim, 200.0));
+        assert!((end - max).abs() < 0.01);
+
+        // A paddle moving fast can't push it any steeper
+        sim.player.velocity = 10_000.0;
+        assert!(angle(bounce(&mut sim, 200.0)) <= max + 0.01);
+        sim.player.velocity = -10_000.0;
+        assert!(angle(bounce(&mut sim, -200.0)) >= -max - 0.01);
+    }
+
+    #[test]
+    fn each_hit_speeds_the_ball_up_to_the_cap() {
+        let mut sim = PongSim::default();
+        let physics = sim.physics;
+        sim.ball.velocity = Vec2::new(-physics.ball_speed, 0.0);
+        sim.deflect(Paddle::Player);
+        let expected = physics.ball_speed * physics.ball_speedup;
+        assert!((sim.ball.velocity.length() - expected).abs() < 0.01);
+
+        for _ in 0..50 {
+            sim.ball.velocity.x = -sim.ball.velocity.x;
+            sim.deflect(Paddle::Player);
+        }
+        assert!((sim.ball.velocity.length() - physics.max_ball_speed).abs() < 0.01);
+    }
+
+    #[test]
+    fn the_serve_resets_the_ball_speed() {
+        let mut sim = PongSim::default();
+        let fast = sim.physics.max_ball_speed;
+        rally(&mut sim, Vec2::new(0.0, -200.0), Vec2::new(fast, 0.0));
+        assert_eq!(points(&run(&mut sim, 60)), vec![Paddle::Player]);
+
+        let serve = PaddleInput { serve: true, ..Default::default() };
+        let events = sim.step(SimInputs { player: serve, opponent: serve });
+        assert!(events.iter().any(|event| matches!(event, SimEvent::Served(_))));
+        assert!((sim.ball.velocity.length() - sim.physics.ball_speed).abs() < 0.01);
+    }
+
+    #[test]
+    fn paddles_stop_at_the_walls() {
+        let mut sim = PongSim::default();
+        let top = WallLocation::Top.inner(&sim.physics.arena).y;
+        let bottom = WallLocation::Bottom.inner(&sim.physics.arena).y;
+        let half = sim.player.size.y / 2.;
+
+        for _ in 0..300 {
+            sim.step(hold(1.0, -1.0));
+        }
+        assert_eq!(sim.player.position.y, top - half);
+        assert_eq!(sim.opponent.position.y, bottom + half);
+        assert_eq!(sim.player.velocity, 0.0);
+
+        for _ in 0..300 {
+            sim.step(hold(-1.0, 1.0));
+        }
+        assert_eq!(sim.player.position.y, bottom + half);
+        assert_eq!(sim.opponent.position.y, top - half);
+    }
+
+    #[test]
+    fn accelerated_paddles_speed_up_to_their_top_speed_and_coast_to_a_stop() {
+        let physics = PhysicsConfig {
+            paddle_movement: PaddleMovement::Accelerated { acceleration: 600.0, friction: 300.0 },
+            ..PhysicsConfig::default()
+        };
+        let mut sim = PongSim::new(physics, MatchRules::default(), 1);
+        let time_step = physics.time_step();
+
+        sim.step(hold(1.0, -1.0));
+        assert!((sim.player.velocity - 600.0 * time_step).abs() < 0.01);
+        assert!((sim.opponent.velocity + 600.0 * time_step).abs() < 0.01);
+
+        for _ in 0..30 {
+            sim.step(hold(1.0, -1.0));
+            assert!(sim.player.velocity <= physics.player_speed);
+            assert!(sim.opponent.velocity >= -physics.opponent_speed);
+        }
+        assert_eq!(sim.player.velocity, physics.player_speed);
+        assert_eq!(sim.opponent.velocity, -physics.opponent_speed);
+
+        // Let go and friction slows it a little at a time
+        sim.step(hold(0.0, 0.0));
+        assert!((sim.player.velocity - (physics.player_speed - 300.0 * time_step)).abs() < 0.01);
+        for _ in 0..60 {
+            sim.step(hold(0.0, 0.0));
+        }
+        assert_eq!(sim.player.velocity, 0.0);
+        assert_eq!(sim.opponent.velocity, 0.0);
+    }
+
+    #[test]
+    fn a_moving_paddle_drags_the_ball_along() {
+        let mut sim = PongSim::default();
+        sim.player.velocity = sim.player.speed;
+        assert!(angle(bounce(&mut sim, 0.0)) > 0.0);
+        sim.player.velocity = -sim.player.speed;
+        assert!(angle(bounce(&mut sim, 0.0)) < 0.0);
+
+        // Without spin the paddle's movement makes no difference
+        sim.physics.paddle_spin = 0.0;
+        assert_eq!(bounce(&mut sim, 0.0).y, 0.0);
+    }
+
+    #[test]
+    fn counts_down_three_two_one_then_serves() {
+        let mut sim = PongSim::default();
+        let server = sim.server();
+        assert_eq!(sim.countdown(), Some(3));
+
+        let mut shown = Vec::new();
+        while let Phase::Serving { .. } = sim.phase() {
+            assert_eq!(sim.ball.position, Vec2::ZERO);
+            assert_eq!(sim.ball.velocity, Vec2::ZERO);
+            let events = sim.step(SimInputs::default());
+            if let Some(seconds) = sim.countdown() {
+                if shown.last() != Some(&seconds) {
+                    shown.push(seconds);
+                }
+            } else {
+                assert_eq!(events, vec![SimEvent::Served(server)]);
+            }
+        }
+        assert_eq!(shown, vec![3, 2, 1]);
+        assert_eq!(sim.tick(), 180);
+
+        // Launched towards the other side, within the serve angle
+        let velocity = sim.ball.velocity;
+        assert_eq!(velocity.x.signum(), server.facing());
+        assert!((velocity.length() - sim.physics.ball_speed).abs() < 0.01);
+        let angle = velocity.y.atan2(velocity.x.abs()).to_degrees();
+        assert!(angle.abs() <= sim.rules.serve.max_angle);
+    }
+
+    #[test]
+    fn only_the_server_can_serve_early() {
+        let mut sim = PongSim::default();
+        let server = sim.server();
+        let mut inputs = SimInputs::default();
+        match server.other() {
+            Paddle::Player => inputs.player.serve = true,
+            Paddle::Opponent => inputs.opponent.serve = true,
+        }
+        assert!(sim.step(inputs).is_empty());
+
+        let inputs = SimInputs {
+            player: PaddleInput { serve: true, ..Default::default() },
+            opponent: PaddleInput { serve: true, ..Default::default() },
+        };
+        assert_eq!(sim.step(inputs), vec![SimEvent::Served(server)]);
+        assert_eq!(sim.phase(), Phase::Rally);
+    }
+
+    #[test]
+    fn a_point_puts_the_ball_back_in_the_centre() {
+        let mut sim = PongSim::default();
+        rally(&mut sim, Vec2::new(0.0, -200.0), Vec2::new(450.0, 0.0));
+        let events = run(&mut sim, 120);
+        assert!(events.iter().any(|event| matches!(event, SimEvent::ServeStarted(_))));
+        assert!(matches!(sim.phase(), Phase::Serving { .. }));
+        assert_eq!(sim.ball.position, Vec2::ZERO);
+        assert_eq!(sim.ball.velocity, Vec2::ZERO);
+    }
+
+    #[test]
+    fn serve_alternates_every_two_points() {
+        let mut sim = PongSim::new(PhysicsConfig::default(), rules(11, 2, 1), 7);
+        let first = sim.server();
+        let mut servers = Vec::new();
+        for _ in 0..6 {
+            score(&mut sim, &[Paddle::Player]);
+            servers.push(sim.server());
+        }
+        let other = first.other();
+        assert_eq!(servers, vec![first, other, other, first, first, other]);
+        assert!(matches!(sim.phase(), Phase::Serving { .. }));
+    }
+
+    #[test]
+    fn winner_and_loser_serve() {
+        let mut winner_serves = rules(11, 2, 1);
+        winner_serves.serve.rotation = ServeRotation::WinnerServes;
+        let mut sim = PongSim::new(PhysicsConfig::default(), winner_serves, 3);
+        score(&mut sim, &[Paddle::Opponent]);
+        assert_eq!(sim.server(), Paddle::Opponent);
+        score(&mut sim, &[Paddle::Player]);
+        assert_eq!(sim.server(), Paddle::Player);
+
+        let mut loser_serves = rules(11, 2, 1);
+        loser_serves.serve.rotation = ServeRotation::LoserServes;
+        let mut sim = PongSim::new(PhysicsConfig::default(), loser_serves, 3);
+        score(&mut sim, &[Paddle::Opponent]);
+        assert_eq!(sim.server(), Paddle::Player);
+    }
+
+    #[test]
+    fn a_game_has_to_be_won_by_two() {
+        let mut sim = PongSim::new(PhysicsConfig::default(), rules(3, 2, 1), 1);
+        use Paddle::*;
+        let events = score(&mut sim, &[Player, Player, Opponent, Opponent, Player]);
+        // 3-2 isn't enough
+        assert!(!events.iter().any(|event| matches!(event, SimEvent::GameWon(_))));
+        assert_eq!(sim.winner(), None);
+
+        let events = score(&mut sim, &[Player]);
+        assert!(events.contains(&SimEvent::GameWon(Player)));
+        assert!(events.contains(&SimEvent::MatchWon(Player)));
+        assert_eq!(sim.winner(), Some(Player));
+        assert_eq!(sim.scoreboard.player_games, 1);
+    }
+
+    #[test]
+    fn best_of_three_goes_to_two_games() {
+        let mut sim = PongSim::new(PhysicsConfig::default(), rules(2, 1, 3), 1);
+        use Paddle::*;
+        let events = score(&mut sim, &[Player, Player, Opponent, Opponent]);
+        assert_eq!(events.iter().filter(|event| matches!(event, SimEvent::GameWon(_))).count(), 2);
+        assert_eq!((sim.scoreboard.player_games, sim.scoreboard.opponent_games), (1, 1));
+        assert_eq!((sim.scoreboard.player_score, sim.scoreboard.opponent_score), (0, 0));
+        assert_eq!(sim.winner(), None);
+
+        let events = score(&mut sim, &[Opponent, Opponent]);
+        assert!(events.contains(&SimEvent::MatchWon(Opponent)));
+        assert_eq!(sim.winner(), Some(Opponent));
+        // Nothing moves once it's over
+        assert!(run(&mut sim, 10).is_empty());
+    }
+
+    #[test]
+    fn the_leader_wins_when_time_runs_out() {
+        let mut timed = rules(11, 2, 1);
+        timed.time_limit = Some(1.0);
+        let mut sim = PongSim::new(PhysicsConfig::default(), timed, 1);
+        score(&mut sim, &[Paddle::Opponent]);
+        // The next serve is still counting down when time is up
+        let events = run(&mut sim, 70);
+        assert!(events.contains(&SimEvent::MatchWon(Paddle::Opponent)));
+        assert_eq!(sim.winner(), Some(Paddle::Opponent));
+    }
+
+    #[test]
+    fn a_tie_at_the_time_limit_goes_to_the_next_point() {
+        let mut timed = rules(11, 2, 1);
+        timed.time_limit = Some(1.0);
+        let mut sim = PongSim::new(PhysicsConfig::default(), timed, 1);
+        score(&mut sim, &[Paddle::Player, Paddle::Opponent]);
+        run(&mut sim, 70);
+        assert_eq!(sim.winner(), None);
+
+        let events = score(&mut sim, &[Paddle::Player]);
+        assert!(events.contains(&SimEvent::MatchWon(Paddle::Player)));
+    }
+
+    #[test]
+    fn same_seed_and_inputs_play_the_same() {
+        let inputs = SimInputs {
+            player: PaddleInput { direction: 0.5, serve: true },
+            opponent: PaddleInput { direction: -1.0, serve: false },
+        };
+        let mut first = PongSim::new(PhysicsConfig::default(), MatchRules::default(), 42);
+        let mut second = PongSim::new(PhysicsConfig::default(), MatchRules::default(), 42);
+        for _ in 0..2000 {
+            assert_eq!(first.step(inputs), second.step(inputs));
+        }
+        assert_eq!(first.state(), second.state());
+    }
+}
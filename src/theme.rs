@@ -0,0 +1,41 @@
+use bevy::prelude::*;
+
+use pong::settings::{Theme, WindowSettings};
+
+// Colours for everything drawn in the arena
+pub struct Palette {
+    pub background: Color,
+    pub wall: Color,
+    pub player: Color,
+    pub opponent: Color,
+    pub ball: Color,
+}
+
+pub fn palette(window: &WindowSettings) -> Palette {
+    match window.theme {
+        Theme::Classic => {
+            let [red, green, blue] = window.background;
+            Palette {
+                background: Color::rgb(red, green, blue),
+                wall: Color::rgb(0.30, 0.30, 0.15),
+                player: Color::BEIGE,
+                opponent: Color::BISQUE,
+                ball: Color::rgb(0.9, 0.5, 0.5),
+            }
+        }
+        Theme::Night => Palette {
+            background: Color::rgb(0.04, 0.05, 0.10),
+            wall: Color::rgb(0.15, 0.17, 0.30),
+            player: Color::rgb(0.55, 0.75, 1.0),
+            opponent: Color::rgb(1.0, 0.65, 0.85),
+            ball: Color::rgb(1.0, 0.95, 0.6),
+        },
+        Theme::HighContrast => Palette {
+            background: Color::BLACK,
+            wall: Color::GRAY,
+            player: Color::YELLOW,
+            opponent: Color::CYAN,
+            ball: Color::WHITE,
+        },
+    }
+}
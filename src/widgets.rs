@@ -0,0 +1,75 @@
+use bevy::{prelude::*, ecs::system::EntityCommands};
+
+use crate::main_menu::spawn_sized_button;
+
+const ROW_HEIGHT: f32 = 45.0;
+const LABEL_WIDTH: f32 = 380.0;
+const FONT_SIZE: f32 = 28.0;
+
+// Moves `target` by `delta` steps when its button is clicked
+#[derive(Component)]
+pub struct Stepper<T: Send + Sync + 'static> {
+    pub target: T,
+    pub delta: i32,
+}
+
+// Shows the value of `target` in its button's text
+#[derive(Component)]
+pub struct ValueLabel<T: Send + Sync + 'static>(pub T);
+
+// Row or column of widgets, sized to fit them
+pub fn spawn_group<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    direction: FlexDirection,
+) -> EntityCommands<'w, 's, 'a> {
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            flex_direction: direction,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        color: Color::NONE.into(),
+        ..default()
+    })
+}
+
+// A value with buttons either side to step it down and up
+pub fn spawn_slider<T: Copy + Send + Sync + 'static>(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    target: T,
+    text: &str,
+) -> Entity {
+    let step_size = Size::new(Val::Px(ROW_HEIGHT), Val::Px(ROW_HEIGHT));
+    let minus = spawn_sized_button(commands, asset_server, "<", step_size, FONT_SIZE)
+        .insert(Stepper { target, delta: -1 })
+        .id();
+    let label = spawn_sized_button(commands, asset_server, text,
+        Size::new(Val::Px(LABEL_WIDTH), Val::Px(ROW_HEIGHT)), FONT_SIZE)
+        .insert(ValueLabel(target))
+        .id();
+    let plus = spawn_sized_button(commands, asset_server, ">", step_size, FONT_SIZE)
+        .insert(Stepper { target, delta: 1 })
+        .id();
+    spawn_group(commands, FlexDirection::Row)
+        .push_children(&[minus, label, plus])
+        .id()
+}
+
+// A single button that steps its value on each click, for switches and
+// short lists. As wide as a slider so they line up.
+pub fn spawn_toggle<T: Copy + Send + Sync + 'static>(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    target: T,
+    text: &str,
+) -> Entity {
+    // A slider's label plus its step buttons and their margins
+    let width = LABEL_WIDTH + 2. * (ROW_HEIGHT + 20.);
+    spawn_sized_button(commands, asset_server, text,
+        Size::new(Val::Px(width), Val::Px(ROW_HEIGHT)), FONT_SIZE)
+        .insert(Stepper { target, delta: 1 })
+        .insert(ValueLabel(target))
+        .id()
+}
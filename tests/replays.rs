@@ -0,0 +1,30 @@
+//! Plays back every replay in `replays/` and checks each tick still comes
+//! out the way it was recorded. A failure here means a change to the
+//! simulation would break old replays.
+
+use std::fs;
+use std::path::Path;
+
+use pong::replay::Replay;
+
+#[test]
+fn checked_in_replays_play_back_exactly() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("replays");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .expect("replays directory")
+        .map(|entry| entry.expect("replays directory entry").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "pongreplay"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no replays in {}", dir.display());
+
+    for path in paths {
+        let replay = Replay::load(&path).unwrap_or_else(|error| panic!("{}", error));
+        // Without hashes there would be nothing to check
+        assert_eq!(replay.hashes.len(), replay.inputs.len(), "{} has no hashes", path.display());
+        if let Err(desync) = replay.verify() {
+            panic!("{} desyncs at tick {}:\n{}", path.display(), desync.tick, desync.diff().join("\n"));
+        }
+        assert!(replay.play().is_finished(), "{} doesn't play to the end of its match", path.display());
+    }
+}